@@ -1,4 +1,8 @@
+#include <setjmp.h>
 #include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <time.h>
 
 void print_int(int n) {
     printf("%d\n", n);
@@ -16,3 +20,143 @@ void print_point(struct Point *p) {
 void print_int_ptr(int *ptr) {
     printf("%p\n", ptr);
 }
+
+void print_str(char *str) {
+    printf("%s\n", str);
+}
+
+int read_int(void) {
+    int n;
+    if (scanf("%d", &n) != 1) {
+        return 0;
+    }
+    return n;
+}
+
+int read_line(char *buf, int len) {
+    if (fgets(buf, len, stdin) == NULL) {
+        return -1;
+    }
+    int n = strlen(buf);
+    if (n > 0 && buf[n - 1] == '\n') {
+        buf[--n] = '\0';
+    }
+    return n;
+}
+
+// The allocator the prelude declares. They're weak so a program can replace them all
+// by defining its own.
+__attribute__((weak)) void *alloc(size_t size) {
+    return malloc(size);
+}
+
+__attribute__((weak)) void *resize(void *ptr, size_t size) {
+    return realloc(ptr, size);
+}
+
+__attribute__((weak)) void dealloc(void *ptr) {
+    free(ptr);
+}
+
+// The entries the compiler puts in the `pl_funcs` section for each function. They're
+// weak so a program with no functions still links, which ld64 does anyway.
+struct pl_func {
+    void *start;
+    char *name;
+    long line;
+};
+#ifdef __APPLE__
+extern struct pl_func __start_pl_funcs[] __asm("section$start$__DATA$pl_funcs");
+extern struct pl_func __stop_pl_funcs[] __asm("section$end$__DATA$pl_funcs");
+#else
+extern struct pl_func __start_pl_funcs[] __attribute__((weak));
+extern struct pl_func __stop_pl_funcs[] __attribute__((weak));
+#endif
+
+static struct pl_func *pl_find_func(void *addr) {
+    struct pl_func *found = NULL;
+    for (struct pl_func *func = __start_pl_funcs; func < __stop_pl_funcs; func++) {
+        if (func->start <= addr && (found == NULL || func->start > found->start)) {
+            found = func;
+        }
+    }
+    return found;
+}
+
+// qbe always keeps a frame pointer, so the stack is walked through the saved frame
+// pointers until `main`. Anything past `main` isn't the program's code.
+static void pl_print_trace(void **frame) {
+    fprintf(stderr, "stack trace:\n");
+    while (frame != NULL) {
+        struct pl_func *func = pl_find_func(frame[1]);
+        if (func == NULL) {
+            break;
+        }
+        if (func->line > 0) {
+            fprintf(stderr, "  at %s (line %ld)\n", func->name, func->line);
+        } else {
+            fprintf(stderr, "  at %s\n", func->name);
+        }
+        if (strcmp(func->name, "main") == 0) {
+            break;
+        }
+        frame = frame[0];
+    }
+}
+
+// Set while a test runs under --panic=unwind-lite, so a panic returns to the harness
+// instead of exiting.
+static jmp_buf *pl_recover;
+
+// Called when an assert or a memory check fails.
+void pl_panic(char *message) {
+    // what the program printed comes before the panic
+    fflush(stdout);
+    fprintf(stderr, "%s\n", message);
+    pl_print_trace(__builtin_frame_address(0));
+    if (pl_recover != NULL) {
+        longjmp(*pl_recover, 1);
+    }
+    exit(1);
+}
+
+struct pl_test {
+    char *name;
+    void (*run)(void);
+};
+
+// Runs the tests named by the arguments in order, printing a line after each one's
+// output to say whether it passed. Returns 1 if any failed, or 2 for an unknown test.
+int pl_run_tests(struct pl_test *tests, int argc, char **argv) {
+    int failed = 0;
+    for (int i = 1; i < argc; i++) {
+        struct pl_test *test = tests;
+        while (test->name != NULL && strcmp(test->name, argv[i]) != 0) {
+            test++;
+        }
+        if (test->name == NULL) {
+            return 2;
+        }
+        jmp_buf recover;
+        pl_recover = &recover;
+        int panicked = setjmp(recover);
+        if (!panicked) {
+            test->run();
+        }
+        pl_recover = NULL;
+        printf("test %s ... %s\n", test->name, panicked ? "FAILED" : "ok");
+        fflush(stdout);
+        failed |= panicked;
+    }
+    return failed;
+}
+
+long pl_now(void) {
+    struct timespec time;
+    clock_gettime(CLOCK_MONOTONIC, &time);
+    return time.tv_sec * 1000000000L + time.tv_nsec;
+}
+
+void pl_print_time(long ns) {
+    printf("%ld\n", ns);
+}
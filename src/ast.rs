@@ -1,10 +1,17 @@
-use crate::symbols::Symbol;
+#![deny(clippy::unwrap_used, clippy::expect_used, clippy::panic)]
+
+use crate::{
+    lexer::Span,
+    symbols::{Symbol, Symbols},
+};
 
 #[derive(Debug, Clone)]
 pub struct If {
     pub cond: Box<Expr>,
+    pub cond_span: Span,
     pub if_block: Block,
     pub else_block: Else,
+    pub span: Span,
 }
 
 #[derive(Debug, Clone)]
@@ -16,18 +23,26 @@ pub enum Else {
 
 #[derive(Debug, Clone)]
 pub enum Expr {
-    Integer(i64),
+    Integer(i64, Span),
+    // a character literal, which is a `u8`
+    Char(u8),
     Bool(bool),
-    Ident(Symbol),
+    Ident {
+        name: Symbol,
+        span: Span,
+    },
     Field {
         expr: Box<Expr>,
         name: Symbol,
+        span: Span,
     },
     Infix {
         left: Box<Expr>,
         right: Box<Expr>,
         op: InfixOp,
+        span: Span,
     },
+    Null,
     Ref(Box<RefExpr>),
     Prefix {
         op: PrefixOp,
@@ -37,16 +52,23 @@ pub enum Expr {
     InitStruct {
         name: Symbol,
         values: Vec<StructValue>,
+        span: Span,
+    },
+    Cast {
+        expr: Box<Expr>,
+        ty: Ty,
+        span: Span,
     },
 }
 
 #[derive(Debug, Clone)]
 pub enum RefExpr {
-    Ident(Symbol),
+    Ident(Symbol, Span),
     Deref(Expr),
     Field {
         ref_expr: Box<RefExpr>,
         name: Symbol,
+        span: Span,
     },
 }
 
@@ -59,6 +81,7 @@ pub struct StructValue {
 #[derive(Debug, Clone, Copy)]
 pub enum PrefixOp {
     Deref,
+    BitNot,
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -77,28 +100,96 @@ pub enum Stmt {
         ident: Symbol,
         expr: Option<Expr>,
         ty: Option<Ty>,
+        span: Span,
     },
     Assign {
         ref_expr: RefExpr,
         expr: Expr,
+        span: Span,
+    },
+    Discard {
+        expr: Expr,
+        span: Span,
     },
     While {
         cond: Expr,
+        cond_span: Span,
         body: Block,
+        span: Span,
+    },
+    Return {
+        expr: Option<Expr>,
+        span: Span,
+    },
+    Unsafe {
+        body: Block,
+        span: Span,
     },
-    Return(Option<Expr>),
     If(If),
     FuncCall(FuncCall),
 }
 
+impl Stmt {
+    pub fn span(&self) -> Span {
+        match self {
+            Stmt::Let { span, .. }
+            | Stmt::Assign { span, .. }
+            | Stmt::Discard { span, .. }
+            | Stmt::While { span, .. }
+            | Stmt::Return { span, .. }
+            | Stmt::Unsafe { span, .. } => *span,
+            Stmt::If(if_stmt) => if_stmt.span,
+            Stmt::FuncCall(func_call) => func_call.span,
+        }
+    }
+}
+
+impl Expr {
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            Expr::Ident { span, .. }
+            | Expr::Field { span, .. }
+            | Expr::Infix { span, .. }
+            | Expr::InitStruct { span, .. }
+            | Expr::Cast { span, .. } => Some(*span),
+            Expr::Integer(_, span) => Some(*span),
+            Expr::FuncCall(func_call) => Some(func_call.span),
+            Expr::Char(_) | Expr::Bool(_) | Expr::Null | Expr::Ref(_) | Expr::Prefix { .. } => None,
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub enum Ty {
-    Struct(Symbol),
+    Struct(Symbol, Span),
     Ref(Box<Ty>),
+    // `?*T`, which can be `null`
+    NullableRef(Box<Ty>),
     Int(Int),
     Bool,
 }
 
+impl Ty {
+    pub fn name(&self, symbols: &Symbols) -> String {
+        match self {
+            Ty::Struct(name, _) => symbols.get_str(*name).to_string(),
+            Ty::Ref(ty) => format!("*{}", ty.name(symbols)),
+            Ty::NullableRef(ty) => format!("?*{}", ty.name(symbols)),
+            Ty::Int(int) => match int {
+                Int::I8 => "i8",
+                Int::I16 => "i16",
+                Int::I32 => "i32",
+                Int::U8 => "u8",
+                Int::U16 => "u16",
+                Int::U32 => "u32",
+                Int::Usize => "usize",
+            }
+            .to_string(),
+            Ty::Bool => "bool".to_string(),
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub enum Int {
     I8,
@@ -107,6 +198,8 @@ pub enum Int {
     U8,
     U16,
     U32,
+    // as wide as a reference
+    Usize,
 }
 
 #[derive(Debug, Clone)]
@@ -118,12 +211,16 @@ pub struct Block {
 pub struct Param {
     pub name: Symbol,
     pub ty: Ty,
+    pub span: Span,
 }
 
 #[derive(Debug, Clone)]
 pub struct FuncCall {
     pub name: Symbol,
     pub args: Vec<Expr>,
+    pub span: Span,
+    // everything between the brackets
+    pub args_span: Span,
 }
 
 #[derive(Debug, Clone)]
@@ -132,24 +229,89 @@ pub struct Func {
     pub params: Vec<Param>,
     pub returns: Option<Ty>,
     pub body: Option<Block>,
+    pub harness: Option<Harness>,
+    // `@unsafe` functions can only be called in an `unsafe` block
+    pub is_unsafe: bool,
+    // `@hidden` functions can be called from the other modules but aren't exported from
+    // a shared library
+    pub is_hidden: bool,
+    // the `///` comment above it, without the slashes
+    pub doc: Option<String>,
+    pub span: Span,
+}
+
+// Functions marked `@test` or `@bench` are run by a generated harness rather than
+// called.
+#[derive(Debug, Clone, Copy, PartialEq, Hash)]
+pub enum Harness {
+    Test,
+    Bench,
+}
+
+impl Harness {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Harness::Test => "test",
+            Harness::Bench => "bench",
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct Struct {
     pub name: Symbol,
     pub fields: Vec<StructField>,
+    pub span: Span,
+    pub repr_c: bool,
+    pub doc: Option<String>,
 }
 
 #[derive(Debug, Clone)]
 pub struct StructField {
     pub name: Symbol,
     pub ty: Ty,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone)]
+pub struct Const {
+    pub name: Symbol,
+    pub ty: Ty,
+    pub expr: Expr,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone)]
+pub struct StaticAssert {
+    pub cond: Expr,
+    pub message: String,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone)]
+pub struct Mod {
+    pub name: Symbol,
+    pub decls: Vec<Decl>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone)]
+pub struct Use {
+    pub path: Symbol,
+    pub alias: Symbol,
+    pub span: Span,
+    // the whole statement, so it can be removed
+    pub stmt_span: Span,
 }
 
 #[derive(Debug, Clone)]
 pub enum Decl {
     Struct(Struct),
     Func(Func),
+    Const(Const),
+    StaticAssert(StaticAssert),
+    Mod(Mod),
+    Use(Use),
 }
 
 #[derive(Debug, Clone)]
@@ -170,4 +332,16 @@ impl Program {
             _ => None,
         })
     }
+    pub fn const_iter(&self) -> impl Iterator<Item = &Const> {
+        self.decls.iter().filter_map(|decl| match decl {
+            Decl::Const(const_decl) => Some(const_decl),
+            _ => None,
+        })
+    }
+    pub fn static_assert_iter(&self) -> impl Iterator<Item = &StaticAssert> {
+        self.decls.iter().filter_map(|decl| match decl {
+            Decl::StaticAssert(static_assert) => Some(static_assert),
+            _ => None,
+        })
+    }
 }
@@ -0,0 +1,85 @@
+use std::{
+    collections::HashMap,
+    io::{self, Write},
+};
+
+use crate::{decls::Decls, ir, qbe::Qbe, session::Panic, symbols::Symbols, target::Target};
+
+pub struct Capabilities {
+    // the extension of the file the module is written to
+    pub extension: &'static str,
+    // whether the module has to be assembled before it can be linked
+    pub needs_assembling: bool,
+}
+
+// The constant data of a module, like panic messages. Identical payloads share one
+// label, and they're all emitted once after the functions.
+#[derive(Default)]
+pub struct Constants {
+    labels: HashMap<String, usize>,
+    pub payloads: Vec<String>,
+}
+
+impl Constants {
+    // Returns the index of the payload's label.
+    pub fn intern(&mut self, payload: String) -> usize {
+        if let Some(label) = self.labels.get(&payload) {
+            return *label;
+        }
+        let label = self.payloads.len();
+        self.labels.insert(payload.clone(), label);
+        self.payloads.push(payload);
+        label
+    }
+}
+
+pub trait Backend {
+    fn capabilities(&self) -> Capabilities;
+    fn emit_module(
+        &self,
+        output: &mut dyn Write,
+        decls: &Decls,
+        symbols: &Symbols,
+    ) -> io::Result<()>;
+    #[allow(clippy::too_many_arguments)]
+    fn emit_func<'a>(
+        &self,
+        output: &mut dyn Write,
+        func: &'a ir::Func,
+        decls: &'a Decls<'a>,
+        symbols: &'a Symbols<'a>,
+        source: &str,
+        // guard every load and store through a reference
+        memory_checks: bool,
+        constants: &mut Constants,
+    ) -> io::Result<()>;
+    fn emit_constants(&self, output: &mut dyn Write, constants: &Constants) -> io::Result<()>;
+    // Checks the emitted module, returning any errors with the line they are on.
+    fn validate(&self, _module: &str) -> Vec<(usize, String)> {
+        vec![]
+    }
+    // Emits a module with a `main` that runs the test named by its only argument,
+    // exiting with 2 if there is no such test. With `Panic::UnwindLite` it runs every
+    // test named by its arguments instead, printing whether each one passed.
+    fn emit_test_harness(
+        &self,
+        output: &mut dyn Write,
+        tests: &[String],
+        panic: Panic,
+    ) -> io::Result<()>;
+    // Emits a module with a `main` that runs the benchmark named by its first argument
+    // as many times as its second, printing how many nanoseconds that took.
+    fn emit_bench_harness(&self, output: &mut dyn Write, benches: &[String]) -> io::Result<()>;
+    // Interleaves an emitted module with the source lines each part of it was generated
+    // from, for `--emit=annotated`.
+    fn annotate(&self, module: &str, source: &str) -> String;
+    // Turns the emitted module into an assembly file. Returns whether it succeeded.
+    fn assemble(&self, module: &str, output: &str) -> bool;
+}
+
+pub fn select(name: &str, target: Option<Target>) -> Result<Box<dyn Backend>, String> {
+    match name {
+        "qbe" => Ok(Box::new(Qbe::new(target)?)),
+        _ => Err(format!("unknown backend '{}'", name)),
+    }
+}
@@ -0,0 +1,28 @@
+use std::fmt::Write;
+
+use crate::{ir, qbe::Label, symbols::Symbols};
+
+// Lists each block of a function under the label it's emitted with, along with
+// where it came from in the source and the blocks it branches to.
+pub fn dump(func: &ir::Func, symbols: &Symbols, source: &str) -> String {
+    let mut output = String::new();
+    writeln!(output, "${}", symbols.get_str(func.name)).unwrap();
+    for (id, block) in func.blocks.iter().enumerate() {
+        let targets = match &block.branch {
+            ir::Branch::Return(_) => "ret".to_string(),
+            ir::Branch::Static(target) => format!("{}", Label(target.0)),
+            ir::Branch::Condition {
+                if_true, if_false, ..
+            } => format!("{} {}", Label(if_true.0), Label(if_false.0)),
+        };
+        writeln!(
+            output,
+            "  {} {} -> {}",
+            Label(id as u32),
+            block.describe(source),
+            targets
+        )
+        .unwrap();
+    }
+    output
+}
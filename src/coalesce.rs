@@ -0,0 +1,60 @@
+use crate::{
+    liveness,
+    typed_ast::{Func, Stmt},
+};
+
+// Assigns each variable a stack slot, letting variables which are never live at
+// the same time share one. Returns the slot of each variable.
+pub fn coalesce_slots(func: &Func) -> Vec<u32> {
+    let var_count = var_count(func);
+    let liveness = liveness::analyse(func, var_count);
+    // a store clobbers the slot, so it conflicts with everything live after it
+    let mut conflicts = vec![vec![false; var_count]; var_count];
+    for block in &func.blocks {
+        let live = liveness.live_out(block);
+        liveness::walk_block(block, live, &mut |var, _, _, live| {
+            let var = var.0 as usize;
+            for (other, _) in live.iter().enumerate().filter(|(_, live)| **live) {
+                if other != var {
+                    conflicts[var][other] = true;
+                    conflicts[other][var] = true;
+                }
+            }
+        });
+    }
+    let mut slots: Vec<Vec<usize>> = vec![];
+    let mut var_slots = vec![];
+    for (var, conflicts) in conflicts.iter().enumerate() {
+        let slot = if is_fixed(var, func, &liveness) {
+            None
+        } else {
+            slots.iter().position(|slot| {
+                slot.iter()
+                    .all(|other| !conflicts[*other] && !is_fixed(*other, func, &liveness))
+            })
+        };
+        let slot = match slot {
+            Some(slot) => slot,
+            None => {
+                slots.push(vec![]);
+                slots.len() - 1
+            }
+        };
+        slots[slot].push(var);
+        var_slots.push(slot as u32);
+    }
+    var_slots
+}
+
+pub fn var_count(func: &Func) -> usize {
+    let allocs = func.blocks.iter().flat_map(|block| &block.stmts);
+    func.params.len()
+        + allocs
+            .filter(|stmt| matches!(stmt, Stmt::Alloc(..)))
+            .count()
+}
+
+// parameters are stored on entry and escaped variables may be accessed at any time
+fn is_fixed(var: usize, func: &Func, liveness: &liveness::Liveness) -> bool {
+    var < func.params.len() || liveness.escaped[var]
+}
@@ -0,0 +1,493 @@
+#![deny(clippy::unwrap_used, clippy::expect_used, clippy::panic)]
+
+use strum::{EnumIter, IntoEnumIterator};
+
+use crate::diagnostic::Severity;
+
+// Every diagnostic has a code so it can be looked up with `--explain`. Codes are
+// never reused once assigned.
+#[derive(Debug, Clone, Copy, PartialEq, EnumIter)]
+pub enum Code {
+    UnexpectedCharacter,
+    UnterminatedString,
+    SyntaxError,
+    Duplicate,
+    InvalidMain,
+    ReprC,
+    RecursiveStruct,
+    UnknownField,
+    ConstCycle,
+    ConstType,
+    ConstEval,
+    StaticAssertFailed,
+    Uninitialized,
+    ArgumentCount,
+    IntegerTooLarge,
+    TypeMismatch,
+    UnknownType,
+    ReturnValue,
+    UnknownName,
+    UnknownFunction,
+    NoValue,
+    MissingField,
+    UnresolvedImport,
+    ImportConflict,
+    PreludeBody,
+    ReservedKeyword,
+    InvalidHarness,
+    NullableDeref,
+    InvalidCast,
+    Unsafe,
+    UnterminatedComment,
+    InvalidChar,
+    CannotInfer,
+
+    UnusedResult,
+    Unreachable,
+    ConstantCondition,
+    DeadStore,
+    Overflow,
+    DivisionByZero,
+    ConstantComparison,
+    UnusedImport,
+    LargeFrame,
+}
+
+impl Code {
+    pub fn str(&self) -> &'static str {
+        match self {
+            Code::UnexpectedCharacter => "E0001",
+            Code::UnterminatedString => "E0002",
+            Code::SyntaxError => "E0003",
+            Code::Duplicate => "E0004",
+            Code::InvalidMain => "E0005",
+            Code::ReprC => "E0006",
+            Code::RecursiveStruct => "E0007",
+            Code::UnknownField => "E0008",
+            Code::ConstCycle => "E0009",
+            Code::ConstType => "E0010",
+            Code::ConstEval => "E0011",
+            Code::StaticAssertFailed => "E0012",
+            Code::Uninitialized => "E0013",
+            Code::ArgumentCount => "E0014",
+            Code::IntegerTooLarge => "E0015",
+            Code::TypeMismatch => "E0016",
+            Code::UnknownType => "E0017",
+            Code::ReturnValue => "E0018",
+            Code::UnknownName => "E0019",
+            Code::UnknownFunction => "E0020",
+            Code::NoValue => "E0021",
+            Code::MissingField => "E0022",
+            Code::UnresolvedImport => "E0023",
+            Code::ImportConflict => "E0024",
+            Code::PreludeBody => "E0025",
+            Code::ReservedKeyword => "E0026",
+            Code::InvalidHarness => "E0027",
+            Code::NullableDeref => "E0028",
+            Code::InvalidCast => "E0029",
+            Code::Unsafe => "E0030",
+            Code::UnterminatedComment => "E0031",
+            Code::InvalidChar => "E0032",
+            Code::CannotInfer => "E0033",
+
+            Code::UnusedResult => "W0001",
+            Code::Unreachable => "W0002",
+            Code::ConstantCondition => "W0003",
+            Code::DeadStore => "W0004",
+            Code::Overflow => "W0005",
+            Code::DivisionByZero => "W0006",
+            Code::ConstantComparison => "W0007",
+            Code::UnusedImport => "W0008",
+            Code::LargeFrame => "W0009",
+        }
+    }
+    pub fn severity(&self) -> Severity {
+        match self.str().as_bytes()[0] {
+            b'W' => Severity::Warning,
+            _ => Severity::Error,
+        }
+    }
+    pub fn explain(&self) -> &'static str {
+        match self {
+            Code::UnexpectedCharacter => {
+                "A character was found that can't start any token.
+
+    func main() {
+        var x = 1 $ 2;
+    }
+
+Outside of string literals, only identifiers, numbers, whitespace and the
+language's symbols may appear. Identifiers can only contain ASCII letters, digits
+and `_`."
+            }
+            Code::UnterminatedString => {
+                "A string literal is missing its closing quote.
+
+    static_assert(true, \"missing the end);
+
+String literals can't span multiple lines, so the closing `\"` must be on the same
+line as the opening one."
+            }
+            Code::SyntaxError => {
+                "The parser found a token it didn't expect.
+
+    func main() {
+        var x = 1
+        x = 2;
+    }
+
+Here the `var` statement is missing its `;`. The error points at the first token
+that couldn't be parsed, which is often just after the real mistake. Slips that
+are common when coming from other languages, like `let` for `var` or `fn` for
+`func`, get a message of their own and the parser carries on past them."
+            }
+            Code::Duplicate => {
+                "The same name is defined more than once.
+
+    func area() i32 { return 1; }
+    func area() i32 { return 2; }
+
+Functions, structs and constants share one namespace per module, and functions
+must also be unique across every module linked together. Parameters and struct
+fields must be unique within their function or struct. Rename or remove one of
+the definitions."
+            }
+            Code::InvalidMain => {
+                "`main` has a signature the runtime can't call.
+
+    func main(n: i32) bool { return true; }
+
+`main` takes either no parameters or `argc: i32, argv: **u8`, and returns
+either nothing or an `i32`, which is used as the exit status."
+            }
+            Code::ReprC => {
+                "A `@repr(c)` struct contains a struct without a C layout.
+
+    struct Inner { x: i32 }
+    @repr(c)
+    struct Outer { inner: Inner }
+
+The layout of `Outer` can only be guaranteed if every struct inside it also has
+a guaranteed layout. Add `@repr(c)` to `Inner`."
+            }
+            Code::RecursiveStruct => {
+                "A struct contains itself, so it would need infinite space.
+
+    struct Node { value: i32, next: Node }
+
+Store the recursive field behind a pointer instead, e.g. `next: *Node`."
+            }
+            Code::UnknownField => {
+                "A field was accessed that the struct doesn't have.
+
+    struct Point { x: i32, y: i32 }
+    func f(p: Point) i32 { return p.z; }
+
+Check the spelling against the fields listed in the note."
+            }
+            Code::ConstCycle => {
+                "A constant's value depends on itself.
+
+    const A: i32 = B + 1;
+    const B: i32 = A;
+
+Constants are evaluated at compile time, so their definitions must not form a
+cycle."
+            }
+            Code::ConstType => {
+                "A constant's value doesn't fit its declared type.
+
+    const SMALL: u8 = 300;
+    const FLAG: bool = 1;
+
+The value must be in range for an integer type, and be a `bool` for `bool`.
+The same check applies to the condition of a `static_assert`."
+            }
+            Code::ConstEval => {
+                "An expression couldn't be evaluated at compile time.
+
+    const A: i32 = 1 / 0;
+    const B: i32 = read_int();
+
+Constants and `static_assert` conditions may only use literals, other
+constants and arithmetic that doesn't overflow or divide by zero."
+            }
+            Code::StaticAssertFailed => {
+                "A `static_assert` condition evaluated to `false`.
+
+    const SIZE: i32 = 8;
+    static_assert(SIZE < 4, \"SIZE must be small\");
+
+The message given to the assertion is included in the error."
+            }
+            Code::Uninitialized => {
+                "A variable is read before it has been assigned on every path.
+
+    func f(flag: bool) i32 {
+        var x: i32;
+        if flag { x = 1; }
+        return x;
+    }
+
+Assign the variable before the branch, or on every branch."
+            }
+            Code::ArgumentCount => {
+                "A function was called with the wrong number of arguments.
+
+    func add(a: i32, b: i32) i32 { return a + b; }
+    var n = add(1);
+    var m = popcount(1, 2);
+
+Pass one argument for each parameter. The builtins `ctz`, `clz`, `popcount` and
+`bswap` each take a single integer."
+            }
+            Code::IntegerTooLarge => {
+                "An integer literal is too large to represent.
+
+    var n = 99999999999999999999;
+    var b: u8 = 256;
+
+Literals must fit in a signed 64 bit integer, and then in their type. A literal
+with no other type is an `i32`."
+            }
+            Code::TypeMismatch => {
+                "Two types that must be the same are different.
+
+    var flag: bool = 1;
+    var n: i32 = 1;
+    var m: u8 = n;
+
+There are no implicit conversions, so integers of different sizes or
+signedness don't mix, and integers and `bool`s are distinct."
+            }
+            Code::UnknownType => {
+                "A type was named that doesn't exist.
+
+    func area(s: Square) i32 { return 0; }
+
+Types are either builtin, like `i32` and `bool`, or a struct declared in the
+same file. Structs inside a `mod` block are named by their path when used outside
+it, e.g. `shapes::Square`."
+            }
+            Code::ReturnValue => {
+                "A `return` doesn't match the function's return type.
+
+    func f() { return 1; }
+    func g() i32 { return; }
+
+A function without a return type can't return a value, and one with a return
+type must return a value."
+            }
+            Code::UnknownName => {
+                "A name was used that isn't a variable or constant in scope.
+
+    func main() { print_int(count); }
+
+Check the spelling, and that the variable is declared before it's used."
+            }
+            Code::UnknownFunction => {
+                "A function was called that isn't declared.
+
+    func main() { prnt_int(1); }
+
+External functions must be declared without a body, e.g. `func print_int(n: i32);`."
+            }
+            Code::NoValue => {
+                "A function without a return type was used as a value.
+
+    func log(n: i32);
+    var x = log(1);"
+            }
+            Code::MissingField => {
+                "A struct literal doesn't give a value for every field.
+
+    struct Point { x: i32, y: i32 }
+    var p = Point { x: 1 };
+
+Every field must be initialized."
+            }
+            Code::UnresolvedImport => {
+                "A `use` names something that doesn't exist.
+
+    mod shapes { func area() i32 { return 1; } }
+    use shapes::volume;
+
+The path is looked up from the module the `use` is in, and then from each module
+around it. It must name a function, struct or constant, not a module."
+            }
+            Code::ImportConflict => {
+                "Two imports in the same module bring in the same name.
+
+    use shapes::area;
+    use rooms::area;
+
+Rename one of them with `as`, e.g. `use rooms::area as room_area;`."
+            }
+            Code::PreludeBody => {
+                "A function in a prelude given with `--prelude` has a body.
+
+    func print_int(n: i32) { putchar(48 + n); }
+
+The prelude is included in every module, so the function would be defined more
+than once. Declare it in the prelude, `func print_int(n: i32);`, and define it in
+one of the source files."
+            }
+            Code::ReservedKeyword => {
+                "A keyword was used as a name.
+
+    var for = 1;
+    var use = 2;
+
+`match`, `enum`, `for` and `pub` aren't used by the language yet, but are
+reserved so that adding them later won't break existing code. `const`,
+`static_assert`, `mod`, `use` and `unsafe` were names before they became
+keywords, so older code may need them renamed. Pick another name."
+            }
+            Code::InvalidHarness => {
+                "A `@test` or `@bench` function has a signature its harness can't call.
+
+    @test
+    func adds(a: i32) bool { return a + 1 > a; }
+
+Tests and benchmarks take no parameters, return nothing and must have a body.
+Check results with `assert(cond)` instead, which fails the test if `cond` is
+false."
+            }
+            Code::NullableDeref => {
+                "A nullable reference is dereferenced without checking it isn't `null`.
+
+    func get(p: ?*i32) i32 { return *p; }
+
+Check it with `is_null(p)` and then use `unwrap(p)`, which gives a `*i32`. If it
+is `null` after all, `unwrap` stops the program with an error.
+
+    func get(p: ?*i32) i32 {
+        if is_null(p) { return 0; }
+        return *unwrap(p);
+    }"
+            }
+            Code::InvalidCast => {
+                "An `as` cast isn't between a reference and a `usize`.
+
+    func f(x: i32) u16 { return x as u16; }
+
+Only references can be cast to `usize`, and only a `usize` can be cast to a
+reference. Casts between integer types aren't supported, apart from casts between
+`char` and `u8`, which are the same type."
+            }
+            Code::Unsafe => {
+                "An operation the compiler can't check is used outside an `unsafe` block.
+
+    func at(addr: usize) *i32 { return addr as *i32; }
+
+Casts between references and `usize`, and calls to functions marked `@unsafe`,
+have to be in an `unsafe` block, which marks the code to review by hand:
+
+    func at(addr: usize) *i32 {
+        unsafe { return addr as *i32; }
+    }
+
+`--emit=unsafe-report` lists every operation in an `unsafe` block."
+            }
+            Code::UnterminatedComment => {
+                "A block comment is missing its closing `*/`.
+
+    /* the rest of the file is a comment
+    func main() {}
+
+Block comments nest, so every `/*` inside one needs its own `*/` as well."
+            }
+            Code::InvalidChar => {
+                "A character literal isn't a single ASCII character.
+
+    func main() { var c = 'ab'; }
+
+A character is a `u8`, so it can only hold an ASCII character or one of the
+escapes `\\n`, `\\t`, `\\r`, `\\0`, `\\\\`, `\\'` and `\\\"`."
+            }
+            Code::CannotInfer => {
+                "A variable's type can't be worked out.
+
+    func main() { var x; }
+
+A variable declared without a type or a value takes its type from how it's used
+later, so `x` needs a type such as `var x: i32;` when nothing assigns to it."
+            }
+            Code::UnusedResult => {
+                "A function's result is ignored.
+
+    func f() i32 { return 1; }
+    func main() { f(); }
+
+Assign it to `_` to show it's discarded on purpose: `_ = f();`."
+            }
+            Code::Unreachable => {
+                "A statement comes after a `return` on every path, so it never runs.
+
+    func f() i32 {
+        return 1;
+        print_int(2);
+    }"
+            }
+            Code::ConstantCondition => {
+                "An `if` or `while` condition always has the same value.
+
+    while false { print_int(1); }
+
+The code it guards either always or never runs, which is usually a mistake."
+            }
+            Code::DeadStore => {
+                "A value is assigned to a variable but never read.
+
+    var x = 1;
+    x = 2;
+    print_int(x);
+
+The first assignment is overwritten before it's used, so it can be removed."
+            }
+            Code::Overflow => {
+                "An arithmetic operation overflows for every possible input.
+
+    var x: u8 = 255 + 1;
+
+The result wraps around, which is rarely intended. Use a wider type."
+            }
+            Code::DivisionByZero => {
+                "A division always divides by zero.
+
+    var x = n / 0;"
+            }
+            Code::ConstantComparison => {
+                "A comparison always has the same result because of the operands' ranges.
+
+    func f(x: u8) bool { return x > 255; }
+
+No `u8` is greater than 255, so the comparison is always `false`."
+            }
+            Code::UnusedImport => {
+                "A `use` brings in a name that's never used.
+
+    use shapes::area;
+    func main() {}
+
+It can be removed. An import is also unused if a declaration in the same module
+has the same name, as the declaration is found first."
+            }
+            Code::LargeFrame => {
+                "A function's stack frame is larger than the limit given with `--frame-limit`.
+
+    struct Buffer { a: i32, b: i32, c: i32, d: i32 }
+    func f() { var x = Buffer { a: 0, b: 0, c: 0, d: 0 }; }
+
+With `--frame-limit=8`, `f` is reported as it needs 16 bytes for `x`. Variables
+that are never live at the same time share a slot, so the frame is often smaller
+than the sum of its variables. Use `--emit=frame-report` to list every function's
+frame size."
+            }
+        }
+    }
+}
+
+pub fn lookup(code: &str) -> Option<Code> {
+    Code::iter().find(|known| known.str().eq_ignore_ascii_case(code))
+}
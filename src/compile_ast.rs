@@ -1,18 +1,44 @@
-use std::collections::{HashMap, HashSet};
+#![deny(clippy::unwrap_used, clippy::expect_used, clippy::panic)]
+
+use std::{
+    collections::{BTreeMap, HashSet},
+    mem,
+};
 
 use crate::{
     ast::{self, Struct},
-    infer::unify,
-    symbols::Symbol,
-    ty::{Field, Int, IntTy, IntTyRef, Signedness, Size, StructTy, StructTyRef, Ty, TyRef},
-    typed_ast,
+    codes::Code,
+    compile_typed_ast::concrete_int,
+    consteval::{self, Consts, Value},
+    dead_store,
+    decls::Decls,
+    diagnostic::Diagnostic,
+    infer::{unify, InferTyRef, Unify},
+    lexer::Span,
+    range,
+    symbols::{Symbol, Symbols},
+    ty::{
+        Field, Int, IntTy, IntTyRef, Signedness, Size, StructTy, StructTyRef, Ty, TyError, TyRef,
+    },
+    typed_ast, uninit,
 };
 
 struct Compiler<'a> {
     scope: Vec<Variable>,
-    program: &'a ast::Program,
+    decls: &'a Decls<'a>,
+    consts: &'a Consts,
+    symbols: &'a Symbols<'a>,
     returns: Option<TyRef>,
     blocks: Vec<typed_ast::Block>,
+    // the statement being compiled, where type errors are reported
+    span: Span,
+    types: Vec<(Span, TyRef)>,
+    // integer literals, which are checked against their type once it's inferred
+    literals: Vec<(i64, Span, IntTyRef)>,
+    in_unsafe: bool,
+    unsafe_ops: Vec<(Span, String)>,
+    errors: Vec<Diagnostic>,
+    warnings: Vec<Diagnostic>,
 }
 
 #[derive(Debug, Clone)]
@@ -20,59 +46,234 @@ struct Variable {
     name: Symbol,
     var: typed_ast::Variable,
     ty: TyRef,
+    span: Span,
+    // set when the type of the variable couldn't be inferred because of an error
+    poisoned: bool,
+}
+
+impl Variable {
+    // A poisoned variable has the error type, so the error that poisoned it isn't
+    // reported again at every use.
+    fn ty(&self) -> TyRef {
+        if self.poisoned {
+            TyRef::new(Ty::Error)
+        } else {
+            self.ty.clone()
+        }
+    }
 }
 
-pub fn compile_func(func: &ast::Func, program: &ast::Program) -> Option<typed_ast::Func> {
+pub fn compile_func<'a>(
+    func: &ast::Func,
+    decls: &'a Decls<'a>,
+    consts: &'a Consts,
+    symbols: &'a Symbols<'a>,
+    warnings: &mut Vec<Diagnostic>,
+) -> Result<Option<typed_ast::Func>, Vec<Diagnostic>> {
     let body = match &func.body {
         Some(body) => body,
-        None => return None,
+        None => return Ok(None),
     };
-    let returns = func.returns.as_ref().map(|ty| compile_ty(&ty, program));
+    let returns = func.returns.as_ref().map(|ty| compile_ty(ty, decls));
     let mut scope = vec![];
     let mut params = vec![];
     for param in &func.params {
-        let ty = compile_ty(&param.ty, program);
+        let ty = compile_ty(&param.ty, decls);
         let var = typed_ast::Variable(scope.len() as u32);
         params.push(ty.clone());
         scope.push(Variable {
             name: param.name,
             ty,
             var,
+            span: param.span,
+            poisoned: false,
         });
     }
     let mut compiler = Compiler {
         scope,
-        program,
+        decls,
+        consts,
+        symbols,
         blocks: vec![],
         returns,
+        span: func.span,
+        types: vec![],
+        literals: vec![],
+        in_unsafe: false,
+        unsafe_ops: vec![],
+        errors: vec![],
+        warnings: vec![],
     };
-    let mut block_id = compiler.new_block();
-    compiler.compile_block(&body, &mut block_id);
-    Some(typed_ast::Func {
+    let mut block_id = compiler.new_block("entry", func.span);
+    compiler.compile_block(body, &mut block_id);
+    for (value, span, int_ty) in &compiler.literals {
+        let int = concrete_int(int_ty);
+        if !range::fits(int, *value) {
+            compiler.errors.push(Diagnostic::new(
+                Code::IntegerTooLarge,
+                *span,
+                format!("`{}` doesn't fit in `{}`", value, IntTy::Int(int)),
+            ));
+        }
+    }
+    warnings.append(&mut compiler.warnings);
+    if !compiler.errors.is_empty() {
+        return Err(compiler.errors);
+    }
+    // a variable with neither a type nor a value, that's never used in a way that
+    // gives it one
+    let unknown: Vec<_> = compiler
+        .scope
+        .iter()
+        .filter(|var| var.ty.map(|ty| matches!(ty, Ty::Any)))
+        .map(|var| {
+            let name = symbols.get_str(var.name);
+            Diagnostic::new(
+                Code::CannotInfer,
+                var.span,
+                format!("cannot infer type of `{}`", name),
+            )
+            .with_note(format!("give it a type, e.g. `var {}: i32;`", name))
+        })
+        .collect();
+    if !unknown.is_empty() {
+        return Err(unknown);
+    }
+    let func = typed_ast::Func {
         blocks: compiler.blocks,
         name: func.name,
         params,
-    })
+        types: compiler.types,
+        unsafe_ops: compiler.unsafe_ops,
+    };
+    let vars: Vec<_> = compiler
+        .scope
+        .iter()
+        .map(|var| (var.name, var.span))
+        .collect();
+    let errors = uninit::check_func(&func, &vars, symbols);
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+    warnings.extend(dead_store::check_func(&func, &vars, symbols));
+    warnings.extend(range::check_func(&func));
+    Ok(Some(func))
+}
+
+pub fn check_struct_cycles(decls: &Decls, symbols: &Symbols) -> Vec<Diagnostic> {
+    let mut checker = CycleChecker {
+        decls,
+        symbols,
+        path: vec![],
+        done: HashSet::new(),
+        errors: vec![],
+    };
+    for struct_decl in decls.program.struct_iter() {
+        checker.visit(struct_decl);
+    }
+    checker.errors
+}
+
+struct CycleChecker<'a> {
+    decls: &'a Decls<'a>,
+    symbols: &'a Symbols<'a>,
+    path: Vec<(Symbol, &'a ast::StructField)>,
+    done: HashSet<Symbol>,
+    errors: Vec<Diagnostic>,
+}
+
+impl<'a> CycleChecker<'a> {
+    fn visit(&mut self, struct_decl: &'a Struct) {
+        if self.done.contains(&struct_decl.name) {
+            return;
+        }
+        if let Some(start) = self
+            .path
+            .iter()
+            .position(|(name, _)| *name == struct_decl.name)
+        {
+            self.report_cycle(start);
+            return;
+        }
+        for field in &struct_decl.fields {
+            if let ast::Ty::Struct(name, _) = &field.ty {
+                if let Some(field_struct) = self.decls.struct_decl(*name) {
+                    self.path.push((struct_decl.name, field));
+                    self.visit(field_struct);
+                    self.path.pop();
+                }
+            }
+        }
+        self.done.insert(struct_decl.name);
+    }
+    fn report_cycle(&mut self, start: usize) {
+        let cycle = &self.path[start..];
+        let (name, field) = cycle[0];
+        let name = self.symbols.get_str(name);
+        let path: Vec<_> = cycle
+            .iter()
+            .map(|(name, field)| {
+                format!(
+                    "`{}.{}`",
+                    self.symbols.get_str(*name),
+                    self.symbols.get_str(field.name)
+                )
+            })
+            .collect();
+        // only fields of a struct type are pushed on the path
+        let ast::Ty::Struct(field_ty, _) = &field.ty else {
+            return;
+        };
+        let diagnostic = Diagnostic::new(
+            Code::RecursiveStruct,
+            field.span,
+            format!("recursive struct `{}` has infinite size", name),
+        )
+        .with_note(format!(
+            "`{}` contains itself through {}",
+            name,
+            path.join(" -> ")
+        ))
+        .with_note(format!(
+            "store one of these fields behind a pointer, e.g. `{}: *{}`",
+            self.symbols.get_str(field.name),
+            self.symbols.get_str(*field_ty)
+        ));
+        self.errors.push(diagnostic);
+    }
 }
 
-fn compile_struct(struct_decl: &Struct, program: &ast::Program) -> Ty {
+fn compile_struct(struct_decl: &Struct, decls: &Decls, outer: &mut Vec<(Symbol, TyRef)>) -> TyRef {
+    if let Some((_, ty)) = outer.iter().find(|(name, _)| *name == struct_decl.name) {
+        return ty.clone();
+    }
+    let ty = TyRef::new(Ty::Any);
+    outer.push((struct_decl.name, ty.clone()));
     let mut fields = vec![];
     for field in &struct_decl.fields {
-        let ty = compile_ty(&field.ty, program);
+        let ty = compile_nested_ty(&field.ty, decls, outer);
         fields.push(Field {
             name: field.name,
             ty,
         });
     }
-    Ty::Struct(StructTyRef::new(StructTy::Known {
+    outer.pop();
+    let struct_ty = TyRef::new(Ty::Struct(StructTyRef::new(StructTy::Known {
         name: struct_decl.name,
         fields,
-    }))
+    })));
+    // `ty` is still unknown, so this can't fail
+    _ = unify(&ty, &struct_ty);
+    ty
 }
 
-pub fn compile_ty(ty: &ast::Ty, program: &ast::Program) -> TyRef {
-    TyRef::new(match ty {
-        ast::Ty::Int(int) => Ty::Int(IntTyRef::new(IntTy::Int(match int {
+pub fn compile_ty(ty: &ast::Ty, decls: &Decls) -> TyRef {
+    compile_nested_ty(ty, decls, &mut vec![])
+}
+
+fn compile_nested_ty(ty: &ast::Ty, decls: &Decls, outer: &mut Vec<(Symbol, TyRef)>) -> TyRef {
+    match ty {
+        ast::Ty::Int(int) => TyRef::new(Ty::Int(IntTyRef::new(IntTy::Int(match int {
             ast::Int::I8 => Int {
                 signedness: Signedness::Signed,
                 size: Size::B8,
@@ -98,32 +299,153 @@ pub fn compile_ty(ty: &ast::Ty, program: &ast::Program) -> TyRef {
                 signedness: Signedness::Unsigned,
                 size: Size::B32,
             },
-        }))),
-        ast::Ty::Bool => Ty::Bool,
-        ast::Ty::Struct(name) => {
-            let struct_decl = program
-                .struct_iter()
-                .find(|struct_decl| struct_decl.name == *name)
-                .unwrap();
-            compile_struct(struct_decl, program)
+            ast::Int::Usize => Int {
+                signedness: Signedness::Unsigned,
+                size: Size::Ptr,
+            },
+        })))),
+        ast::Ty::Bool => TyRef::new(Ty::Bool),
+        // unknown types have already been reported
+        ast::Ty::Struct(name, _) => match decls.struct_decl(*name) {
+            Some(struct_decl) => compile_struct(struct_decl, decls, outer),
+            None => TyRef::new(Ty::Error),
+        },
+        ast::Ty::Ref(ty) => TyRef::new(Ty::Ref(compile_nested_ty(ty, decls, outer))),
+        ast::Ty::NullableRef(ty) => {
+            TyRef::new(Ty::NullableRef(compile_nested_ty(ty, decls, outer)))
         }
-        ast::Ty::Ref(ty) => Ty::Ref(compile_ty(ty, program)),
-    })
+    }
 }
 
-fn deref_ty(ty: &TyRef) -> TyRef {
-    let any_ty = TyRef::new(Ty::Any);
-    let ref_ty = TyRef::new(Ty::Ref(any_ty.clone()));
-    unify(&ref_ty, ty).unwrap();
-    any_ty
+fn always_returns(stmt: &ast::Stmt) -> bool {
+    match stmt {
+        ast::Stmt::Return { .. } => true,
+        ast::Stmt::If(if_stmt) => if_always_returns(if_stmt),
+        ast::Stmt::Unsafe { body, .. } => body.stmts.iter().any(always_returns),
+        _ => false,
+    }
+}
+
+fn if_always_returns(if_stmt: &ast::If) -> bool {
+    let else_returns = match &if_stmt.else_block {
+        ast::Else::Block(block) => block.stmts.iter().any(always_returns),
+        ast::Else::If(if_stmt) => if_always_returns(if_stmt),
+        ast::Else::None => false,
+    };
+    else_returns && if_stmt.if_block.stmts.iter().any(always_returns)
+}
+
+fn unused_result(span: Span, message: String) -> Diagnostic {
+    let start = Span {
+        start: span.start,
+        end: span.start,
+    };
+    Diagnostic::new(Code::UnusedResult, span, message).with_suggestion(
+        start,
+        "_ = ",
+        "assign it to `_` to discard it explicitly".to_string(),
+    )
 }
 
 impl<'a> Compiler<'a> {
-    fn new_block(&mut self) -> typed_ast::BlockId {
+    fn unify<T: Unify>(&mut self, a: &InferTyRef<T>, b: &InferTyRef<T>) {
+        if let Err(err) = unify(a, b) {
+            self.report(err);
+        }
+    }
+    fn report(&mut self, err: TyError) {
+        match err {
+            TyError::MissingField {
+                struct_name,
+                field,
+                span,
+                fields,
+            } => {
+                let struct_name = self.symbols.get_str(struct_name);
+                let message = format!(
+                    "struct `{}` has no field `{}`",
+                    struct_name,
+                    self.symbols.get_str(field)
+                );
+                let note = if fields.is_empty() {
+                    format!("`{}` has no fields", struct_name)
+                } else {
+                    let fields: Vec<_> = fields
+                        .iter()
+                        .map(|field| format!("`{}`", self.symbols.get_str(*field)))
+                        .collect();
+                    format!("available fields are {}", fields.join(", "))
+                };
+                self.errors
+                    .push(Diagnostic::new(Code::UnknownField, span, message).with_note(note));
+            }
+            TyError::Mismatch => self.error(Code::TypeMismatch, "mismatched types".to_string()),
+        }
+    }
+    fn error(&mut self, code: Code, message: String) {
+        self.errors.push(Diagnostic::new(code, self.span, message));
+    }
+    // Unifies a value's type with the type it's assigned to, except that a reference
+    // can be assigned to a nullable reference.
+    fn coerce(&mut self, to: &TyRef, ty: &TyRef) {
+        let to_nullable = to.map(|ty| match ty {
+            Ty::NullableRef(ty) => Some(ty.clone()),
+            _ => None,
+        });
+        let from_ref = ty.map(|ty| match ty {
+            Ty::Ref(ty) => Some(ty.clone()),
+            _ => None,
+        });
+        match (to_nullable, from_ref) {
+            (Some(to), Some(ty)) => self.unify(&to, &ty),
+            _ => self.unify(to, ty),
+        }
+    }
+    fn check_unsafe(&mut self, span: Span, op: String) {
+        if self.in_unsafe {
+            self.unsafe_ops.push((span, op));
+        } else {
+            self.errors.push(Diagnostic::new(
+                Code::Unsafe,
+                span,
+                format!("{} is only allowed in an `unsafe` block", op),
+            ));
+        }
+    }
+    fn deref_ty(&mut self, ty: &TyRef) -> TyRef {
+        let nullable = ty.map(|ty| match ty {
+            Ty::NullableRef(ty) => Some(ty.clone()),
+            _ => None,
+        });
+        if let Some(ty) = nullable {
+            self.errors.push(
+                Diagnostic::new(
+                    Code::NullableDeref,
+                    self.span,
+                    "a nullable reference can't be dereferenced".to_string(),
+                )
+                .with_note("check it with `is_null` and then `unwrap` it".to_string()),
+            );
+            return ty;
+        }
+        let any_ty = TyRef::new(Ty::Any);
+        let ref_ty = TyRef::new(Ty::Ref(any_ty.clone()));
+        self.unify(&ref_ty, ty);
+        any_ty
+    }
+    fn compile_ty(&mut self, ty: &ast::Ty) -> TyRef {
+        if let Some(err) = self.decls.check_ty(ty, self.span, self.symbols) {
+            self.errors.push(err);
+        }
+        compile_ty(ty, self.decls)
+    }
+    fn new_block(&mut self, name: &'static str, span: Span) -> typed_ast::BlockId {
         let id = typed_ast::BlockId(self.blocks.len() as u32);
         self.blocks.push(typed_ast::Block {
             stmts: vec![],
             branch: typed_ast::Branch::Return(None),
+            name,
+            span,
         });
         id
     }
@@ -134,19 +456,50 @@ impl<'a> Compiler<'a> {
         self.blocks[id.0 as usize].branch = branch;
     }
     fn compile_block(&mut self, block: &ast::Block, block_id: &mut typed_ast::BlockId) {
+        let mut returned = false;
         for stmt in &block.stmts {
+            if returned {
+                self.warnings.push(
+                    Diagnostic::new(
+                        Code::Unreachable,
+                        stmt.span(),
+                        "unreachable statement".to_string(),
+                    )
+                    .with_note("every path before it returns".to_string()),
+                );
+                returned = false;
+            }
             self.compile_stmt(stmt, block_id);
+            returned |= always_returns(stmt);
         }
     }
+    fn eval_const(&self, expr: &ast::Expr) -> Option<Value> {
+        consteval::eval_expr(expr, self.consts, |name| {
+            self.scope.iter().any(|var| var.name == name)
+        })
+    }
     fn compile_stmt(&mut self, stmt: &ast::Stmt, block_id: &mut typed_ast::BlockId) {
+        self.span = stmt.span();
         match stmt {
-            ast::Stmt::While { cond, body } => {
-                let mut loop_block = self.new_block();
-                let cond_block = self.new_block();
-                let exit_block = self.new_block();
+            ast::Stmt::While {
+                cond,
+                cond_span,
+                body,
+                span,
+            } => {
+                if let Some(Value::Bool(false)) = self.eval_const(cond) {
+                    self.warnings.push(Diagnostic::new(
+                        Code::ConstantCondition,
+                        *cond_span,
+                        "loop condition is always `false`".to_string(),
+                    ));
+                }
+                let mut loop_block = self.new_block("while.body", *span);
+                let cond_block = self.new_block("while.cond", *span);
+                let exit_block = self.new_block("while.end", *span);
                 self.set_branch(*block_id, typed_ast::Branch::Static(cond_block));
                 let (cond_expr, cond_ty) = self.compile_expr(cond);
-                unify(&cond_ty, &TyRef::new(Ty::Bool)).unwrap();
+                self.unify(&cond_ty, &TyRef::new(Ty::Bool));
                 self.set_branch(
                     cond_block,
                     typed_ast::Branch::Condition {
@@ -159,10 +512,16 @@ impl<'a> Compiler<'a> {
                 self.set_branch(loop_block, typed_ast::Branch::Static(cond_block));
                 *block_id = exit_block;
             }
+            ast::Stmt::Unsafe { body, .. } => {
+                let outer = mem::replace(&mut self.in_unsafe, true);
+                self.compile_block(body, block_id);
+                self.in_unsafe = outer;
+            }
             ast::Stmt::Let {
                 ident,
                 expr,
                 ty: ast_ty,
+                span,
             } => {
                 let var = typed_ast::Variable(self.scope.len() as u32);
                 let ty = TyRef::new(Ty::Any);
@@ -170,51 +529,121 @@ impl<'a> Compiler<'a> {
                     name: *ident,
                     var,
                     ty: ty.clone(),
+                    span: *span,
+                    poisoned: false,
                 });
-                self.push_stmt(*block_id, typed_ast::Stmt::Alloc(ty.clone()));
+                self.push_stmt(*block_id, typed_ast::Stmt::Alloc(var, ty.clone()));
+                self.types.push((*span, ty.clone()));
+                let errors = self.errors.len();
 
                 if let Some(ast_ty) = ast_ty {
-                    let ast_ty = compile_ty(ast_ty, self.program);
-                    unify(&ty, &ast_ty).unwrap();
+                    let ast_ty = self.compile_ty(ast_ty);
+                    self.unify(&ty, &ast_ty);
                 }
 
                 if let Some(expr) = expr {
                     let (expr, expr_ty) = self.compile_expr(expr);
-                    unify(&ty, &expr_ty).unwrap();
+                    self.coerce(&ty, &expr_ty);
                     self.push_stmt(
                         *block_id,
                         typed_ast::Stmt::Assign {
                             ref_expr: typed_ast::RefExpr::Variable(var),
                             ty,
                             expr,
+                            span: *span,
                         },
                     );
                 }
+                // an explicit type still holds, even if the value didn't match it
+                if ast_ty.is_none() && self.errors.len() > errors {
+                    self.scope[var.0 as usize].poisoned = true;
+                }
             }
-            ast::Stmt::Assign { ref_expr, expr } => {
+            ast::Stmt::Assign {
+                ref_expr,
+                expr,
+                span,
+            } => {
                 let (ref_expr, ref_ty) = self.compile_ref_expr(ref_expr);
                 let (expr, ty) = self.compile_expr(expr);
-                unify(&ref_ty, &ty).unwrap();
-                self.push_stmt(*block_id, typed_ast::Stmt::Assign { ref_expr, expr, ty })
+                self.coerce(&ref_ty, &ty);
+                self.push_stmt(
+                    *block_id,
+                    typed_ast::Stmt::Assign {
+                        ref_expr,
+                        expr,
+                        ty,
+                        span: *span,
+                    },
+                )
+            }
+            ast::Stmt::Discard { expr, .. } => {
+                let (expr, _) = self.compile_expr(expr);
+                self.push_stmt(*block_id, typed_ast::Stmt::Discard(expr))
             }
-            ast::Stmt::Return(expr) => {
+            ast::Stmt::Return { expr, span } => {
                 let expr = expr.as_ref().map(|expr| self.compile_expr(expr));
-                let expr = match (expr, &self.returns) {
+                let expr = match (expr, self.returns.clone()) {
                     (Some((expr, ty)), Some(returns)) => {
-                        unify(&returns, &ty).unwrap();
+                        self.coerce(&returns, &ty);
                         Some(expr)
                     }
                     (None, None) => None,
-                    _ => panic!(),
+                    (Some((expr, _)), None) => {
+                        self.error(
+                            Code::ReturnValue,
+                            "this function doesn't return a value".to_string(),
+                        );
+                        Some(expr)
+                    }
+                    (None, Some(_)) => {
+                        self.error(Code::ReturnValue, "expected a value to return".to_string());
+                        None
+                    }
                 };
                 self.set_branch(*block_id, typed_ast::Branch::Return(expr));
-                *block_id = self.new_block();
+                *block_id = self.new_block("return.after", *span);
             }
             ast::Stmt::If(if_stmt) => self.compile_if(if_stmt, block_id),
             ast::Stmt::FuncCall(fn_call) => {
-                let (args, ty) = self.compile_fn_call(fn_call);
+                if self.is_assert(fn_call.name) {
+                    if let Some(stmt) = self.compile_assert(fn_call) {
+                        self.push_stmt(*block_id, stmt);
+                    }
+                    return;
+                }
+                if let Some(op) = self.atomic(fn_call.name) {
+                    let (expr, _) = self.compile_atomic(op, fn_call);
+                    // the other atomics are mostly used for their effect
+                    if let typed_ast::AtomicOp::Load = op {
+                        self.warnings.push(unused_result(
+                            fn_call.span,
+                            "result of `atomic_load` is unused".to_string(),
+                        ));
+                    }
+                    self.push_stmt(*block_id, typed_ast::Stmt::Discard(expr));
+                    return;
+                }
+                if let Some(intrinsic) = self.intrinsic(fn_call.name) {
+                    let (expr, _) = self.compile_intrinsic(intrinsic, fn_call);
+                    let name = self.symbols.get_str(fn_call.name);
+                    self.warnings.push(unused_result(
+                        fn_call.span,
+                        format!("result of `{}` is unused", name),
+                    ));
+                    self.push_stmt(*block_id, typed_ast::Stmt::Discard(expr));
+                    return;
+                }
+                let (args, ty) = match self.compile_fn_call(fn_call) {
+                    Some(call) => call,
+                    None => return,
+                };
                 if ty.is_some() {
-                    panic!()
+                    let name = self.symbols.get_str(fn_call.name);
+                    self.warnings.push(unused_result(
+                        fn_call.span,
+                        format!("result of call to `{}` is unused", name),
+                    ));
                 }
                 self.push_stmt(
                     *block_id,
@@ -228,18 +657,29 @@ impl<'a> Compiler<'a> {
     }
     fn compile_ref_expr(&mut self, ref_expr: &ast::RefExpr) -> (typed_ast::RefExpr, TyRef) {
         match ref_expr {
-            ast::RefExpr::Ident(name) => {
-                let var = self.lookup_var(*name);
-                (typed_ast::RefExpr::Variable(var.var), var.ty.clone())
-            }
-            ast::RefExpr::Field { ref_expr, name } => {
+            ast::RefExpr::Ident(name, _) => match self.lookup_var(*name) {
+                Some(var) => (typed_ast::RefExpr::Variable(var.var), var.ty()),
+                None => {
+                    let name = self.symbols.get_str(*name);
+                    self.error(Code::UnknownName, format!("unknown variable `{}`", name));
+                    (
+                        typed_ast::RefExpr::Variable(typed_ast::Variable(0)),
+                        TyRef::new(Ty::Error),
+                    )
+                }
+            },
+            ast::RefExpr::Field {
+                ref_expr,
+                name,
+                span,
+            } => {
                 let (ref_expr, ref_expr_ty) = self.compile_ref_expr(ref_expr);
                 let field_ty = TyRef::new(Ty::Any);
-                let mut with_fields = HashMap::new();
-                with_fields.insert(*name, field_ty.clone());
+                let mut with_fields = BTreeMap::new();
+                with_fields.insert(*name, (field_ty.clone(), *span));
                 let struct_ty = StructTyRef::new(StructTy::WithFields(with_fields));
                 let ty = TyRef::new(Ty::Struct(struct_ty.clone()));
-                unify(&ty, &ref_expr_ty).unwrap();
+                self.unify(&ty, &ref_expr_ty);
                 (
                     typed_ast::RefExpr::Field {
                         ref_expr: Box::new(ref_expr),
@@ -251,14 +691,27 @@ impl<'a> Compiler<'a> {
             }
             ast::RefExpr::Deref(expr) => {
                 let (expr, ty) = self.compile_expr(expr);
-                (typed_ast::RefExpr::Deref(Box::new(expr)), deref_ty(&ty))
+                let ty = self.deref_ty(&ty);
+                (typed_ast::RefExpr::Deref(Box::new(expr), self.span), ty)
             }
         }
     }
     fn compile_if(&mut self, if_stmt: &ast::If, block_id: &mut typed_ast::BlockId) {
-        let mut if_block = self.new_block();
-        let mut else_block = self.new_block();
+        if let Some(Value::Bool(value)) = self.eval_const(&if_stmt.cond) {
+            self.warnings.push(Diagnostic::new(
+                Code::ConstantCondition,
+                if_stmt.cond_span,
+                format!("condition is always `{}`", value),
+            ));
+        }
+        let mut if_block = self.new_block("if.then", if_stmt.span);
+        let else_name = match if_stmt.else_block {
+            ast::Else::None => "if.end",
+            _ => "if.else",
+        };
+        let mut else_block = self.new_block(else_name, if_stmt.span);
         let (cond_expr, cond_ty) = self.compile_expr(&if_stmt.cond);
+        self.unify(&cond_ty, &TyRef::new(Ty::Bool));
         self.set_branch(
             *block_id,
             typed_ast::Branch::Condition {
@@ -268,11 +721,10 @@ impl<'a> Compiler<'a> {
             },
         );
         self.compile_block(&if_stmt.if_block, &mut if_block);
-        unify(&cond_ty, &TyRef::new(Ty::Bool)).unwrap();
 
         match &if_stmt.else_block {
             ast::Else::Block(else_ast_block) => {
-                let exit_block_id = self.new_block();
+                let exit_block_id = self.new_block("if.end", if_stmt.span);
                 self.compile_block(else_ast_block, &mut else_block);
                 self.set_branch(else_block, typed_ast::Branch::Static(exit_block_id));
                 self.set_branch(if_block, typed_ast::Branch::Static(exit_block_id));
@@ -289,44 +741,88 @@ impl<'a> Compiler<'a> {
             }
         }
     }
-    fn lookup_var(&self, name: Symbol) -> &Variable {
-        self.scope.iter().find(|var| var.name == name).unwrap()
+    fn lookup_var(&self, name: Symbol) -> Option<&Variable> {
+        self.scope.iter().find(|var| var.name == name)
     }
     fn compile_expr(&mut self, expr: &ast::Expr) -> (typed_ast::Expr, TyRef) {
+        let (typed_expr, ty) = self.compile_expr_kind(expr);
+        if let Some(span) = expr.span() {
+            self.types.push((span, ty.clone()));
+        }
+        (typed_expr, ty)
+    }
+    fn compile_expr_kind(&mut self, expr: &ast::Expr) -> (typed_ast::Expr, TyRef) {
         match expr {
-            ast::Expr::Integer(value) => {
+            ast::Expr::Integer(value, span) => {
                 let int_ty = IntTyRef::new(IntTy::Any);
+                self.literals.push((*value, *span, int_ty.clone()));
                 (typed_ast::Expr::Int(*value), TyRef::new(Ty::Int(int_ty)))
             }
+            ast::Expr::Char(value) => {
+                let ty = compile_ty(&ast::Ty::Int(ast::Int::U8), self.decls);
+                (typed_ast::Expr::Int(*value as i64), ty)
+            }
             ast::Expr::Bool(value) => (typed_ast::Expr::Bool(*value), TyRef::new(Ty::Bool)),
-            ast::Expr::Infix { left, right, op } => match op {
-                ast::InfixOp::Add => self.compile_arth_expr(left, right, typed_ast::BinaryOp::Add),
+            ast::Expr::Null => {
+                let ty = TyRef::new(Ty::NullableRef(TyRef::new(Ty::Any)));
+                (typed_ast::Expr::Null, ty)
+            }
+            ast::Expr::Infix {
+                left,
+                right,
+                op,
+                span,
+            } => match op {
+                ast::InfixOp::Add => {
+                    self.compile_arth_expr(left, right, typed_ast::BinaryOp::Add, *span)
+                }
                 ast::InfixOp::Subtract => {
-                    self.compile_arth_expr(left, right, typed_ast::BinaryOp::Subtract)
+                    self.compile_arth_expr(left, right, typed_ast::BinaryOp::Subtract, *span)
                 }
                 ast::InfixOp::Multiply => {
-                    self.compile_arth_expr(left, right, typed_ast::BinaryOp::Multiply)
+                    self.compile_arth_expr(left, right, typed_ast::BinaryOp::Multiply, *span)
                 }
                 ast::InfixOp::Divide => {
-                    self.compile_arth_expr(left, right, typed_ast::BinaryOp::Divide)
+                    self.compile_arth_expr(left, right, typed_ast::BinaryOp::Divide, *span)
                 }
 
                 ast::InfixOp::LessThan => {
-                    self.compile_cmp_expr(left, right, typed_ast::BinaryOp::LessThan)
+                    self.compile_cmp_expr(left, right, typed_ast::BinaryOp::LessThan, *span)
                 }
                 ast::InfixOp::GreaterThan => {
-                    self.compile_cmp_expr(left, right, typed_ast::BinaryOp::GreaterThan)
+                    self.compile_cmp_expr(left, right, typed_ast::BinaryOp::GreaterThan, *span)
                 }
             },
-            ast::Expr::Ident(ident) => {
-                let var = self.lookup_var(*ident);
-                (
-                    typed_ast::Expr::Load {
-                        var: var.var,
-                        ty: var.ty.clone(),
-                    },
-                    var.ty.clone(),
-                )
+            ast::Expr::Ident { name: ident, span } => {
+                if let Some(var) = self.lookup_var(*ident) {
+                    let ty = var.ty();
+                    return (
+                        typed_ast::Expr::Load {
+                            var: var.var,
+                            ty: ty.clone(),
+                            span: *span,
+                        },
+                        ty,
+                    );
+                }
+                let const_decl = self.decls.const_decl(*ident);
+                let expr = match (const_decl, self.consts.get(*ident)) {
+                    (Some(const_decl), Some(value)) => {
+                        let expr = match value {
+                            Value::Int(value) => typed_ast::Expr::Int(value),
+                            Value::Bool(value) => typed_ast::Expr::Bool(value),
+                        };
+                        return (expr, compile_ty(&const_decl.ty, self.decls));
+                    }
+                    _ => typed_ast::Expr::Int(0),
+                };
+                let name = self.symbols.get_str(*ident);
+                self.errors.push(Diagnostic::new(
+                    Code::UnknownName,
+                    *span,
+                    format!("unknown variable or constant `{}`", name),
+                ));
+                (expr, TyRef::new(Ty::Error))
             }
             ast::Expr::Ref(ref_expr) => {
                 let (ref_expr, ty) = self.compile_ref_expr(ref_expr);
@@ -335,19 +831,60 @@ impl<'a> Compiler<'a> {
             ast::Expr::Prefix { op, expr } => match op {
                 ast::PrefixOp::Deref => {
                     let (expr, ty) = self.compile_expr(expr);
-                    let ty = deref_ty(&ty);
+                    let ty = self.deref_ty(&ty);
                     (
                         typed_ast::Expr::Deref {
                             expr: Box::new(expr),
                             ty: ty.clone(),
+                            span: self.span,
+                        },
+                        ty,
+                    )
+                }
+                ast::PrefixOp::BitNot => {
+                    let (expr, expr_ty) = self.compile_expr(expr);
+                    let int_ty = IntTyRef::new(IntTy::Any);
+                    let ty = TyRef::new(Ty::Int(int_ty.clone()));
+                    self.unify(&ty, &expr_ty);
+                    (
+                        typed_ast::Expr::BitNot {
+                            expr: Box::new(expr),
+                            ty: int_ty,
                         },
                         ty,
                     )
                 }
             },
             ast::Expr::FuncCall(fn_call) => {
-                let (args, ty) = self.compile_fn_call(fn_call);
-                let result = ty.unwrap();
+                if let Some(intrinsic) = self.intrinsic(fn_call.name) {
+                    return self.compile_intrinsic(intrinsic, fn_call);
+                }
+                if let Some(op) = self.atomic(fn_call.name) {
+                    if let typed_ast::AtomicOp::Store = op {
+                        self.errors.push(Diagnostic::new(
+                            Code::NoValue,
+                            fn_call.span,
+                            "`atomic_store` doesn't return a value".to_string(),
+                        ));
+                    }
+                    return self.compile_atomic(op, fn_call);
+                }
+                if let Some(expr) = self.compile_null_check(fn_call) {
+                    return expr;
+                }
+                let (args, ty) = match self.compile_fn_call(fn_call) {
+                    Some(call) => call,
+                    None => return (typed_ast::Expr::Int(0), TyRef::new(Ty::Error)),
+                };
+                let result = ty.unwrap_or_else(|| {
+                    let name = self.symbols.get_str(fn_call.name);
+                    self.errors.push(Diagnostic::new(
+                        Code::NoValue,
+                        fn_call.span,
+                        format!("`{}` doesn't return a value", name),
+                    ));
+                    TyRef::new(Ty::Error)
+                });
                 (
                     typed_ast::Expr::FuncCall(typed_ast::FuncCall {
                         name: fn_call.name,
@@ -356,46 +893,67 @@ impl<'a> Compiler<'a> {
                     result,
                 )
             }
-            ast::Expr::InitStruct { name, values } => {
-                let struct_decl = self
-                    .program
-                    .struct_iter()
-                    .find(|struct_decl| struct_decl.name == *name)
-                    .unwrap();
-                let mut done = HashSet::new();
+            ast::Expr::InitStruct { name, values, span } => {
+                let struct_name = self.symbols.get_str(*name);
+                let struct_decl = match self.decls.struct_decl(*name) {
+                    Some(struct_decl) => struct_decl,
+                    None => {
+                        self.error(
+                            Code::UnknownType,
+                            format!("unknown struct `{}`", struct_name),
+                        );
+                        return (typed_ast::Expr::Int(0), TyRef::new(Ty::Error));
+                    }
+                };
+                for value in values {
+                    let known = struct_decl
+                        .fields
+                        .iter()
+                        .any(|field| field.name == value.name);
+                    if !known {
+                        let field = self.symbols.get_str(value.name);
+                        self.error(
+                            Code::UnknownField,
+                            format!("struct `{}` has no field `{}`", struct_name, field),
+                        );
+                    }
+                }
                 let mut mir_values = vec![];
                 let mut tys = vec![];
                 for field in struct_decl.fields.iter() {
-                    if done.contains(&field.name) {
-                        panic!()
-                    }
-                    done.insert(field.name);
-                    let value = values
-                        .iter()
-                        .find(|value| value.name == field.name)
-                        .unwrap();
+                    let value = match values.iter().find(|value| value.name == field.name) {
+                        Some(value) => value,
+                        None => {
+                            let field = self.symbols.get_str(field.name);
+                            self.error(
+                                Code::MissingField,
+                                format!("missing a value for `{}.{}`", struct_name, field),
+                            );
+                            continue;
+                        }
+                    };
                     let (expr, ty) = self.compile_expr(&value.expr);
-                    let field_ty = compile_ty(&field.ty, self.program);
-                    unify(&ty, &field_ty).unwrap();
+                    let field_ty = compile_ty(&field.ty, self.decls);
+                    self.coerce(&field_ty, &ty);
                     tys.push(field_ty);
-                    mir_values.push(typed_ast::StructValue { ty, expr });
+                    mir_values.push(typed_ast::StructValue {
+                        name: field.name,
+                        ty,
+                        expr,
+                    });
                 }
-                let struct_decl = self
-                    .program
-                    .struct_iter()
-                    .find(|struct_decl| struct_decl.name == *name)
-                    .unwrap();
-                let ty = TyRef::new(compile_struct(struct_decl, self.program));
+                let ty = compile_ty(&ast::Ty::Struct(*name, *span), self.decls);
                 (typed_ast::Expr::InitStruct(mir_values), ty)
             }
-            ast::Expr::Field { expr, name } => {
+            ast::Expr::Cast { expr, ty, span } => self.compile_cast(expr, ty, *span),
+            ast::Expr::Field { expr, name, span } => {
                 let (expr, expr_ty) = self.compile_expr(expr);
                 let field_ty = TyRef::new(Ty::Any);
-                let mut with_fields = HashMap::new();
-                with_fields.insert(*name, field_ty.clone());
+                let mut with_fields = BTreeMap::new();
+                with_fields.insert(*name, (field_ty.clone(), *span));
                 let struct_ty = StructTyRef::new(StructTy::WithFields(with_fields));
                 let derefs_ty = TyRef::new(Ty::Struct(struct_ty.clone()));
-                unify(&derefs_ty, &expr_ty).unwrap();
+                self.unify(&derefs_ty, &expr_ty);
                 (
                     typed_ast::Expr::Field {
                         expr: Box::new(expr),
@@ -407,17 +965,235 @@ impl<'a> Compiler<'a> {
             }
         }
     }
+    // A reference and a `usize` are represented the same way, so a cast between them
+    // keeps the value and only changes its type. `char` is another name for `u8`.
+    fn compile_cast(
+        &mut self,
+        expr: &ast::Expr,
+        ty: &ast::Ty,
+        span: Span,
+    ) -> (typed_ast::Expr, TyRef) {
+        let (expr, expr_ty) = self.compile_expr(expr);
+        let to = self.compile_ty(ty);
+        let usize_ty = TyRef::new(Ty::Int(IntTyRef::new(IntTy::Int(Int {
+            signedness: Signedness::Unsigned,
+            size: Size::Ptr,
+        }))));
+        let op = format!("casting to `{}`", ty.name(self.symbols));
+        match ty {
+            ast::Ty::Ref(_) | ast::Ty::NullableRef(_) => {
+                self.unify(&usize_ty, &expr_ty);
+                self.check_unsafe(span, op);
+            }
+            ast::Ty::Int(ast::Int::Usize) => {
+                let is_nullable = expr_ty.map(|ty| matches!(ty, Ty::NullableRef(_)));
+                if !is_nullable {
+                    self.unify(&TyRef::new(Ty::Ref(TyRef::new(Ty::Any))), &expr_ty);
+                }
+                self.check_unsafe(span, op);
+            }
+            // a cast between `char` and `u8` doesn't change anything
+            ast::Ty::Int(ast::Int::U8) => self.unify(&to, &expr_ty),
+            _ => self.errors.push(Diagnostic::new(
+                Code::InvalidCast,
+                span,
+                format!("can't cast to `{}`", ty.name(self.symbols)),
+            )),
+        }
+        (expr, to)
+    }
+    // Builtins can be shadowed by a function with the same name.
+    fn intrinsic(&self, name: Symbol) -> Option<typed_ast::Intrinsic> {
+        if self.decls.func(name).is_some() {
+            return None;
+        }
+        match self.symbols.get_str(name) {
+            "ctz" => Some(typed_ast::Intrinsic::Ctz),
+            "clz" => Some(typed_ast::Intrinsic::Clz),
+            "popcount" => Some(typed_ast::Intrinsic::Popcount),
+            "bswap" => Some(typed_ast::Intrinsic::Bswap),
+            _ => None,
+        }
+    }
+    fn compile_intrinsic(
+        &mut self,
+        intrinsic: typed_ast::Intrinsic,
+        fn_call: &ast::FuncCall,
+    ) -> (typed_ast::Expr, TyRef) {
+        let int_ty = IntTyRef::new(IntTy::Any);
+        let ty = TyRef::new(Ty::Int(int_ty.clone()));
+        let arg = match &fn_call.args[..] {
+            [arg] => {
+                let (arg, arg_ty) = self.compile_expr(arg);
+                self.unify(&ty, &arg_ty);
+                arg
+            }
+            args => {
+                self.errors.push(self.argument_count(fn_call, 1));
+                for arg in args {
+                    self.compile_expr(arg);
+                }
+                typed_ast::Expr::Int(0)
+            }
+        };
+        let expr = typed_ast::Expr::Intrinsic {
+            intrinsic,
+            arg: Box::new(arg),
+            ty: int_ty,
+        };
+        (expr, ty)
+    }
+    // The atomics are builtins like the intrinsics, which take a reference to an integer
+    // followed by the values for the operation.
+    fn atomic(&self, name: Symbol) -> Option<typed_ast::AtomicOp> {
+        if self.decls.func(name).is_some() {
+            return None;
+        }
+        match self.symbols.get_str(name) {
+            "atomic_load" => Some(typed_ast::AtomicOp::Load),
+            "atomic_store" => Some(typed_ast::AtomicOp::Store),
+            "atomic_add" => Some(typed_ast::AtomicOp::Add),
+            "atomic_compare_exchange" => Some(typed_ast::AtomicOp::CompareExchange),
+            _ => None,
+        }
+    }
+    fn compile_atomic(
+        &mut self,
+        op: typed_ast::AtomicOp,
+        fn_call: &ast::FuncCall,
+    ) -> (typed_ast::Expr, TyRef) {
+        let int_ty = IntTyRef::new(IntTy::Any);
+        let ty = TyRef::new(Ty::Int(int_ty.clone()));
+        let values = match op {
+            typed_ast::AtomicOp::Load => 0,
+            typed_ast::AtomicOp::Store | typed_ast::AtomicOp::Add => 1,
+            typed_ast::AtomicOp::CompareExchange => 2,
+        };
+        if fn_call.args.len() != values + 1 {
+            self.errors.push(self.argument_count(fn_call, values + 1));
+        }
+        let mut args = vec![];
+        for (index, arg) in fn_call.args.iter().enumerate() {
+            let (arg, arg_ty) = self.compile_expr(arg);
+            match index {
+                0 => self.unify(&TyRef::new(Ty::Ref(ty.clone())), &arg_ty),
+                _ => self.unify(&ty, &arg_ty),
+            }
+            args.push(arg);
+        }
+        args.resize(values + 1, typed_ast::Expr::Int(0));
+        let result = match op {
+            typed_ast::AtomicOp::CompareExchange => TyRef::new(Ty::Bool),
+            _ => ty,
+        };
+        let expr = typed_ast::Expr::Atomic {
+            op,
+            args,
+            ty: int_ty,
+            span: self.span,
+        };
+        (expr, result)
+    }
+    // `is_null` and `unwrap` are builtins like the intrinsics. A nullable reference has
+    // to be unwrapped before it can be dereferenced, which panics if it's null.
+    fn compile_null_check(&mut self, fn_call: &ast::FuncCall) -> Option<(typed_ast::Expr, TyRef)> {
+        if self.decls.func(fn_call.name).is_some() {
+            return None;
+        }
+        let unwrap = match self.symbols.get_str(fn_call.name) {
+            "unwrap" => true,
+            "is_null" => false,
+            _ => return None,
+        };
+        let ty = TyRef::new(Ty::Any);
+        let arg = match &fn_call.args[..] {
+            [arg] => {
+                let (arg, arg_ty) = self.compile_expr(arg);
+                self.unify(&TyRef::new(Ty::NullableRef(ty.clone())), &arg_ty);
+                arg
+            }
+            args => {
+                self.errors.push(self.argument_count(fn_call, 1));
+                for arg in args {
+                    self.compile_expr(arg);
+                }
+                typed_ast::Expr::Null
+            }
+        };
+        Some(if unwrap {
+            let expr = typed_ast::Expr::Unwrap {
+                expr: Box::new(arg),
+                span: self.span,
+            };
+            (expr, TyRef::new(Ty::Ref(ty)))
+        } else {
+            (typed_ast::Expr::IsNull(Box::new(arg)), TyRef::new(Ty::Bool))
+        })
+    }
+    // `assert` is a builtin like the intrinsics, but as it has no value it can only be
+    // called as a statement.
+    fn is_assert(&self, name: Symbol) -> bool {
+        self.decls.func(name).is_none() && self.symbols.get_str(name) == "assert"
+    }
+    fn compile_assert(&mut self, fn_call: &ast::FuncCall) -> Option<typed_ast::Stmt> {
+        let [cond] = &fn_call.args[..] else {
+            self.errors.push(self.argument_count(fn_call, 1));
+            for arg in &fn_call.args {
+                self.compile_expr(arg);
+            }
+            return None;
+        };
+        let (expr, ty) = self.compile_expr(cond);
+        self.unify(&ty, &TyRef::new(Ty::Bool));
+        Some(typed_ast::Stmt::Assert {
+            expr,
+            span: fn_call.args_span,
+        })
+    }
+    fn argument_count(&self, func_call: &ast::FuncCall, params: usize) -> Diagnostic {
+        let plural = if params == 1 { "" } else { "s" };
+        Diagnostic::new(
+            Code::ArgumentCount,
+            func_call.span,
+            format!(
+                "`{}` takes {} argument{} but {} were given",
+                self.symbols.get_str(func_call.name),
+                params,
+                plural,
+                func_call.args.len()
+            ),
+        )
+    }
+    // Returns `None` if the function doesn't exist.
     fn compile_fn_call(
         &mut self,
         func_call: &ast::FuncCall,
-    ) -> (Vec<typed_ast::Expr>, Option<TyRef>) {
-        let func = self
-            .program
-            .func_iter()
-            .find(|func| func.name == func_call.name)
-            .unwrap();
+    ) -> Option<(Vec<typed_ast::Expr>, Option<TyRef>)> {
+        let func = match self.decls.func(func_call.name) {
+            Some(func) => func,
+            None => {
+                let name = self.symbols.get_str(func_call.name);
+                self.errors.push(Diagnostic::new(
+                    Code::UnknownFunction,
+                    func_call.span,
+                    format!("unknown function `{}`", name),
+                ));
+                for arg in &func_call.args {
+                    self.compile_expr(arg);
+                }
+                return None;
+            }
+        };
+        if func.is_unsafe {
+            let name = self.symbols.get_str(func_call.name);
+            self.check_unsafe(func_call.span, format!("calling `{}`", name));
+        }
         if func_call.args.len() != func.params.len() {
-            panic!()
+            self.errors
+                .push(self.argument_count(func_call, func.params.len()));
+            for arg in &func_call.args[func.params.len().min(func_call.args.len())..] {
+                self.compile_expr(arg);
+            }
         }
         let args = func_call
             .args
@@ -425,34 +1201,50 @@ impl<'a> Compiler<'a> {
             .zip(&func.params)
             .map(|(arg, param)| {
                 let (expr, ty) = self.compile_expr(arg);
-                let param_ty = compile_ty(&param.ty, self.program);
-                unify(&ty, &param_ty).unwrap();
+                let param_ty = compile_ty(&param.ty, self.decls);
+                self.coerce(&param_ty, &ty);
                 expr
             })
             .collect();
-        (
+        Some((
             args,
-            func.returns.as_ref().map(|ty| compile_ty(ty, self.program)),
-        )
+            func.returns.as_ref().map(|ty| compile_ty(ty, self.decls)),
+        ))
     }
     fn compile_arth_expr(
         &mut self,
         left: &ast::Expr,
         right: &ast::Expr,
         op: typed_ast::BinaryOp,
+        span: Span,
     ) -> (typed_ast::Expr, TyRef) {
         let (left_expr, left_ty) = self.compile_expr(left);
         let (right_expr, right_ty) = self.compile_expr(right);
         let int_ty = IntTyRef::new(IntTy::Any);
         let ty = TyRef::new(Ty::Int(int_ty.clone()));
-        unify(&ty, &left_ty).unwrap();
-        unify(&ty, &right_ty).unwrap();
+        let is_ptr = left_ty.map(|ty| matches!(ty, Ty::Ref(_)));
+        if is_ptr && matches!(op, typed_ast::BinaryOp::Add | typed_ast::BinaryOp::Subtract) {
+            self.unify(&ty, &right_ty);
+            return (
+                typed_ast::Expr::PtrOffset {
+                    ptr: Box::new(left_expr),
+                    offset: Box::new(right_expr),
+                    ty: self.deref_ty(&left_ty),
+                    offset_ty: int_ty,
+                    op,
+                },
+                left_ty,
+            );
+        }
+        self.unify(&ty, &left_ty);
+        self.unify(&ty, &right_ty);
         (
             typed_ast::Expr::Binary {
                 left: Box::new(left_expr),
                 right: Box::new(right_expr),
                 ty: int_ty,
                 op,
+                span,
             },
             ty,
         )
@@ -462,20 +1254,22 @@ impl<'a> Compiler<'a> {
         left: &ast::Expr,
         right: &ast::Expr,
         op: typed_ast::BinaryOp,
+        span: Span,
     ) -> (typed_ast::Expr, TyRef) {
         let (left_expr, left_ty) = self.compile_expr(left);
         let (right_expr, right_ty) = self.compile_expr(right);
         let int_ty = IntTyRef::new(IntTy::Any);
         let ty = TyRef::new(Ty::Int(int_ty.clone()));
         let bool_ty = TyRef::new(Ty::Bool);
-        unify(&ty, &left_ty).unwrap();
-        unify(&ty, &right_ty).unwrap();
+        self.unify(&ty, &left_ty);
+        self.unify(&ty, &right_ty);
         (
             typed_ast::Expr::Binary {
                 left: Box::new(left_expr),
                 right: Box::new(right_expr),
                 ty: int_ty,
                 op,
+                span,
             },
             bool_ty,
         )
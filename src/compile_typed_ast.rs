@@ -1,16 +1,16 @@
-use crate::{ir, ty, typed_ast};
+use crate::{coalesce, ir, ty, typed_ast};
 
 fn concrete_ty(ty: &ty::TyRef) -> ir::Ty {
     ty.map(|ty| match ty {
         ty::Ty::Bool => ir::Ty::Bool,
-        ty::Ty::Ref(_) => ir::Ty::Ptr,
+        ty::Ty::Ref(_) | ty::Ty::NullableRef(_) => ir::Ty::Ptr,
         ty::Ty::Int(int) => ir::Ty::Int(concrete_int(int)),
         ty::Ty::Struct(s) => ir::Ty::Struct(concrete_struct(s)),
-        ty::Ty::Any => panic!(),
+        ty::Ty::Any | ty::Ty::Error => panic!(),
     })
 }
 
-fn concrete_int(ty: &ty::IntTyRef) -> ty::Int {
+pub fn concrete_int(ty: &ty::IntTyRef) -> ty::Int {
     ty.map(|ty| match ty {
         ty::IntTy::Int(int) => *int,
         ty::IntTy::Any => ty::Int {
@@ -33,21 +33,59 @@ fn concrete_struct(ty: &ty::StructTyRef) -> Vec<ir::StructField> {
     })
 }
 
-pub fn lower_func(func: &typed_ast::Func) -> ir::Func {
-    let blocks = func.blocks.iter().map(|block| lower_block(block)).collect();
-    let params = func.params.iter().map(|ty| concrete_ty(ty)).collect();
+// Struct literals are built in temporaries which only live until the end of the
+// statement, so temporaries from different statements can share a slot.
+struct Temps {
+    tys: Vec<Vec<ir::Ty>>,
+    used: usize,
+}
+
+impl Temps {
+    fn alloc(&mut self, ty: ir::Ty) -> u32 {
+        if self.used == self.tys.len() {
+            self.tys.push(vec![]);
+        }
+        self.tys[self.used].push(ty);
+        self.used += 1;
+        self.used as u32 - 1
+    }
+}
+
+pub fn lower_func(func: &typed_ast::Func, coalesce: bool) -> ir::Func {
+    let mut temps = Temps {
+        tys: vec![],
+        used: 0,
+    };
+    let blocks = func.blocks.iter().map(|block| lower_block(block, &mut temps)).collect();
+    let params: Vec<_> = func.params.iter().map(concrete_ty).collect();
+    let mut vars: Vec<_> = params.iter().cloned().map(Some).collect();
+    vars.resize(coalesce::var_count(func), None);
+    for stmt in func.blocks.iter().flat_map(|block| &block.stmts) {
+        if let typed_ast::Stmt::Alloc(var, ty) = stmt {
+            vars[var.0 as usize] = Some(concrete_ty(ty));
+        }
+    }
+    let slots = if coalesce {
+        coalesce::coalesce_slots(func)
+    } else {
+        (0..coalesce::var_count(func) as u32).collect()
+    };
     ir::Func {
         blocks,
         name: func.name,
         params,
+        vars: vars.into_iter().map(Option::unwrap).collect(),
+        slots,
+        temps: temps.tys,
     }
 }
 
-fn lower_block(block: &typed_ast::Block) -> ir::Block {
-    let stmts = block.stmts.iter().map(|stmt| lower_stmt(stmt)).collect();
+fn lower_block(block: &typed_ast::Block, temps: &mut Temps) -> ir::Block {
+    let stmts = block.stmts.iter().filter_map(|stmt| lower_stmt(stmt, temps)).collect();
+    temps.used = 0;
     let branch = match &block.branch {
         typed_ast::Branch::Return(expr) => {
-            ir::Branch::Return(expr.as_ref().map(|expr| lower_expr(expr)))
+            ir::Branch::Return(expr.as_ref().map(|expr| lower_expr(expr, temps)))
         }
         typed_ast::Branch::Static(block) => ir::Branch::Static(*block),
         typed_ast::Branch::Condition {
@@ -55,37 +93,51 @@ fn lower_block(block: &typed_ast::Block) -> ir::Block {
             if_true,
             if_false,
         } => ir::Branch::Condition {
-            expr: lower_expr(&expr),
+            expr: lower_expr(expr, temps),
             if_true: *if_true,
             if_false: *if_false,
         },
     };
-    ir::Block { stmts, branch }
+    ir::Block {
+        stmts,
+        branch,
+        name: block.name,
+        span: block.span,
+    }
 }
 
-fn lower_stmt(stmt: &typed_ast::Stmt) -> ir::Stmt {
-    match stmt {
-        typed_ast::Stmt::Alloc(ty) => ir::Stmt::Alloc(concrete_ty(ty)),
-        typed_ast::Stmt::Assign { ref_expr, expr, ty } => ir::Stmt::Assign {
-            ref_expr: lower_ref_expr(ref_expr),
+// allocations are hoisted into the function's entry block
+fn lower_stmt(stmt: &typed_ast::Stmt, temps: &mut Temps) -> Option<ir::Stmt> {
+    temps.used = 0;
+    Some(match stmt {
+        typed_ast::Stmt::Alloc(..) => return None,
+        typed_ast::Stmt::Assign {
+            ref_expr, expr, ty, ..
+        } => ir::Stmt::Assign {
+            ref_expr: lower_ref_expr(ref_expr, temps),
             ty: concrete_ty(ty),
-            expr: lower_expr(expr),
+            expr: lower_expr(expr, temps),
         },
         typed_ast::Stmt::FuncCall(func_call) => {
-            let args = func_call.args.iter().map(|expr| lower_expr(expr)).collect();
+            let args = func_call.args.iter().map(|expr| lower_expr(expr, temps)).collect();
             ir::Stmt::FuncCall(ir::FuncCall {
                 name: func_call.name,
                 args,
             })
         }
-    }
+        typed_ast::Stmt::Discard(expr) => ir::Stmt::Discard(lower_expr(expr, temps)),
+        typed_ast::Stmt::Assert { expr, span } => ir::Stmt::Assert {
+            expr: lower_expr(expr, temps),
+            span: *span,
+        },
+    })
 }
 
-fn lower_ref_expr(ref_expr: &typed_ast::RefExpr) -> ir::RefExpr {
+fn lower_ref_expr(ref_expr: &typed_ast::RefExpr, temps: &mut Temps) -> ir::RefExpr {
     match ref_expr {
         typed_ast::RefExpr::Variable(var) => ir::RefExpr::Variable(*var),
         typed_ast::RefExpr::Field { ref_expr, name, ty } => {
-            let ref_expr = Box::new(lower_ref_expr(ref_expr));
+            let ref_expr = Box::new(lower_ref_expr(ref_expr, temps));
             let fields = concrete_struct(ty);
             ir::RefExpr::Field {
                 ref_expr,
@@ -93,50 +145,94 @@ fn lower_ref_expr(ref_expr: &typed_ast::RefExpr) -> ir::RefExpr {
                 name: *name,
             }
         }
-        typed_ast::RefExpr::Deref(expr) => {
-            let expr = Box::new(lower_expr(expr));
-            ir::RefExpr::Deref(expr)
+        typed_ast::RefExpr::Deref(expr, span) => {
+            let expr = Box::new(lower_expr(expr, temps));
+            ir::RefExpr::Deref(expr, *span)
         }
     }
 }
 
-fn lower_expr(expr: &typed_ast::Expr) -> ir::Expr {
+fn lower_expr(expr: &typed_ast::Expr, temps: &mut Temps) -> ir::Expr {
     match expr {
         typed_ast::Expr::Int(value) => ir::Expr::Int(*value),
         typed_ast::Expr::Bool(value) => ir::Expr::Bool(*value),
+        typed_ast::Expr::Null => ir::Expr::Int(0),
+        typed_ast::Expr::Unwrap { expr, span } => ir::Expr::Unwrap {
+            expr: Box::new(lower_expr(expr, temps)),
+            span: *span,
+        },
+        typed_ast::Expr::IsNull(expr) => ir::Expr::IsNull(Box::new(lower_expr(expr, temps))),
         typed_ast::Expr::Binary {
             left,
             right,
             ty,
             op,
+            ..
         } => ir::Expr::Binary {
-            left: Box::new(lower_expr(left)),
-            right: Box::new(lower_expr(right)),
+            left: Box::new(lower_expr(left, temps)),
+            right: Box::new(lower_expr(right, temps)),
             ty: concrete_int(ty),
             op: *op,
         },
-        typed_ast::Expr::Load { var, ty } => ir::Expr::Load {
+        typed_ast::Expr::Intrinsic { intrinsic, arg, ty } => ir::Expr::Intrinsic {
+            intrinsic: *intrinsic,
+            arg: Box::new(lower_expr(arg, temps)),
+            ty: concrete_int(ty),
+        },
+        typed_ast::Expr::Atomic { op, args, ty, span } => ir::Expr::Atomic {
+            op: *op,
+            args: args.iter().map(|arg| lower_expr(arg, temps)).collect(),
+            ty: concrete_int(ty),
+            span: *span,
+        },
+        typed_ast::Expr::BitNot { expr, ty } => ir::Expr::BitNot {
+            expr: Box::new(lower_expr(expr, temps)),
+            ty: concrete_int(ty),
+        },
+        typed_ast::Expr::PtrOffset {
+            ptr,
+            offset,
+            ty,
+            offset_ty,
+            op,
+        } => ir::Expr::PtrOffset {
+            ptr: Box::new(lower_expr(ptr, temps)),
+            offset: Box::new(lower_expr(offset, temps)),
+            ty: concrete_ty(ty),
+            offset_ty: concrete_int(offset_ty),
+            op: *op,
+        },
+        typed_ast::Expr::Load { var, ty, .. } => ir::Expr::Load {
             var: *var,
             ty: concrete_ty(ty),
         },
-        typed_ast::Expr::Ref(ref_expr) => ir::Expr::Ref(lower_ref_expr(ref_expr)),
-        typed_ast::Expr::Deref { expr, ty } => ir::Expr::Deref {
-            expr: Box::new(lower_expr(expr)),
+        typed_ast::Expr::Ref(ref_expr) => ir::Expr::Ref(lower_ref_expr(ref_expr, temps)),
+        typed_ast::Expr::Deref { expr, ty, span } => ir::Expr::Deref {
+            expr: Box::new(lower_expr(expr, temps)),
             ty: concrete_ty(ty),
+            span: *span,
         },
-        typed_ast::Expr::FuncCall(func_call) => ir::Expr::FuncCall(lower_func_call(func_call)),
+        typed_ast::Expr::FuncCall(func_call) => ir::Expr::FuncCall(lower_func_call(func_call, temps)),
         typed_ast::Expr::InitStruct(values) => {
+            let fields = values
+                .iter()
+                .map(|value| ir::StructField {
+                    name: value.name,
+                    ty: concrete_ty(&value.ty),
+                })
+                .collect();
+            let temp = temps.alloc(ir::Ty::Struct(fields));
             let values = values
                 .iter()
                 .map(|value| ir::StructValue {
-                    expr: lower_expr(&value.expr),
+                    expr: lower_expr(&value.expr, temps),
                     ty: concrete_ty(&value.ty),
                 })
                 .collect();
-            ir::Expr::InitStruct(values)
+            ir::Expr::InitStruct { values, temp }
         }
         typed_ast::Expr::Field { expr, name, ty } => {
-            let expr = Box::new(lower_expr(expr));
+            let expr = Box::new(lower_expr(expr, temps));
             let fields = concrete_struct(ty);
             ir::Expr::Field {
                 expr,
@@ -147,8 +243,8 @@ fn lower_expr(expr: &typed_ast::Expr) -> ir::Expr {
     }
 }
 
-fn lower_func_call(func_call: &typed_ast::FuncCall) -> ir::FuncCall {
-    let args = func_call.args.iter().map(|expr| lower_expr(expr)).collect();
+fn lower_func_call(func_call: &typed_ast::FuncCall, temps: &mut Temps) -> ir::FuncCall {
+    let args = func_call.args.iter().map(|expr| lower_expr(expr, temps)).collect();
     ir::FuncCall {
         name: func_call.name,
         args,
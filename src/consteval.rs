@@ -0,0 +1,222 @@
+#![deny(clippy::unwrap_used, clippy::expect_used, clippy::panic)]
+
+use std::collections::HashMap;
+
+use crate::{
+    ast::{Const, Expr, InfixOp, Int, PrefixOp, StaticAssert, Ty},
+    codes::Code,
+    decls::Decls,
+    diagnostic::Diagnostic,
+    symbols::{Symbol, Symbols},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Value {
+    Int(i64),
+    Bool(bool),
+}
+
+pub struct Consts {
+    values: HashMap<Symbol, Value>,
+}
+
+struct Evaluator<'a> {
+    decls: &'a Decls<'a>,
+    symbols: &'a Symbols<'a>,
+    values: HashMap<Symbol, Option<Value>>,
+    stack: Vec<Symbol>,
+    errors: Vec<Diagnostic>,
+}
+
+impl Consts {
+    pub fn get(&self, name: Symbol) -> Option<Value> {
+        self.values.get(&name).copied()
+    }
+}
+
+pub fn eval_consts<'a>(
+    decls: &'a Decls<'a>,
+    symbols: &'a Symbols<'a>,
+) -> (Consts, Vec<Diagnostic>) {
+    let mut evaluator = Evaluator {
+        decls,
+        symbols,
+        values: HashMap::new(),
+        stack: vec![],
+        errors: vec![],
+    };
+    for const_decl in decls.program.const_iter() {
+        evaluator.eval_const(const_decl);
+    }
+    for static_assert in decls.program.static_assert_iter() {
+        evaluator.eval_static_assert(static_assert);
+    }
+    let values = evaluator
+        .values
+        .into_iter()
+        .filter_map(|(name, value)| Some((name, value?)))
+        .collect();
+    (Consts { values }, evaluator.errors)
+}
+
+fn int_range(int: &Int) -> (i64, i64) {
+    match int {
+        Int::I8 => (i8::MIN as i64, i8::MAX as i64),
+        Int::I16 => (i16::MIN as i64, i16::MAX as i64),
+        Int::I32 => (i32::MIN as i64, i32::MAX as i64),
+        Int::U8 => (0, u8::MAX as i64),
+        Int::U16 => (0, u16::MAX as i64),
+        Int::U32 => (0, u32::MAX as i64),
+        // constants are evaluated as an i64
+        Int::Usize => (0, i64::MAX),
+    }
+}
+
+impl<'a> Evaluator<'a> {
+    fn eval_const(&mut self, const_decl: &Const) -> Option<Value> {
+        if let Some(value) = self.values.get(&const_decl.name) {
+            return *value;
+        }
+        let name = self.symbols.get_str(const_decl.name);
+        if let Some(start) = self.stack.iter().position(|name| *name == const_decl.name) {
+            let path: Vec<_> = self.stack[start..]
+                .iter()
+                .chain([&const_decl.name])
+                .map(|name| format!("`{}`", self.symbols.get_str(*name)))
+                .collect();
+            self.errors.push(
+                Diagnostic::new(
+                    Code::ConstCycle,
+                    const_decl.span,
+                    format!("constant `{}` depends on itself", name),
+                )
+                .with_note(format!("the cycle is {}", path.join(" -> "))),
+            );
+            return None;
+        }
+        self.stack.push(const_decl.name);
+        let value = self.eval(&const_decl.expr);
+        self.stack.pop();
+        let value = match value.map(|value| check_ty(value, &const_decl.ty)) {
+            Ok(Ok(value)) => Some(value),
+            Ok(Err((message, note))) => {
+                self.errors.push(
+                    Diagnostic::new(
+                        Code::ConstType,
+                        const_decl.span,
+                        format!("constant `{}` {}", name, message),
+                    )
+                    .with_note(note),
+                );
+                None
+            }
+            Err(Some(message)) => {
+                self.errors
+                    .push(Diagnostic::new(Code::ConstEval, const_decl.span, message));
+                None
+            }
+            // already reported on the constant it depends on
+            Err(None) => None,
+        };
+        self.values.insert(const_decl.name, value);
+        value
+    }
+    fn eval_static_assert(&mut self, static_assert: &StaticAssert) {
+        let (code, message) = match self.eval(&static_assert.cond) {
+            Ok(Value::Bool(true)) | Err(None) => return,
+            Ok(Value::Bool(false)) => (
+                Code::StaticAssertFailed,
+                format!("static assertion failed: {}", static_assert.message),
+            ),
+            Ok(Value::Int(_)) => (
+                Code::ConstType,
+                "static assertion condition must be a `bool`".to_string(),
+            ),
+            Err(Some(message)) => (Code::ConstEval, message),
+        };
+        self.errors
+            .push(Diagnostic::new(code, static_assert.span, message));
+    }
+    fn eval(&mut self, expr: &Expr) -> Result<Value, Option<String>> {
+        eval_with(expr, &mut |name| {
+            let const_decl = self
+                .decls
+                .const_decl(name)
+                .ok_or_else(|| format!("`{}` is not a constant", self.symbols.get_str(name)))?;
+            self.eval_const(const_decl).ok_or(None)
+        })
+    }
+}
+
+// Evaluates an expression in a function body, returning `None` if its value isn't
+// known at compile time. Locals may shadow constants.
+pub fn eval_expr(expr: &Expr, consts: &Consts, is_local: impl Fn(Symbol) -> bool) -> Option<Value> {
+    eval_with(expr, &mut |name| {
+        if is_local(name) {
+            return Err(None);
+        }
+        consts.get(name).ok_or(None)
+    })
+    .ok()
+}
+
+type Lookup<'l> = dyn FnMut(Symbol) -> Result<Value, Option<String>> + 'l;
+
+fn eval_with(expr: &Expr, lookup: &mut Lookup) -> Result<Value, Option<String>> {
+    Ok(match expr {
+        Expr::Integer(value, _) => Value::Int(*value),
+        Expr::Char(value) => Value::Int(*value as i64),
+        Expr::Bool(value) => Value::Bool(*value),
+        Expr::Ident { name, .. } => lookup(*name)?,
+        Expr::Infix {
+            left, right, op, ..
+        } => {
+            let left = eval_int(left, lookup)?;
+            let right = eval_int(right, lookup)?;
+            let value = match op {
+                InfixOp::Add => left.checked_add(right),
+                InfixOp::Subtract => left.checked_sub(right),
+                InfixOp::Multiply => left.checked_mul(right),
+                InfixOp::Divide if right == 0 => Err("division by zero".to_string())?,
+                InfixOp::Divide => left.checked_div(right),
+                InfixOp::LessThan => return Ok(Value::Bool(left < right)),
+                InfixOp::GreaterThan => return Ok(Value::Bool(left > right)),
+            };
+            Value::Int(value.ok_or("arithmetic overflow".to_string())?)
+        }
+        Expr::Prefix {
+            op: PrefixOp::BitNot,
+            expr,
+        } => Value::Int(!eval_int(expr, lookup)?),
+        _ => Err("expression cannot be evaluated at compile time".to_string())?,
+    })
+}
+
+fn eval_int(expr: &Expr, lookup: &mut Lookup) -> Result<i64, Option<String>> {
+    match eval_with(expr, lookup)? {
+        Value::Int(value) => Ok(value),
+        Value::Bool(_) => Err(Some("expected an integer but found `bool`".to_string())),
+    }
+}
+
+fn check_ty(value: Value, ty: &Ty) -> Result<Value, (&'static str, String)> {
+    match (value, ty) {
+        (Value::Bool(_), Ty::Bool) => Ok(value),
+        (Value::Int(int), Ty::Int(int_ty)) => {
+            let (min, max) = int_range(int_ty);
+            if int < min || int > max {
+                Err((
+                    "is out of range",
+                    format!(
+                        "its value is {} but its type allows {} to {}",
+                        int, min, max
+                    ),
+                ))
+            } else {
+                Ok(value)
+            }
+        }
+        (Value::Bool(_), _) => Err(("has the wrong type", "its value is a `bool`".to_string())),
+        (Value::Int(_), _) => Err(("has the wrong type", "its value is an integer".to_string())),
+    }
+}
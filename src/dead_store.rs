@@ -0,0 +1,32 @@
+#![deny(clippy::unwrap_used, clippy::expect_used, clippy::panic)]
+
+use crate::{
+    codes::Code,
+    diagnostic::Diagnostic,
+    lexer::Span,
+    liveness,
+    symbols::{Symbol, Symbols},
+    typed_ast::Func,
+};
+
+pub fn check_func(func: &Func, vars: &[(Symbol, Span)], symbols: &Symbols) -> Vec<Diagnostic> {
+    let liveness = liveness::analyse(func, vars.len());
+    let mut errors = vec![];
+    for id in liveness::reachable(func) {
+        let block = &func.blocks[id];
+        let live = liveness.live_out(block);
+        liveness::walk_block(block, live, &mut |var, span, whole, live| {
+            let index = var.0 as usize;
+            if whole && !live[index] && !liveness.escaped[index] {
+                let name = symbols.get_str(vars[index].0);
+                errors.push(Diagnostic::new(
+                    Code::DeadStore,
+                    span,
+                    format!("value assigned to `{}` is never read", name),
+                ));
+            }
+        });
+    }
+    errors.sort_by_key(|err| err.span.start);
+    errors
+}
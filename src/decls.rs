@@ -0,0 +1,222 @@
+#![deny(clippy::unwrap_used, clippy::expect_used, clippy::panic)]
+
+use std::collections::HashMap;
+
+use crate::{
+    ast::{Const, Func, Int, Program, Struct, Ty},
+    codes::Code,
+    diagnostic::Diagnostic,
+    lexer::Span,
+    symbols::{Symbol, Symbols},
+};
+
+pub struct Decls<'a> {
+    pub program: &'a Program,
+    funcs: HashMap<Symbol, &'a Func>,
+    structs: HashMap<Symbol, &'a Struct>,
+    consts: HashMap<Symbol, &'a Const>,
+}
+
+impl<'a> Decls<'a> {
+    pub fn collect(program: &'a Program, symbols: &Symbols) -> (Decls<'a>, Vec<Diagnostic>) {
+        let mut errors = vec![];
+        let mut funcs: HashMap<_, &Func> = HashMap::new();
+        for func in program.func_iter() {
+            match funcs.get(&func.name) {
+                Some(first) => errors.push(duplicate(
+                    "function",
+                    symbols.get_str(func.name),
+                    func.span,
+                    first.span,
+                )),
+                None => _ = funcs.insert(func.name, func),
+            }
+            if symbols.get_str(func.name) == "main" {
+                if let Some(err) = check_main(func) {
+                    errors.push(err);
+                }
+            }
+            if let Some(harness) = func.harness {
+                if !func.params.is_empty() || func.returns.is_some() || func.body.is_none() {
+                    let message = format!(
+                        "a {} must have a body, no parameters and no return type",
+                        harness.name()
+                    );
+                    errors.push(
+                        Diagnostic::new(Code::InvalidHarness, func.span, message)
+                            .with_note("it's run by a generated harness".to_string()),
+                    );
+                }
+            }
+            let mut params: HashMap<_, Span> = HashMap::new();
+            for param in &func.params {
+                match params.get(&param.name) {
+                    Some(first) => errors.push(duplicate(
+                        "parameter",
+                        symbols.get_str(param.name),
+                        param.span,
+                        *first,
+                    )),
+                    None => _ = params.insert(param.name, param.span),
+                }
+            }
+        }
+        let mut structs: HashMap<_, &Struct> = HashMap::new();
+        for struct_decl in program.struct_iter() {
+            match structs.get(&struct_decl.name) {
+                Some(first) => errors.push(duplicate(
+                    "struct",
+                    symbols.get_str(struct_decl.name),
+                    struct_decl.span,
+                    first.span,
+                )),
+                None => _ = structs.insert(struct_decl.name, struct_decl),
+            }
+            let mut fields: HashMap<_, Span> = HashMap::new();
+            for field in &struct_decl.fields {
+                match fields.get(&field.name) {
+                    Some(first) => errors.push(duplicate(
+                        "field",
+                        symbols.get_str(field.name),
+                        field.span,
+                        *first,
+                    )),
+                    None => _ = fields.insert(field.name, field.span),
+                }
+            }
+        }
+        for struct_decl in program
+            .struct_iter()
+            .filter(|struct_decl| struct_decl.repr_c)
+        {
+            for field in &struct_decl.fields {
+                let field_struct = match &field.ty {
+                    Ty::Struct(name, _) => structs.get(name),
+                    _ => None,
+                };
+                if let Some(field_struct) = field_struct.filter(|s| !s.repr_c) {
+                    let name = symbols.get_str(field_struct.name);
+                    errors.push(
+                        Diagnostic::new(
+                            Code::ReprC,
+                            field.span,
+                            format!(
+                                "`{}` can't have a C layout as `{}` doesn't",
+                                symbols.get_str(struct_decl.name),
+                                name
+                            ),
+                        )
+                        .with_span_note(
+                            field_struct.span,
+                            format!("add `@repr(c)` to `{}` here", name),
+                        ),
+                    );
+                }
+            }
+        }
+        let mut consts: HashMap<_, &Const> = HashMap::new();
+        for const_decl in program.const_iter() {
+            match consts.get(&const_decl.name) {
+                Some(first) => errors.push(duplicate(
+                    "constant",
+                    symbols.get_str(const_decl.name),
+                    const_decl.span,
+                    first.span,
+                )),
+                None => _ = consts.insert(const_decl.name, const_decl),
+            }
+        }
+        let decls = Decls {
+            program,
+            funcs,
+            structs,
+            consts,
+        };
+        let mut tys = vec![];
+        for struct_decl in program.struct_iter() {
+            tys.extend(
+                struct_decl
+                    .fields
+                    .iter()
+                    .map(|field| (&field.ty, field.span)),
+            );
+        }
+        for func in program.func_iter() {
+            tys.extend(func.params.iter().map(|param| (&param.ty, param.span)));
+            tys.extend(func.returns.iter().map(|ty| (ty, func.span)));
+        }
+        tys.extend(
+            program
+                .const_iter()
+                .map(|const_decl| (&const_decl.ty, const_decl.span)),
+        );
+        errors.extend(
+            tys.into_iter()
+                .filter_map(|(ty, span)| decls.check_ty(ty, span, symbols)),
+        );
+        (decls, errors)
+    }
+    pub fn check_ty(&self, ty: &Ty, span: Span, symbols: &Symbols) -> Option<Diagnostic> {
+        match ty {
+            Ty::Struct(name, _) if self.struct_decl(*name).is_none() => Some(Diagnostic::new(
+                Code::UnknownType,
+                span,
+                format!("unknown type `{}`", symbols.get_str(*name)),
+            )),
+            Ty::Ref(ty) | Ty::NullableRef(ty) => self.check_ty(ty, span, symbols),
+            _ => None,
+        }
+    }
+    pub fn func(&self, name: Symbol) -> Option<&'a Func> {
+        self.funcs.get(&name).copied()
+    }
+    pub fn struct_decl(&self, name: Symbol) -> Option<&'a Struct> {
+        self.structs.get(&name).copied()
+    }
+    pub fn const_decl(&self, name: Symbol) -> Option<&'a Const> {
+        self.consts.get(&name).copied()
+    }
+}
+
+fn check_main(func: &Func) -> Option<Diagnostic> {
+    let takes_args = match &func.params[..] {
+        [] => true,
+        [argc, argv] => match (&argc.ty, &argv.ty) {
+            (Ty::Int(Int::I32), Ty::Ref(argv)) => {
+                matches!(&**argv, Ty::Ref(arg) if matches!(**arg, Ty::Int(Int::U8)))
+            }
+            _ => false,
+        },
+        _ => false,
+    };
+    if !takes_args {
+        return Some(
+            Diagnostic::new(
+                Code::InvalidMain,
+                func.span,
+                "`main` has the wrong parameters".to_string(),
+            )
+            .with_note("expected `main()` or `main(argc: i32, argv: **u8)`".to_string()),
+        );
+    }
+    match func.returns {
+        None | Some(Ty::Int(Int::I32)) => None,
+        Some(_) => Some(
+            Diagnostic::new(
+                Code::InvalidMain,
+                func.span,
+                "`main` must return `i32` or nothing".to_string(),
+            )
+            .with_note("the returned value is used as the exit status".to_string()),
+        ),
+    }
+}
+
+fn duplicate(kind: &str, name: &str, span: Span, first: Span) -> Diagnostic {
+    Diagnostic::new(
+        Code::Duplicate,
+        span,
+        format!("{} `{}` is defined more than once", kind, name),
+    )
+    .with_span_note(first, format!("`{}` is first defined here", name))
+}
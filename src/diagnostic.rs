@@ -0,0 +1,275 @@
+#![deny(clippy::unwrap_used, clippy::expect_used, clippy::panic)]
+
+use std::fmt;
+
+use crate::{codes::Code, lexer::Span};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub code: Code,
+    pub severity: Severity,
+    pub span: Span,
+    pub message: String,
+    pub notes: Vec<Note>,
+    pub suggestions: Vec<Suggestion>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Note {
+    pub span: Option<Span>,
+    pub message: String,
+}
+
+// Replacing the text at `span` with `replacement` fixes the problem.
+#[derive(Debug, Clone)]
+pub struct Suggestion {
+    pub span: Span,
+    pub replacement: String,
+    pub message: String,
+}
+
+pub struct DiagnosticDisplay<'a> {
+    diagnostic: &'a Diagnostic,
+    source: &'a str,
+    color: bool,
+}
+
+// ANSI styles
+const BOLD: &str = "1";
+const BLUE: &str = "1;34";
+const CYAN: &str = "1;36";
+const GREEN: &str = "1;32";
+
+impl Severity {
+    pub fn str(&self) -> &'static str {
+        match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        }
+    }
+    fn style(&self) -> &'static str {
+        match self {
+            Severity::Error => "1;31",
+            Severity::Warning => "1;33",
+        }
+    }
+}
+
+impl Diagnostic {
+    pub fn new(code: Code, span: Span, message: String) -> Diagnostic {
+        Diagnostic {
+            code,
+            severity: code.severity(),
+            span,
+            message,
+            notes: vec![],
+            suggestions: vec![],
+        }
+    }
+    pub fn with_note(mut self, message: String) -> Diagnostic {
+        self.notes.push(Note {
+            span: None,
+            message,
+        });
+        self
+    }
+    pub fn with_span_note(mut self, span: Span, message: String) -> Diagnostic {
+        self.notes.push(Note {
+            span: Some(span),
+            message,
+        });
+        self
+    }
+    pub fn with_suggestion(mut self, span: Span, replacement: &str, message: String) -> Diagnostic {
+        self.suggestions.push(Suggestion {
+            span,
+            replacement: replacement.to_string(),
+            message,
+        });
+        self
+    }
+    // Moves every span, for a diagnostic found in code that was copied elsewhere to
+    // be checked.
+    pub fn map_spans(mut self, f: impl Fn(Span) -> Span) -> Diagnostic {
+        self.span = f(self.span);
+        for note in &mut self.notes {
+            note.span = note.span.map(&f);
+        }
+        for suggestion in &mut self.suggestions {
+            suggestion.span = f(suggestion.span);
+        }
+        self
+    }
+    pub fn display<'a>(&'a self, source: &'a str) -> DiagnosticDisplay<'a> {
+        DiagnosticDisplay {
+            diagnostic: self,
+            source,
+            color: false,
+        }
+    }
+    // One JSON object on a single line, so a stream of diagnostics can be read line by line.
+    pub fn json(&self, file: Option<&str>, source: &str) -> String {
+        let mut spans = vec![json_span(self.span, None, source)];
+        let mut notes = vec![];
+        let suggestions: Vec<_> = self
+            .suggestions
+            .iter()
+            .map(|suggestion| json_suggestion(suggestion, source))
+            .collect();
+        for note in &self.notes {
+            match note.span {
+                Some(span) => spans.push(json_span(span, Some(&note.message), source)),
+                None => notes.push(json_str(&note.message)),
+            }
+        }
+        format!(
+            "{{\"severity\":\"{}\",\"code\":\"{}\",\"message\":{},\"file\":{},\"spans\":[{}],\"notes\":[{}],\"suggestions\":[{}]}}",
+            self.severity.str(),
+            self.code.str(),
+            json_str(&self.message),
+            file.map(json_str).unwrap_or("null".to_string()),
+            spans.join(","),
+            notes.join(","),
+            suggestions.join(",")
+        )
+    }
+}
+
+fn json_span(span: Span, label: Option<&str>, source: &str) -> String {
+    let pos = span.pos(source);
+    format!(
+        "{{\"start\":{},\"end\":{},\"line\":{},\"column\":{},\"primary\":{},\"label\":{}}}",
+        span.start,
+        span.end,
+        pos.line,
+        pos.column,
+        label.is_none(),
+        label.map(json_str).unwrap_or("null".to_string())
+    )
+}
+
+fn json_suggestion(suggestion: &Suggestion, source: &str) -> String {
+    let pos = suggestion.span.pos(source);
+    format!(
+        "{{\"start\":{},\"end\":{},\"line\":{},\"column\":{},\"replacement\":{},\"message\":{}}}",
+        suggestion.span.start,
+        suggestion.span.end,
+        pos.line,
+        pos.column,
+        json_str(&suggestion.replacement),
+        json_str(&suggestion.message)
+    )
+}
+
+fn json_str(str: &str) -> String {
+    let mut json = "\"".to_string();
+    for ch in str.chars() {
+        match ch {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\t' => json.push_str("\\t"),
+            ch if (ch as u32) < 0x20 => json.push_str(&format!("\\u{:04x}", ch as u32)),
+            ch => json.push(ch),
+        }
+    }
+    json.push('"');
+    json
+}
+
+impl<'a> DiagnosticDisplay<'a> {
+    pub fn color(self, color: bool) -> DiagnosticDisplay<'a> {
+        DiagnosticDisplay { color, ..self }
+    }
+    fn paint(&self, style: &str, text: &str) -> String {
+        if self.color {
+            format!("\x1b[{}m{}\x1b[0m", style, text)
+        } else {
+            text.to_string()
+        }
+    }
+    // Prints the line the span starts on, underlining the part of it the span covers.
+    fn snippet(&self, f: &mut fmt::Formatter<'_>, span: Span, style: &str) -> fmt::Result {
+        let pos = span.pos(self.source);
+        let line = match self.source.lines().nth(pos.line as usize - 1) {
+            Some(line) => line,
+            None => return Ok(()),
+        };
+        let number = pos.line.to_string();
+        let gutter = " ".repeat(number.len());
+        let start = pos.column as usize - 1;
+        // tabs are kept so the underline stays aligned with the code above it
+        let indent: String = line
+            .chars()
+            .take(start)
+            .map(|ch| if ch == '\t' { '\t' } else { ' ' })
+            .collect();
+        let len = self.source[span.start..span.end]
+            .lines()
+            .next()
+            .map_or(0, |text| text.chars().count());
+        let underline = "^".repeat(len.max(1));
+        write!(
+            f,
+            "\n {} {} {}\n {} {} {}{}",
+            self.paint(BLUE, &number),
+            self.paint(BLUE, "|"),
+            line,
+            gutter,
+            self.paint(BLUE, "|"),
+            indent,
+            self.paint(style, &underline)
+        )
+    }
+}
+
+impl<'a> fmt::Display for DiagnosticDisplay<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let severity = self.diagnostic.severity;
+        let pos = self.diagnostic.span.pos(self.source);
+        let header = format!(
+            " on line {} column {}: {}",
+            pos.line, pos.column, self.diagnostic.message
+        );
+        let label = format!("{}[{}]", severity.str(), self.diagnostic.code.str());
+        write!(
+            f,
+            "{}{}",
+            self.paint(severity.style(), &label),
+            self.paint(BOLD, &header)
+        )?;
+        self.snippet(f, self.diagnostic.span, severity.style())?;
+        for note in &self.diagnostic.notes {
+            let note_label = self.paint(CYAN, "note");
+            match note.span {
+                Some(span) => {
+                    let pos = span.pos(self.source);
+                    write!(
+                        f,
+                        "\n  {} on line {} column {}: {}",
+                        note_label, pos.line, pos.column, note.message
+                    )?;
+                }
+                None => write!(f, "\n  {}: {}", note_label, note.message)?,
+            }
+        }
+        for suggestion in &self.diagnostic.suggestions {
+            let pos = suggestion.span.pos(self.source);
+            write!(
+                f,
+                "\n  {} on line {} column {}: {}",
+                self.paint(GREEN, "help"),
+                pos.line,
+                pos.column,
+                suggestion.message
+            )?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,82 @@
+#![deny(clippy::unwrap_used, clippy::expect_used, clippy::panic)]
+
+use std::fmt::Write;
+
+use crate::outline::{Item, ItemKind};
+
+// The declaration as it would be written, e.g. `func area(w: i32, h: i32) i32`.
+fn signature(item: &Item, path: &str) -> String {
+    match item.kind.keyword() {
+        Some(keyword) => format!("{} {}{}", keyword, path, item.detail),
+        None => format!("{}{}", path, item.detail),
+    }
+}
+
+// Items in a module are listed under their full path rather than nested, so every
+// function and struct gets a heading of its own.
+fn flatten<'a>(items: &'a [Item], prefix: &str, flat: &mut Vec<(String, &'a Item)>) {
+    for item in items {
+        let path = format!("{}{}", prefix, item.name);
+        match item.kind {
+            ItemKind::Mod => flatten(&item.children, &format!("{}::", path), flat),
+            _ => flat.push((path, item)),
+        }
+    }
+}
+
+pub fn markdown(title: &str, items: &[Item]) -> String {
+    let mut flat = vec![];
+    flatten(items, "", &mut flat);
+    let mut output = format!("# {}\n", title);
+    for (path, item) in flat {
+        _ = write!(output, "\n## `{}`\n", signature(item, &path));
+        if let Some(doc) = &item.doc {
+            _ = write!(output, "\n{}\n", doc);
+        }
+        if !item.children.is_empty() {
+            output.push('\n');
+        }
+        for field in &item.children {
+            _ = writeln!(output, "- `{}`", signature(field, &field.name));
+        }
+    }
+    output
+}
+
+pub fn html(title: &str, items: &[Item]) -> String {
+    let mut flat = vec![];
+    flatten(items, "", &mut flat);
+    let title = escape(title);
+    let mut output = String::from("<!DOCTYPE html>\n<html>\n<head>\n");
+    _ = writeln!(output, "<meta charset=\"utf-8\">\n<title>{}</title>", title);
+    _ = writeln!(output, "</head>\n<body>\n<h1>{}</h1>", title);
+    for (path, item) in flat {
+        _ = writeln!(
+            output,
+            "<h2 id=\"{}\"><code>{}</code></h2>",
+            escape(&path),
+            escape(&signature(item, &path))
+        );
+        // blank lines separate paragraphs, as in markdown
+        for paragraph in item.doc.iter().flat_map(|doc| doc.split("\n\n")) {
+            _ = writeln!(output, "<p>{}</p>", escape(paragraph.trim()));
+        }
+        if !item.children.is_empty() {
+            output.push_str("<ul>\n");
+            for field in &item.children {
+                let field = escape(&signature(field, &field.name));
+                _ = writeln!(output, "<li><code>{}</code></li>", field);
+            }
+            output.push_str("</ul>\n");
+        }
+    }
+    output.push_str("</body>\n</html>\n");
+    output
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
@@ -0,0 +1,119 @@
+#![deny(clippy::unwrap_used, clippy::expect_used, clippy::panic)]
+
+use std::mem;
+
+use crate::{
+    diagnostic::{Diagnostic, Severity},
+    frontend,
+    lexer::Span,
+    profile::Profiler,
+};
+
+// A fenced code block in a `///` comment. Blocks marked as another language, like
+// ```text, aren't examples.
+pub struct Example {
+    pub code: String,
+    // where the opening fence is in the module
+    pub span: Span,
+    // where each line of the code starts, and where that line is in the module
+    lines: Vec<(usize, usize)>,
+}
+
+// The block a line of a doc comment is in.
+enum Fence {
+    None,
+    Other,
+    Example(Example),
+}
+
+pub fn examples(source: &str) -> Vec<Example> {
+    let mut examples = vec![];
+    let mut fence = Fence::None;
+    let mut offset = 0;
+    for line in source.split_inclusive('\n') {
+        let start = offset;
+        offset += line.len();
+        let text = line.trim_end_matches(['\n', '\r']);
+        let comment = match text.trim_start().strip_prefix("///") {
+            Some(comment) => comment.strip_prefix(' ').unwrap_or(comment),
+            None => {
+                // an unclosed block ends with the comment
+                fence = Fence::None;
+                continue;
+            }
+        };
+        let comment_start = start + text.len() - comment.len();
+        match (&mut fence, comment.trim().strip_prefix("```")) {
+            (Fence::None, Some(lang)) if lang.is_empty() || lang == "pl" => {
+                fence = Fence::Example(Example {
+                    code: String::new(),
+                    span: Span {
+                        start: comment_start,
+                        end: start + text.len(),
+                    },
+                    lines: vec![],
+                })
+            }
+            (Fence::None, Some(_)) => fence = Fence::Other,
+            (_, Some(_)) => {
+                if let Fence::Example(example) = mem::replace(&mut fence, Fence::None) {
+                    examples.push(example);
+                }
+            }
+            (Fence::Example(example), None) => {
+                example.lines.push((example.code.len(), comment_start));
+                example.code.push_str(comment);
+                example.code.push('\n');
+            }
+            _ => {}
+        }
+    }
+    examples
+}
+
+impl Example {
+    // The example is checked as the body of a function added to the end of its
+    // module, so it can use anything the module declares. Any errors are moved back
+    // to where the example is in the module.
+    pub fn check(
+        &self,
+        source: &str,
+        prelude: Option<&str>,
+        profiler: &mut Profiler,
+    ) -> Vec<Diagnostic> {
+        let header = format!("{}\nfunc __doc_example() {{\n", source);
+        let wrapped = format!("{}{}}}\n", header, self.code);
+        let diagnostics = match frontend::check_source(&wrapped, prelude, profiler) {
+            Ok(_) => return vec![],
+            Err(diagnostics) => diagnostics,
+        };
+        let offset = |offset: usize| {
+            if offset <= source.len() {
+                return offset;
+            }
+            let offset = match offset.checked_sub(header.len()) {
+                Some(offset) if offset <= self.code.len() => offset,
+                // the function around the example
+                _ => return self.span.start,
+            };
+            let (code_start, start) = self
+                .lines
+                .iter()
+                .rev()
+                .find(|(code_start, _)| *code_start <= offset)
+                .copied()
+                .unwrap_or((0, self.span.start));
+            start + offset - code_start
+        };
+        diagnostics
+            .into_iter()
+            .filter(|diagnostic| diagnostic.severity == Severity::Error)
+            .map(|diagnostic| {
+                diagnostic.map_spans(|span| Span {
+                    start: offset(span.start),
+                    end: offset(span.end),
+                })
+            })
+            .collect()
+    }
+}
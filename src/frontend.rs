@@ -0,0 +1,453 @@
+#![deny(clippy::unwrap_used, clippy::expect_used, clippy::panic)]
+
+use crate::{
+    ast::{Decl, Mod, Program},
+    codes::Code,
+    compile_ast, consteval,
+    decls::Decls,
+    diagnostic::Diagnostic,
+    lexer::{self, Span},
+    parser,
+    profile::Profiler,
+    resolve::{self, RefKind, Reference, Target},
+    symbols::Symbols,
+    ty::{self, ConcreteTy},
+    typed_ast,
+};
+
+pub struct CheckedModule<'s> {
+    pub program: Program,
+    pub symbols: Symbols<'s>,
+    pub funcs: Vec<typed_ast::Func>,
+    pub references: Vec<Reference>,
+    pub warnings: Vec<Diagnostic>,
+}
+
+pub struct TextEdit {
+    pub span: Span,
+    pub text: String,
+}
+
+impl<'s> CheckedModule<'s> {
+    pub fn decls(&self) -> Decls<'_> {
+        // the declarations have already been checked
+        Decls::collect(&self.program, &self.symbols).0
+    }
+    // The type of the innermost expression or variable declaration around `offset`.
+    pub fn type_at(&self, offset: usize) -> Option<ConcreteTy> {
+        self.funcs
+            .iter()
+            .flat_map(|func| &func.types)
+            .filter(|(span, _)| span.start <= offset && offset < span.end)
+            .min_by_key(|(span, _)| span.end - span.start)
+            .and_then(|(_, ty)| ty::concrete(ty))
+    }
+    // The declaration or variable named at `offset`.
+    pub fn target_at(&self, offset: usize) -> Option<Target> {
+        self.references
+            .iter()
+            .find(|reference| reference.span.start <= offset && offset < reference.span.end)
+            .map(|reference| reference.target)
+    }
+    pub fn references_of(&self, target: Target) -> Vec<Span> {
+        let mut spans: Vec<_> = self
+            .references
+            .iter()
+            .filter(|reference| reference.target == target)
+            .map(|reference| reference.span)
+            .collect();
+        spans.sort_by_key(|span| span.start);
+        spans
+    }
+    // Only something defined in this module can be renamed, as otherwise its
+    // definition would be left with the old name.
+    pub fn rename(&self, target: Target, new_name: &str) -> Vec<TextEdit> {
+        let references: Vec<_> = self
+            .references
+            .iter()
+            .filter(|reference| reference.target == target)
+            .collect();
+        if !references
+            .iter()
+            .any(|reference| reference.kind == RefKind::Def)
+        {
+            return vec![];
+        }
+        references
+            .into_iter()
+            .filter(|reference| !reference.aliased)
+            .map(|reference| TextEdit {
+                span: reference.span,
+                text: new_name.to_string(),
+            })
+            .collect()
+    }
+}
+
+// Lexes, parses and type checks a module. No input should make this panic, so any
+// problem with the source is reported as a diagnostic. If there are errors, they're
+// returned after any warnings found along the way. The declarations in the prelude
+// are added to the module as `mod prelude`, which names are looked up in last.
+pub fn check_source<'s>(
+    source: &'s str,
+    prelude: Option<&'s str>,
+    profiler: &mut Profiler,
+) -> Result<CheckedModule<'s>, Vec<Diagnostic>> {
+    let (tokens, lex_errors) = profiler.time("lex", || lexer::lex(source));
+    if !lex_errors.is_empty() {
+        return Err(lex_errors);
+    }
+    let (mut program, mut symbols, parse_errors) =
+        profiler.time("parse", || parser::parse(&tokens, Symbols::new()));
+    if !parse_errors.is_empty() {
+        return Err(parse_errors);
+    }
+    if let Some(prelude) = prelude {
+        // the prelude has already been checked by `check_prelude`
+        let (tokens, _) = lexer::lex(prelude);
+        let (prelude, prelude_symbols, _) = parser::parse(&tokens, symbols);
+        symbols = prelude_symbols;
+        program.decls.push(Decl::Mod(Mod {
+            name: symbols.get_symbol("prelude"),
+            decls: prelude.decls,
+            // the prelude isn't part of the source
+            span: Span { start: 0, end: 0 },
+        }));
+    }
+    let mut warnings = vec![];
+    let (program, references, mut errors) = profiler.time("resolve", || {
+        resolve::resolve(program, &mut symbols, &mut warnings)
+    });
+    let funcs = profiler.time("check", || {
+        if !errors.is_empty() {
+            return vec![];
+        }
+        let (decls, decl_errors) = Decls::collect(&program, &symbols);
+        errors = decl_errors;
+        if errors.is_empty() {
+            errors = compile_ast::check_struct_cycles(&decls, &symbols);
+        }
+        if !errors.is_empty() {
+            return vec![];
+        }
+        let (consts, const_errors) = consteval::eval_consts(&decls, &symbols);
+        if !const_errors.is_empty() {
+            errors = const_errors;
+            return vec![];
+        }
+        program
+            .func_iter()
+            .filter_map(|func_ast| {
+                match compile_ast::compile_func(func_ast, &decls, &consts, &symbols, &mut warnings)
+                {
+                    Ok(func_mir) => func_mir,
+                    Err(func_errors) => {
+                        errors.extend(func_errors);
+                        None
+                    }
+                }
+            })
+            .collect()
+    });
+    warnings.sort_by_key(|warning| warning.span.start);
+    if !errors.is_empty() {
+        warnings.extend(errors);
+        return Err(warnings);
+    }
+    Ok(CheckedModule {
+        program,
+        symbols,
+        funcs,
+        references,
+        warnings,
+    })
+}
+
+// A prelude is included in every module, so it can only declare functions. Otherwise
+// they would be defined once per module.
+pub fn check_prelude(source: &str, profiler: &mut Profiler) -> Vec<Diagnostic> {
+    let checked = match check_source(source, None, profiler) {
+        Ok(checked) => checked,
+        Err(diagnostics) => return diagnostics,
+    };
+    let mut diagnostics = checked.warnings;
+    for func in checked.program.func_iter() {
+        if func.body.is_some() {
+            let name = checked.symbols.get_str(func.name);
+            diagnostics.push(
+                Diagnostic::new(
+                    Code::PreludeBody,
+                    func.span,
+                    format!("`{}` can't have a body in a prelude", name),
+                )
+                .with_note("declare it here and define it in a source file".to_string()),
+            );
+        }
+    }
+    diagnostics
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diagnostic::Severity;
+
+    // The errors in a module, without the warnings.
+    fn errors(source: &str) -> Vec<Code> {
+        match check_source(source, None, &mut Profiler::new(false)) {
+            Ok(_) => vec![],
+            Err(diagnostics) => diagnostics
+                .iter()
+                .filter(|diagnostic| diagnostic.severity == Severity::Error)
+                .map(|diagnostic| diagnostic.code)
+                .collect(),
+        }
+    }
+
+    // The code of each warning in a module, with the source it points at.
+    fn warnings(source: &str) -> Vec<(Code, &str)> {
+        let diagnostics = match check_source(source, None, &mut Profiler::new(false)) {
+            Ok(checked) => checked.warnings,
+            Err(diagnostics) => diagnostics,
+        };
+        diagnostics
+            .iter()
+            .map(|warning| (warning.code, &source[warning.span.start..warning.span.end]))
+            .collect()
+    }
+
+    #[test]
+    fn forward_references() {
+        let source = "
+            func main() {
+                var point = make(1, 2);
+                _ = sum(point);
+            }
+            func make(x: i32, y: i32) Point {
+                return Point { x: x, y: y };
+            }
+            func sum(point: Point) i32 {
+                return point.x + point.y;
+            }
+            struct Point { x: i32, y: i32 }
+        ";
+        assert_eq!(errors(source), vec![]);
+    }
+
+    #[test]
+    fn char_casts() {
+        let source = "
+            func main() {
+                var c = 'a';
+                var byte = c as u8 + 1;
+                var next = byte as char;
+                _ = next;
+            }
+        ";
+        assert_eq!(errors(source), vec![]);
+        let source = "func main() { var n: i32 = 1; _ = n as char; }";
+        assert_eq!(errors(source), vec![Code::TypeMismatch]);
+    }
+
+    #[test]
+    fn literals_fit_their_type() {
+        let source = "
+            func main() {
+                var a: i32 = 99999999999;
+                var b: u8 = 256;
+                var c = 3000000000;
+                var d: u8 = 255;
+                var e: usize = 99999999999;
+                _ = a + c;
+                _ = b + d;
+                _ = e;
+            }
+        ";
+        let too_large = Code::IntegerTooLarge;
+        assert_eq!(
+            warnings(source),
+            vec![
+                (too_large, "99999999999"),
+                (too_large, "256"),
+                (too_large, "3000000000")
+            ]
+        );
+    }
+
+    #[test]
+    fn keywords_as_names() {
+        let sources = [
+            "func main() { var use = 1; }",
+            "func mod() {} func main() {}",
+            "struct unsafe { x: i32 } func main() {}",
+            "func main() { var n: i32 = 1; _ = n + const; }",
+            "func main() { var for = 1; }",
+        ];
+        // recovering from the error can report more after it
+        for source in sources {
+            let first = errors(source).first().copied();
+            assert_eq!(first, Some(Code::ReservedKeyword), "{}", source);
+        }
+    }
+
+    // Each field of a struct is initialized separately.
+    #[test]
+    fn partially_initialized_structs() {
+        let source = "
+            struct Point { x: i32, y: i32 }
+            struct Line { start: Point, end: Point }
+            func main() {
+                var p: Point;
+                p.x = 1;
+                _ = p.x;
+                _ = p.y;
+                _ = p;
+                var line: Line;
+                line.start.x = 1;
+                line.start.y = 2;
+                line.end = line.start;
+                _ = line;
+            }
+        ";
+        let uninitialized = Code::Uninitialized;
+        assert_eq!(
+            warnings(source),
+            vec![(uninitialized, "p"), (uninitialized, "p")]
+        );
+    }
+
+    // A cast that isn't allowed at all doesn't also ask for an `unsafe` block.
+    #[test]
+    fn invalid_casts() {
+        let source = "func main() { var b: u8 = 1; _ = b as i32; }";
+        assert_eq!(warnings(source), vec![(Code::InvalidCast, "as")]);
+        let source = "func main() { var n: i32 = 1; _ = &n as usize; }";
+        assert_eq!(errors(source), vec![Code::Unsafe]);
+    }
+
+    #[test]
+    fn main_signatures() {
+        let accepted = [
+            "func main() {}",
+            "func main() i32 { return 0; }",
+            "func main(argc: i32, argv: **u8) {}",
+            "func main(argc: i32, argv: **u8) i32 { return argc; }",
+        ];
+        for source in accepted {
+            assert_eq!(errors(source), vec![], "{}", source);
+        }
+        let rejected = [
+            "func main(argc: i32) {}",
+            "func main(argc: u32, argv: **u8) {}",
+            "func main(argc: i32, argv: *u8) {}",
+            "func main(argc: i32, argv: **u8, envp: **u8) {}",
+            "func main() bool { return true; }",
+            "func main(argc: i32, argv: **u8) u8 { return 0; }",
+        ];
+        for source in rejected {
+            assert_eq!(errors(source), vec![Code::InvalidMain], "{}", source);
+        }
+    }
+
+    #[test]
+    fn constant_conditions_point_at_the_condition() {
+        let source = "
+            const DEBUG: bool = false;
+            func main() {
+                if (DEBUG) { _ = 1; }
+                while 1 > 2 { _ = 2; }
+            }
+        ";
+        assert_eq!(
+            warnings(source),
+            vec![
+                (Code::ConstantCondition, "(DEBUG)"),
+                (Code::ConstantCondition, "1 > 2"),
+            ]
+        );
+    }
+
+    const SEEDS: &[&str] = &[
+        include_str!("../example.txt"),
+        include_str!("../prelude.txt"),
+        "
+        mod shapes {
+            @repr(c)
+            struct Rect { w: u16, h: u16 }
+            func area(rect: Rect) u16 { return rect.w * rect.h; }
+        }
+        use shapes::Rect;
+        const SIDE: i32 = 4 * 2;
+        static_assert(SIDE > 0, \"side\");
+        struct Empty {}
+        @test
+        func counts() {
+            var n = 0;
+            var c: char = 'x';
+            _ = shapes::area(Rect { w: 2, h: 3 });
+            assert(c > 'a');
+            while n < SIDE { n = n + 1; }
+            assert(n > 7);
+            unsafe { var p = null; _ = p; }
+            /* nested /* comment */ */
+        }
+        ",
+    ];
+
+    // Deterministic, so a failure can be reproduced from its iteration.
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self, below: usize) -> usize {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            (self.0 % below as u64) as usize
+        }
+    }
+
+    fn check(source: &str) {
+        _ = check_source(source, None, &mut Profiler::new(false));
+    }
+
+    #[test]
+    fn truncated_sources() {
+        for seed in SEEDS {
+            for (end, _) in seed.char_indices() {
+                check(&seed[..end]);
+            }
+        }
+    }
+
+    // Mutates the seeds with fragments of the language, which reaches more of the
+    // checker than random bytes. Set `PL_FUZZ_ITERATIONS` to run it for longer.
+    #[test]
+    fn fuzz_check_source() {
+        let fragments: Vec<_> = "func struct var if else while return mod use :: const unsafe \
+            null true as i32 u8 char bool * ? & ( ) { } ; : , . = + - < > @test x Point main \
+            0 1 99999999999 'a' \"s\" /* \n"
+            .split(' ')
+            .collect();
+        let iterations = std::env::var("PL_FUZZ_ITERATIONS")
+            .ok()
+            .and_then(|iterations| iterations.parse().ok())
+            .unwrap_or(2000);
+        let mut rng = Rng(0x9e3779b97f4a7c15);
+        for _ in 0..iterations {
+            let seed = SEEDS[rng.next(SEEDS.len())];
+            let mut tokens: Vec<&str> = seed.split_inclusive([' ', '\n']).collect();
+            for _ in 0..=rng.next(4) {
+                let index = rng.next(tokens.len() + 1);
+                match rng.next(3) {
+                    0 => _ = tokens.drain(index..(index + rng.next(5)).min(tokens.len())),
+                    1 => tokens.insert(index, fragments[rng.next(fragments.len())]),
+                    _ => {
+                        let other = tokens.get(rng.next(tokens.len().max(1))).copied();
+                        tokens.insert(index, other.unwrap_or(""));
+                    }
+                }
+            }
+            check(&tokens.concat());
+        }
+    }
+}
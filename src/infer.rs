@@ -1,12 +1,16 @@
+#![deny(clippy::unwrap_used, clippy::expect_used, clippy::panic)]
+
 use std::{rc::Rc, cell::RefCell, fmt};
 
+use crate::ty::TyError;
+
 #[derive(Debug)]
 pub struct InferTyRef<T: Unify>(Rc<RefCell<InferTy<T>>>);
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum InferTy<T: Unify> {
     Equal(InferTyRef<T>),
-    Known(Option<T>),
+    Known(T),
 }
 
 impl<T: Unify> Clone for InferTyRef<T> {
@@ -15,47 +19,47 @@ impl<T: Unify> Clone for InferTyRef<T> {
     }
 }
 
-pub trait Unify where Self: Sized {
-    fn unify(a: Self, b: Self) -> Result<Self, ()>;
+pub trait Unify where Self: Sized + Clone {
+    fn unify(a: Self, b: Self) -> Result<Self, TyError>;
 }
 
 impl<T: Unify> InferTyRef<T> {
     pub fn new(ty: T) -> InferTyRef<T> {
-        InferTyRef(Rc::new(RefCell::new(InferTy::Known(Some(ty)))))
+        InferTyRef(Rc::new(RefCell::new(InferTy::Known(ty))))
     }
     pub fn map<T1>(&self, f: impl Fn(&T) -> T1) -> T1 {
         match &*self.0.borrow() {
             InferTy::Equal(r) => r.map(f),
-            InferTy::Known(ty) => f(ty.as_ref().unwrap()),
+            InferTy::Known(ty) => f(ty),
         }
     }
 }
 
-pub fn unify<T: Unify>(a: &InferTyRef<T>, b: &InferTyRef<T>) -> Result<InferTyRef<T>, ()> {
+pub fn unify<T: Unify>(a: &InferTyRef<T>, b: &InferTyRef<T>) -> Result<InferTyRef<T>, TyError> {
     if Rc::ptr_eq(&a.0, &b.0) {
         return Ok(a.clone())
     }
 
-    let mut a_ref = a.0.borrow_mut();
-    let mut b_ref = b.0.borrow_mut();
+    // Copy both sides out before recursing, as a struct's fields may refer back to
+    // the struct through a pointer and would otherwise still be borrowed.
+    let a_ty = a.0.borrow().clone();
+    let b_ty = b.0.borrow().clone();
 
-    match (&mut *a_ref, &mut *b_ref) {
-        (InferTy::Equal(a), _) => {
-            drop(b_ref);
-            let unified = unify(a, b)?;
-            *a = unified.clone();
+    match (a_ty, b_ty) {
+        (InferTy::Equal(a_inner), _) => {
+            let unified = unify(&a_inner, b)?;
+            *a.0.borrow_mut() = InferTy::Equal(unified.clone());
             Ok(unified)
         }
-        (_, InferTy::Equal(b)) => {
-            drop(a_ref);
-            let unified = unify(a, b)?;
-            *b = unified.clone();
+        (_, InferTy::Equal(b_inner)) => {
+            let unified = unify(a, &b_inner)?;
+            *b.0.borrow_mut() = InferTy::Equal(unified.clone());
             Ok(unified)
         }
-        (InferTy::Known(a), InferTy::Known(b)) => {
-            let unified = InferTyRef::new(T::unify(a.take().unwrap(), b.take().unwrap())?);
-            *a_ref = InferTy::Equal(unified.clone());
-            *b_ref = InferTy::Equal(unified.clone());
+        (InferTy::Known(a_ty), InferTy::Known(b_ty)) => {
+            let unified = InferTyRef::new(T::unify(a_ty, b_ty)?);
+            *a.0.borrow_mut() = InferTy::Equal(unified.clone());
+            *b.0.borrow_mut() = InferTy::Equal(unified.clone());
             Ok(unified)
         }
     }
@@ -65,7 +69,7 @@ impl<T: fmt::Display + Unify> fmt::Display for InferTyRef<T> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match &*self.0.borrow() {
             InferTy::Equal(ty) => write!(f, "{}", ty),
-            InferTy::Known(ty) => write!(f, "{}", ty.as_ref().unwrap()),
+            InferTy::Known(ty) => write!(f, "{}", ty),
         }
     }
 }
\ No newline at end of file
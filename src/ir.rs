@@ -1,4 +1,4 @@
-use crate::{symbols::Symbol, ty::Int, typed_ast};
+use crate::{lexer::Span, symbols::Symbol, ty::Int, typed_ast};
 
 #[derive(Debug, Clone)]
 pub enum Ty {
@@ -18,6 +18,9 @@ pub struct StructField {
 pub struct Func {
     pub name: Symbol,
     pub params: Vec<Ty>,
+    pub vars: Vec<Ty>,
+    pub slots: Vec<u32>,
+    pub temps: Vec<Vec<Ty>>,
     pub blocks: Vec<Block>,
 }
 
@@ -25,6 +28,14 @@ pub struct Func {
 pub struct Block {
     pub stmts: Vec<Stmt>,
     pub branch: Branch,
+    pub name: &'static str,
+    pub span: Span,
+}
+
+impl Block {
+    pub fn describe(&self, source: &str) -> String {
+        format!("{} at line {}", self.name, self.span.pos(source).line)
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -40,13 +51,17 @@ pub enum Branch {
 
 #[derive(Debug, Clone)]
 pub enum Stmt {
-    Alloc(Ty),
     Assign {
         ref_expr: RefExpr,
         ty: Ty,
         expr: Expr,
     },
     FuncCall(FuncCall),
+    Discard(Expr),
+    Assert {
+        expr: Expr,
+        span: Span,
+    },
 }
 
 #[derive(Debug, Clone)]
@@ -58,7 +73,34 @@ pub enum Expr {
         ty: Int,
         op: typed_ast::BinaryOp,
     },
+    BitNot {
+        expr: Box<Expr>,
+        ty: Int,
+    },
+    Intrinsic {
+        intrinsic: typed_ast::Intrinsic,
+        arg: Box<Expr>,
+        ty: Int,
+    },
+    Atomic {
+        op: typed_ast::AtomicOp,
+        args: Vec<Expr>,
+        ty: Int,
+        span: Span,
+    },
+    PtrOffset {
+        ptr: Box<Expr>,
+        offset: Box<Expr>,
+        ty: Ty,
+        offset_ty: Int,
+        op: typed_ast::BinaryOp,
+    },
     Bool(bool),
+    Unwrap {
+        expr: Box<Expr>,
+        span: Span,
+    },
+    IsNull(Box<Expr>),
     Load {
         var: typed_ast::Variable,
         ty: Ty,
@@ -67,9 +109,13 @@ pub enum Expr {
     Deref {
         expr: Box<Expr>,
         ty: Ty,
+        span: Span,
     },
     FuncCall(FuncCall),
-    InitStruct(Vec<StructValue>),
+    InitStruct {
+        values: Vec<StructValue>,
+        temp: u32,
+    },
     Field {
         expr: Box<Expr>,
         fields: Vec<StructField>,
@@ -80,7 +126,7 @@ pub enum Expr {
 #[derive(Debug, Clone)]
 pub enum RefExpr {
     Variable(typed_ast::Variable),
-    Deref(Box<Expr>),
+    Deref(Box<Expr>, Span),
     Field {
         ref_expr: Box<RefExpr>,
         fields: Vec<StructField>,
@@ -0,0 +1,223 @@
+use crate::{ir, ty::Size};
+
+// Structs are laid out like C structs: fields in declaration order, each
+// aligned to its size. Only `@repr(c)` structs are guaranteed to keep this.
+
+pub fn size_bytes(ty: &ir::Ty) -> u32 {
+    match ty {
+        ir::Ty::Bool => 1,
+        ir::Ty::Int(int_ty) => match int_ty.size {
+            Size::B8 => 1,
+            Size::B16 => 2,
+            Size::B32 => 4,
+            Size::Ptr => 8,
+        },
+        ir::Ty::Ptr => 8,
+        ir::Ty::Struct(fields) => {
+            let end = match (fields.last(), field_offsets(fields).last()) {
+                (Some(field), Some(offset)) => offset + size_bytes(&field.ty),
+                _ => 0,
+            };
+            // padded like a C struct so that arrays and by value arguments line up
+            align_to(end, align_bytes(ty))
+        }
+    }
+}
+
+pub fn field_offsets(fields: &[ir::StructField]) -> Vec<u32> {
+    let mut end = 0;
+    fields
+        .iter()
+        .map(|field| {
+            let offset = align_to(end, align_bytes(&field.ty));
+            end = offset + size_bytes(&field.ty);
+            offset
+        })
+        .collect()
+}
+
+pub fn align_to(offset: u32, align: u32) -> u32 {
+    (offset + align - 1) & !(align - 1)
+}
+
+pub fn align_bytes(ty: &ir::Ty) -> u32 {
+    match ty {
+        ir::Ty::Bool => 1,
+        ir::Ty::Int(int_ty) => match int_ty.size {
+            Size::B8 => 1,
+            Size::B16 => 2,
+            Size::B32 => 4,
+            Size::Ptr => 8,
+        },
+        ir::Ty::Ptr => 8,
+        // C doesn't allow an empty struct, but GNU C gives one a size of 0 and aligns
+        // it like a byte, which is what's done here
+        ir::Ty::Struct(fields) => {
+            let mut max = 1;
+            for field in fields {
+                let align = align_bytes(&field.ty);
+                if align > max {
+                    max = align
+                }
+            }
+            max
+        }
+    }
+}
+
+// The bytes allocated on the stack for a function's variables after coalescing, and
+// for its temporaries. Each slot is as large as the largest type stored in it.
+pub fn frame_size(func: &ir::Func) -> u32 {
+    let mut slot_sizes = vec![0; func.vars.len()];
+    for (ty, slot) in func.vars.iter().zip(&func.slots) {
+        let size = &mut slot_sizes[*slot as usize];
+        *size = size_bytes(ty).max(*size);
+    }
+    let temp_sizes = func
+        .temps
+        .iter()
+        .map(|tys| tys.iter().map(size_bytes).max().unwrap_or(0));
+    slot_sizes.into_iter().chain(temp_sizes).sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        symbols::Symbol,
+        ty::{Int, Signedness},
+    };
+    use std::{env, fs, process, process::Command};
+
+    fn int(size: Size) -> ir::Ty {
+        ir::Ty::Int(Int {
+            signedness: Signedness::Signed,
+            size,
+        })
+    }
+
+    fn fields(tys: Vec<ir::Ty>) -> Vec<ir::StructField> {
+        tys.into_iter()
+            .enumerate()
+            .map(|(index, ty)| ir::StructField {
+                name: Symbol(index as u32),
+                ty,
+            })
+            .collect()
+    }
+
+    // The offsets, size and alignment of a struct with fields of these types.
+    fn layout(tys: Vec<ir::Ty>) -> (Vec<u32>, u32, u32) {
+        let fields = fields(tys);
+        let offsets = field_offsets(&fields);
+        let ty = ir::Ty::Struct(fields);
+        (offsets, size_bytes(&ty), align_bytes(&ty))
+    }
+
+    // The C type that has the same layout, with fields named by their index.
+    fn c_type(ty: &ir::Ty) -> String {
+        match ty {
+            ir::Ty::Bool => "_Bool".to_string(),
+            ir::Ty::Int(int) => match int.size {
+                Size::B8 => "char",
+                Size::B16 => "short",
+                Size::B32 => "int",
+                Size::Ptr => "long",
+            }
+            .to_string(),
+            ir::Ty::Ptr => "void *".to_string(),
+            ir::Ty::Struct(fields) => {
+                let fields: String = fields
+                    .iter()
+                    .enumerate()
+                    .map(|(index, field)| format!("{} f{}; ", c_type(&field.ty), index))
+                    .collect();
+                format!("struct {{ {}}}", fields)
+            }
+        }
+    }
+
+    // Compiles and runs a C program printing the offsets, size and alignment of each
+    // struct, in the same form as `layout`. None if there's no C compiler.
+    fn c_layouts(structs: &[Vec<ir::Ty>]) -> Option<Vec<(Vec<u32>, u32, u32)>> {
+        let dir = env::temp_dir().join(format!("layout-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let mut program = "#include <stddef.h>\n#include <stdio.h>\n".to_string();
+        for (index, tys) in structs.iter().enumerate() {
+            let ty = ir::Ty::Struct(fields(tys.clone()));
+            program += &format!("typedef {} s{};\n", c_type(&ty), index);
+        }
+        program += "int main(void) {\n";
+        for (index, tys) in structs.iter().enumerate() {
+            for field in 0..tys.len() {
+                program += &format!("  printf(\"%zu \", offsetof(s{}, f{}));\n", index, field);
+            }
+            program += &format!(
+                "  printf(\"%zu %zu\\n\", sizeof(s{0}), _Alignof(s{0}));\n",
+                index
+            );
+        }
+        program += "}\n";
+        let source = dir.join("layout.c");
+        let binary = dir.join("layout");
+        fs::write(&source, program).unwrap();
+        let compiled = Command::new("cc")
+            .arg("-o")
+            .arg(&binary)
+            .arg(&source)
+            .output()
+            .ok()?;
+        assert!(
+            compiled.status.success(),
+            "{}",
+            String::from_utf8_lossy(&compiled.stderr)
+        );
+        let output = Command::new(&binary).output().unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        let layouts = String::from_utf8(output.stdout)
+            .unwrap()
+            .lines()
+            .map(|line| {
+                let mut numbers: Vec<u32> = line.split(' ').map(|n| n.parse().unwrap()).collect();
+                let align = numbers.pop().unwrap();
+                let size = numbers.pop().unwrap();
+                (numbers, size, align)
+            })
+            .collect();
+        Some(layouts)
+    }
+
+    #[test]
+    fn matches_c() {
+        let inner = || ir::Ty::Struct(fields(vec![int(Size::B8), ir::Ty::Ptr]));
+        let structs = vec![
+            vec![int(Size::B8), int(Size::B32)],
+            vec![int(Size::B32), int(Size::B8)],
+            vec![int(Size::B16), ir::Ty::Bool],
+            vec![int(Size::B8), int(Size::Ptr), int(Size::B8)],
+            vec![int(Size::B8), inner(), int(Size::B16)],
+            vec![ir::Ty::Bool, int(Size::B16), inner(), ir::Ty::Bool],
+            vec![int(Size::B16), int(Size::B16), int(Size::B8)],
+            vec![
+                ir::Ty::Struct(fields(vec![inner(), int(Size::B8)])),
+                int(Size::B32),
+            ],
+        ];
+        let Some(expected) = c_layouts(&structs) else {
+            eprintln!("skipping: no C compiler found");
+            return;
+        };
+        assert_eq!(expected.len(), structs.len());
+        for (tys, expected) in structs.into_iter().zip(expected) {
+            assert_eq!(layout(tys), expected);
+        }
+    }
+
+    // Not checked against C, which doesn't allow them without an extension.
+    #[test]
+    fn empty_structs() {
+        assert_eq!(layout(vec![]), (vec![], 0, 1));
+        let empty = ir::Ty::Struct(vec![]);
+        assert_eq!(layout(vec![empty, int(Size::B32)]), (vec![0, 0], 4, 4));
+    }
+}
@@ -1,6 +1,10 @@
+#![deny(clippy::unwrap_used, clippy::expect_used, clippy::panic)]
+
 use strum::IntoEnumIterator;
 
 use crate::{
+    codes::Code,
+    diagnostic::Diagnostic,
     token::{Keyword, RawToken, Symbol, TokenKind},
     tokens::Tokens,
 };
@@ -17,13 +21,101 @@ pub struct Position {
     pub column: u32,
 }
 
-pub fn lex<'s>(source: &'s str) -> Tokens<'s> {
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+pub fn lex<'s>(source: &'s str) -> (Tokens<'s>, Vec<Diagnostic>) {
     let mut lexer = Lexer { offset: 0, source };
     let mut raw_tokens = vec![];
+    let mut comments = vec![];
+    let mut errors = vec![];
     while let Some(token) = lexer.next_token() {
-        raw_tokens.push(token);
+        let span = Span {
+            start: token.offset,
+            end: lexer.offset,
+        };
+        let text = &source[span.start..span.end];
+        if token.kind == TokenKind::Unknown {
+            let (code, message) = if text.starts_with('"') {
+                (
+                    Code::UnterminatedString,
+                    "unterminated string literal".to_string(),
+                )
+            } else if text.starts_with('\'') {
+                (Code::InvalidChar, "invalid character literal".to_string())
+            } else if text.starts_with("/*") {
+                (
+                    Code::UnterminatedComment,
+                    "unterminated block comment".to_string(),
+                )
+            } else {
+                (
+                    Code::UnexpectedCharacter,
+                    format!("unexpected character `{}`", text),
+                )
+            };
+            let mut error = Diagnostic::new(code, span, message);
+            if text.chars().all(char::is_alphanumeric) {
+                error = error.with_note(
+                    "identifiers can only contain ASCII letters, digits and `_`".to_string(),
+                );
+            }
+            errors.push(error);
+        } else if token.kind == TokenKind::Comment {
+            comments.push(span);
+        } else if token.kind == TokenKind::Integer && text.parse::<i64>().is_err() {
+            errors.push(Diagnostic::new(
+                Code::IntegerTooLarge,
+                span,
+                "integer literal is too large".to_string(),
+            ));
+        } else {
+            raw_tokens.push(token);
+        }
+    }
+    (
+        Tokens {
+            raw_tokens,
+            comments,
+            source,
+        },
+        errors,
+    )
+}
+
+// The byte a character literal stands for, which must be ASCII as a char is a `u8`.
+pub fn char_value(text: &str) -> Option<u8> {
+    let inner = text.strip_prefix('\'')?.strip_suffix('\'')?;
+    match inner.as_bytes() {
+        [b'\\', b'n'] => Some(b'\n'),
+        [b'\\', b't'] => Some(b'\t'),
+        [b'\\', b'r'] => Some(b'\r'),
+        [b'\\', b'0'] => Some(0),
+        [b'\\', ch @ (b'\\' | b'\'' | b'"')] => Some(*ch),
+        [ch] if ch.is_ascii() && *ch != b'\'' && *ch != b'\\' => Some(*ch),
+        _ => None,
+    }
+}
+
+impl Span {
+    pub fn pos(&self, source: &str) -> Position {
+        let mut line = 1;
+        let mut column = 1;
+        for (offset, ch) in source.char_indices() {
+            if offset == self.start {
+                return Position { line, column };
+            }
+            if ch == '\n' {
+                column = 0;
+                line += 1;
+            }
+            column += 1;
+        }
+        Position { line, column }
     }
-    Tokens { raw_tokens, source }
 }
 
 impl<'a> Lexer<'a> {
@@ -37,7 +129,7 @@ impl<'a> Lexer<'a> {
     }
     fn eat_if(&mut self, f: impl Fn(char) -> bool) -> bool {
         let ch = self.source[self.offset..].chars().next();
-        if ch.map_or(false, f) {
+        if ch.is_some_and(f) {
             self.offset += ch.map_or(0, |ch| ch.len_utf8());
             true
         } else {
@@ -61,20 +153,67 @@ impl<'a> Lexer<'a> {
 
             if self.eat_str("//") {
                 self.eat_while(|ch| ch != '\n');
-                continue;
+                return Some(RawToken {
+                    kind: TokenKind::Comment,
+                    offset,
+                });
+            }
+            // block comments nest, so code with a comment in it can be commented out
+            if self.eat_str("/*") {
+                let mut depth = 1;
+                while depth > 0 && self.offset < self.source.len() {
+                    if self.eat_str("/*") {
+                        depth += 1;
+                    } else if self.eat_str("*/") {
+                        depth -= 1;
+                    } else {
+                        self.eat_if(|_| true);
+                    }
+                }
+                let kind = if depth == 0 {
+                    TokenKind::Comment
+                } else {
+                    TokenKind::Unknown
+                };
+                return Some(RawToken { kind, offset });
             }
             if self.eat_if(|ch| ch.is_whitespace()) {
                 continue;
             }
-            if self.eat_if(|ch| ch.is_numeric()) {
-                self.eat_while(|ch| ch.is_numeric());
+            if self.eat_if(|ch| ch.is_ascii_digit()) {
+                self.eat_while(|ch| ch.is_ascii_digit());
                 return Some(RawToken {
                     kind: TokenKind::Integer,
                     offset,
                 });
             }
-            if self.eat_if(|ch| ch.is_alphabetic() || ch == '_') {
-                self.eat_while(|ch| ch.is_alphanumeric() || ch == '_');
+            if self.eat_str("\"") {
+                self.eat_while(|ch| ch != '"' && ch != '\n');
+                let kind = if self.eat_str("\"") {
+                    TokenKind::String
+                } else {
+                    TokenKind::Unknown
+                };
+                return Some(RawToken { kind, offset });
+            }
+            if self.eat_str("'") {
+                loop {
+                    if self.eat_str("\\") {
+                        self.eat_if(|ch| ch != '\n');
+                    } else if !self.eat_if(|ch| ch != '\'' && ch != '\n') {
+                        break;
+                    }
+                }
+                self.eat_str("'");
+                let kind = match char_value(&self.source[offset..self.offset]) {
+                    Some(_) => TokenKind::Char,
+                    None => TokenKind::Unknown,
+                };
+                return Some(RawToken { kind, offset });
+            }
+            // identifiers are ASCII so they can be used as symbol names as they are
+            if self.eat_if(|ch| ch.is_ascii_alphabetic() || ch == '_') {
+                self.eat_while(|ch| ch.is_ascii_alphanumeric() || ch == '_');
                 for keyword in Keyword::iter() {
                     if keyword.str() == &self.source[offset..self.offset] {
                         return Some(RawToken {
@@ -96,7 +235,26 @@ impl<'a> Lexer<'a> {
                     });
                 }
             }
-            panic!("{}", &self.source[self.offset..])
+            self.eat_if(|_| true);
+            return Some(RawToken {
+                kind: TokenKind::Unknown,
+                offset,
+            });
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn non_ascii_identifiers() {
+        let (tokens, errors) = lex("var café = 1;");
+        assert_eq!(tokens.raw_tokens.len(), 5);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].code, Code::UnexpectedCharacter);
+        assert_eq!(errors[0].span, Span { start: 7, end: 9 });
+        assert_eq!(errors[0].notes.len(), 1);
+    }
+}
@@ -0,0 +1,191 @@
+#![deny(clippy::unwrap_used, clippy::expect_used, clippy::panic)]
+
+use crate::{
+    lexer::Span,
+    typed_ast::{Block, Branch, Expr, Func, RefExpr, Stmt, Variable},
+};
+
+pub struct Liveness {
+    pub live_in: Vec<Vec<bool>>,
+    // a variable which has had its address taken may be read through a pointer
+    pub escaped: Vec<bool>,
+}
+
+pub fn analyse(func: &Func, var_count: usize) -> Liveness {
+    let mut escaped = vec![false; var_count];
+    for block in &func.blocks {
+        visit_block(block, &mut |expr| {
+            if let Expr::Ref(ref_expr) = expr {
+                if let Some(var) = root_var(ref_expr) {
+                    escaped[var.0 as usize] = true;
+                }
+            }
+        });
+    }
+    let mut liveness = Liveness {
+        live_in: vec![vec![false; var_count]; func.blocks.len()],
+        escaped,
+    };
+    loop {
+        let mut changed = false;
+        for (id, block) in func.blocks.iter().enumerate().rev() {
+            let live = walk_block(block, liveness.live_out(block), &mut |_, _, _, _| {});
+            if live != liveness.live_in[id] {
+                liveness.live_in[id] = live;
+                changed = true;
+            }
+        }
+        if !changed {
+            break;
+        }
+    }
+    liveness
+}
+
+impl Liveness {
+    pub fn live_out(&self, block: &Block) -> Vec<bool> {
+        let mut live = vec![false; self.escaped.len()];
+        for succ in successors(&block.branch) {
+            for (live, succ_live) in live.iter_mut().zip(&self.live_in[succ]) {
+                *live |= succ_live;
+            }
+        }
+        live
+    }
+}
+
+pub fn successors(branch: &Branch) -> Vec<usize> {
+    match branch {
+        Branch::Return(_) => vec![],
+        Branch::Static(target) => vec![target.0 as usize],
+        Branch::Condition {
+            if_true, if_false, ..
+        } => vec![if_true.0 as usize, if_false.0 as usize],
+    }
+}
+
+pub fn reachable(func: &Func) -> Vec<usize> {
+    let mut visited = vec![0];
+    let mut work = vec![0];
+    while let Some(id) = work.pop() {
+        for succ in successors(&func.blocks[id].branch) {
+            if !visited.contains(&succ) {
+                visited.push(succ);
+                work.push(succ);
+            }
+        }
+    }
+    visited
+}
+
+// Walks a block backwards from the variables live at its end, returning the
+// variables live at its start. `on_store` is called for every store to a
+// variable with whether it overwrites the whole variable and the variables
+// live after the store.
+pub fn walk_block(
+    block: &Block,
+    mut live: Vec<bool>,
+    on_store: &mut impl FnMut(Variable, Span, bool, &[bool]),
+) -> Vec<bool> {
+    match &block.branch {
+        Branch::Return(expr) => expr.iter().for_each(|expr| uses(expr, &mut live)),
+        Branch::Static(_) => {}
+        Branch::Condition { expr, .. } => uses(expr, &mut live),
+    }
+    for stmt in block.stmts.iter().rev() {
+        match stmt {
+            Stmt::Alloc(..) => {}
+            Stmt::Assign {
+                ref_expr,
+                expr,
+                span,
+                ..
+            } => {
+                if let RefExpr::Variable(var) = ref_expr {
+                    on_store(*var, *span, true, &live);
+                    live[var.0 as usize] = false;
+                } else {
+                    if let Some(var) = root_var(ref_expr) {
+                        on_store(var, *span, false, &live);
+                    }
+                    // writing through a pointer or to a single field leaves the rest of the value live
+                    visit_ref_expr(ref_expr, &mut |expr| mark_load(expr, &mut live));
+                }
+                uses(expr, &mut live);
+            }
+            Stmt::FuncCall(func_call) => func_call.args.iter().for_each(|arg| uses(arg, &mut live)),
+            Stmt::Discard(expr) | Stmt::Assert { expr, .. } => uses(expr, &mut live),
+        }
+    }
+    live
+}
+
+fn uses(expr: &Expr, live: &mut [bool]) {
+    visit_expr(expr, &mut |expr| mark_load(expr, live));
+}
+
+fn mark_load(expr: &Expr, live: &mut [bool]) {
+    if let Expr::Load { var, .. } = expr {
+        live[var.0 as usize] = true;
+    }
+}
+
+fn root_var(ref_expr: &RefExpr) -> Option<Variable> {
+    match ref_expr {
+        RefExpr::Variable(var) => Some(*var),
+        RefExpr::Deref(..) => None,
+        RefExpr::Field { ref_expr, .. } => root_var(ref_expr),
+    }
+}
+
+fn visit_block(block: &Block, f: &mut impl FnMut(&Expr)) {
+    for stmt in &block.stmts {
+        match stmt {
+            Stmt::Alloc(..) => {}
+            Stmt::Assign { ref_expr, expr, .. } => {
+                visit_ref_expr(ref_expr, f);
+                visit_expr(expr, f);
+            }
+            Stmt::FuncCall(func_call) => func_call.args.iter().for_each(|arg| visit_expr(arg, f)),
+            Stmt::Discard(expr) | Stmt::Assert { expr, .. } => visit_expr(expr, f),
+        }
+    }
+    match &block.branch {
+        Branch::Return(expr) => expr.iter().for_each(|expr| visit_expr(expr, f)),
+        Branch::Static(_) => {}
+        Branch::Condition { expr, .. } => visit_expr(expr, f),
+    }
+}
+
+fn visit_expr(expr: &Expr, f: &mut impl FnMut(&Expr)) {
+    f(expr);
+    match expr {
+        Expr::Int(_) | Expr::Bool(_) | Expr::Null | Expr::Load { .. } => {}
+        Expr::Binary { left, right, .. } => {
+            visit_expr(left, f);
+            visit_expr(right, f);
+        }
+        Expr::BitNot { expr, .. }
+        | Expr::Intrinsic { arg: expr, .. }
+        | Expr::Unwrap { expr, .. }
+        | Expr::IsNull(expr) => visit_expr(expr, f),
+        Expr::PtrOffset { ptr, offset, .. } => {
+            visit_expr(ptr, f);
+            visit_expr(offset, f);
+        }
+        Expr::Ref(ref_expr) => visit_ref_expr(ref_expr, f),
+        Expr::Deref { expr, .. } => visit_expr(expr, f),
+        Expr::FuncCall(func_call) => func_call.args.iter().for_each(|arg| visit_expr(arg, f)),
+        Expr::Atomic { args, .. } => args.iter().for_each(|arg| visit_expr(arg, f)),
+        Expr::InitStruct(values) => values.iter().for_each(|value| visit_expr(&value.expr, f)),
+        Expr::Field { expr, .. } => visit_expr(expr, f),
+    }
+}
+
+fn visit_ref_expr(ref_expr: &RefExpr, f: &mut impl FnMut(&Expr)) {
+    match ref_expr {
+        RefExpr::Variable(_) => {}
+        RefExpr::Deref(expr, _) => visit_expr(expr, f),
+        RefExpr::Field { ref_expr, .. } => visit_ref_expr(ref_expr, f),
+    }
+}
@@ -1,55 +1,890 @@
-use std::{fs::File, process::Command};
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap},
+    env, fs,
+    hash::{Hash, Hasher},
+    io::{self, Read},
+    path::Path,
+    process::{self, Command},
+    thread,
+    time::Duration,
+};
+
+use ast::Harness;
+use backend::{Backend, Constants};
+use codes::Code;
+use diagnostic::{Diagnostic, Severity};
+use lexer::Span;
+use manifest::MANIFEST;
+use profile::{CountingAlloc, Profiler};
+use session::{DocFormat, Emit, ErrorFormat, Panic, Prelude, Query, Session};
+use target::Os;
 
 mod ast;
+mod backend;
+mod cfg;
+mod coalesce;
+mod codes;
 mod compile_ast;
 mod compile_typed_ast;
+mod consteval;
+mod dead_store;
+mod decls;
+mod diagnostic;
+mod doc;
+mod doctest;
+mod frontend;
 mod infer;
 mod ir;
+mod layout;
 mod lexer;
+mod liveness;
+mod manifest;
+mod outline;
 mod parser;
+mod profile;
 mod qbe;
+mod range;
+mod resolve;
+mod semantic;
+mod session;
 mod symbols;
+mod target;
 mod token;
 mod tokens;
 mod ty;
 mod typed_ast;
+mod uninit;
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
 
 fn main() {
-    let source = include_str!("../example.txt");
-    let tokens = lexer::lex(source);
-    let (program, symbols, parse_errors) = parser::parse(&tokens);
-    if parse_errors.len() > 0 {
-        for err in parse_errors {
-            println!("{}", err)
+    let mut session = match Session::from_args(env::args().skip(1)) {
+        Ok(session) => session,
+        Err(err) => {
+            eprintln!("{}", err);
+            process::exit(1);
+        }
+    };
+    if let Some(code) = &session.explain {
+        match codes::lookup(code) {
+            Some(code) => println!("{}", code.explain()),
+            None => {
+                eprintln!("unknown error code '{}'", code);
+                process::exit(1);
+            }
         }
         return;
     }
+    let backend = match backend::select(&session.backend, session.target) {
+        Ok(backend) => backend,
+        Err(err) => {
+            eprintln!("{}", err);
+            process::exit(1);
+        }
+    };
+    if !session.watch {
+        process::exit(build(&mut session, &*backend));
+    }
+    if session.sources.is_empty() {
+        eprintln!("'--watch' needs source files to watch");
+        process::exit(1);
+    }
+    let mut paths = session.sources.clone();
+    if let Prelude::File(path) = &session.prelude {
+        paths.push(path.clone());
+    }
+    if Path::new(MANIFEST).exists() {
+        paths.push(MANIFEST.to_string());
+    }
+    loop {
+        build(&mut session, &*backend);
+        println!("waiting for changes...");
+        wait_for_change(&paths);
+    }
+}
 
-    // println!("{:#?}", program);
+// Reads and compiles the sources, then links and runs the output, returning the exit
+// status of the compiler or the program it ran.
+fn build(session: &mut Session, backend: &dyn Backend) -> i32 {
+    let sources = if session.sources.is_empty() {
+        vec![(None, include_str!("../example.txt").to_string())]
+    } else {
+        let mut sources = vec![];
+        for path in &session.sources {
+            match fs::read_to_string(path) {
+                Ok(source) => sources.push((Some(path.clone()), source)),
+                Err(err) => {
+                    eprintln!("failed to read '{}': {}", path, err);
+                    return 1;
+                }
+            }
+        }
+        sources
+    };
 
-    let mut func_mirs = vec![];
-    for func_ast in program.func_iter() {
-        if let Some(func_mir) = compile_ast::compile_func(func_ast, &program) {
-            // println!("{}", func_mir);
-            func_mirs.push(func_mir);
+    let prelude = match &session.prelude {
+        Prelude::Builtin => Some(include_str!("../prelude.txt").to_string()),
+        Prelude::File(path) => {
+            let source = match fs::read_to_string(path) {
+                Ok(source) => source,
+                Err(err) => {
+                    eprintln!("failed to read '{}': {}", path, err);
+                    return 1;
+                }
+            };
+            let diagnostics = frontend::check_prelude(&source, &mut session.profiler);
+            for diagnostic in &diagnostics {
+                report(diagnostic, Some(path), &source, session.error_format);
+            }
+            if diagnostics
+                .iter()
+                .any(|diagnostic| diagnostic.severity == Severity::Error)
+            {
+                return 1;
+            }
+            Some(source)
+        }
+        Prelude::None => None,
+    };
+    if let Some(query) = session.query.clone() {
+        let mut answered = true;
+        for (path, source) in &sources {
+            answered &= answer(&query, path.as_deref(), source, &prelude, session);
         }
+        return if answered { 0 } else { 1 };
     }
 
-    let file = File::create("output.ssa").unwrap();
-    for struct_decl in program.struct_iter() {
-        qbe::compile_struct(struct_decl, &file, &symbols).unwrap();
+    // modules are linked in the order they were given so the output is deterministic
+    let mut objects = vec![];
+    let mut defined: HashMap<String, &str> = HashMap::new();
+    let mut harnessed = vec![];
+    let mut failed = false;
+    for (index, (path, source)) in sources.iter().enumerate() {
+        let base = match sources.len() {
+            1 => "output".to_string(),
+            _ => format!("output{}", index),
+        };
+        let path = path.as_deref();
+        let prelude = prelude.as_deref();
+        let funcs = match compile_module(path, source, prelude, &base, backend, session) {
+            Some(funcs) => funcs,
+            None => {
+                failed = true;
+                continue;
+            }
+        };
+        let name = path.unwrap_or("example.txt");
+        for func in funcs {
+            if func.harness.is_some() && func.harness == session.harness {
+                harnessed.push(func.name.clone());
+            }
+            match defined.get(func.name.as_str()) {
+                Some(first) => {
+                    let message = format!(
+                        "function `{}` is defined in both `{}` and `{}`",
+                        func.name, first, name
+                    );
+                    let err = Diagnostic::new(Code::Duplicate, func.span, message);
+                    report(&err, path, source, session.error_format);
+                    failed = true;
+                }
+                None => _ = defined.insert(func.name, name),
+            }
+        }
+        if matches!(
+            session.emit,
+            Emit::FrameReport | Emit::UnsafeReport | Emit::Annotated
+        ) {
+            continue;
+        }
+        objects.push(format!("{}.o", base));
     }
-    for func_mir in &func_mirs {
-        let func_lir = compile_typed_ast::lower_func(func_mir);
-        qbe::compile_func(&func_lir, &file, &symbols, &program).unwrap();
+    let cc = session.cc();
+    let profiler = &mut session.profiler;
+    if failed {
+        profiler.report();
+        return 1;
     }
-    Command::new("qbe/obj/qbe")
-        .args(["output.ssa", "-o", "output.S"])
-        .status()
-        .unwrap();
-    Command::new("gcc")
-        .args(["-o", "output", "main.c", "output.S"])
-        .status()
+    if let Some(harness) = session.harness {
+        let binary = match link_harness(session, backend, objects, harness, &harnessed) {
+            Some(binary) => binary,
+            None => return 1,
+        };
+        return match harness {
+            Harness::Test => {
+                let examples_passed = check_examples(&sources, prelude.as_deref(), session);
+                match run_tests(&binary, &harnessed, session) {
+                    0 if !examples_passed => 1,
+                    status => status,
+                }
+            }
+            Harness::Bench => run_benches(&binary, &harnessed, session),
+        };
+    }
+    let (default_output, status) = match session.emit {
+        // the report has already been printed
+        Emit::FrameReport | Emit::UnsafeReport | Emit::Annotated => {
+            profiler.report();
+            return 0;
+        }
+        // an object file or library is for embedding in another build, so it isn't run
+        Emit::Obj => {
+            let output = session.output.as_deref().unwrap_or("output.o");
+            let status = profiler.time("link", || match &objects[..] {
+                // copying a file onto itself would truncate it
+                [object] if fs::canonicalize(object).ok() == fs::canonicalize(output).ok() => true,
+                [object] => fs::copy(object, output).is_ok(),
+                _ => cc_command(cc)
+                    .args(["-r", "-nostdlib", "-o", output])
+                    .args(&objects)
+                    .status()
+                    .unwrap()
+                    .success(),
+            });
+            ("output.o", status)
+        }
+        Emit::Lib => {
+            let output = session.output.as_deref().unwrap_or("output.a");
+            _ = fs::remove_file(output);
+            let status = profiler.time("archive", || {
+                Command::new("ar")
+                    .args(["rcs", output])
+                    .args(&objects)
+                    .status()
+                    .unwrap()
+            });
+            ("output.a", status.success())
+        }
+        // a shared library has the runtime linked in like an executable, and exports
+        // every function that isn't `@hidden`
+        Emit::Exe | Emit::Shared => {
+            let macos = target::os(session.target) == Os::MacOs;
+            let (default_output, kind): (_, &[&str]) = match session.emit {
+                Emit::Shared if macos => ("output.dylib", &["-dynamiclib"]),
+                Emit::Shared => ("output.so", &["-shared"]),
+                _ => ("output", &[]),
+            };
+            let output = session.output.as_deref().unwrap_or(default_output);
+            // the atomics are calls into libatomic, which macOS has in its C library
+            let (runtime, libs): (&[&str], &[&str]) = if session.freestanding {
+                (&["-nostdlib", "-static", "start.S"], &[])
+            } else if macos {
+                (&["main.c"], &[])
+            } else {
+                (&["main.c"], &["-latomic"])
+            };
+            let status = profiler.time("link", || {
+                cc_command(cc)
+                    .args(kind)
+                    .args(["-o", output])
+                    .args(runtime)
+                    .args(session.pic.then_some("-fPIC"))
+                    .args(&objects)
+                    .args(libs)
+                    .args(session.libs.iter().map(|lib| format!("-l{}", lib)))
+                    .status()
+                    .unwrap()
+            });
+            (default_output, status.success())
+        }
+    };
+    profiler.report();
+    if !status {
+        return 1;
+    }
+    // a program built for another target can't be run here
+    if session.emit != Emit::Exe || session.target.is_some() {
+        return 0;
+    }
+    let output = session.output.as_deref().unwrap_or(default_output);
+    let status = Command::new(Path::new(".").join(output)).status().unwrap();
+    status.code().unwrap_or(1)
+}
+
+// Polls the files, as there's no portable way to be notified when they change.
+fn wait_for_change(paths: &[String]) {
+    let modified = |path: &String| fs::metadata(path).and_then(|meta| meta.modified()).ok();
+    let before: Vec<_> = paths.iter().map(modified).collect();
+    while paths.iter().map(modified).eq(before.iter().copied()) {
+        thread::sleep(Duration::from_millis(200));
+    }
+}
+
+fn report(diagnostic: &Diagnostic, path: Option<&str>, source: &str, format: ErrorFormat) {
+    match format {
+        ErrorFormat::Human { color } => {
+            let prefix = path.map(|path| format!("{}: ", path)).unwrap_or_default();
+            println!("{}{}", prefix, diagnostic.display(source).color(color))
+        }
+        ErrorFormat::Json => println!("{}", diagnostic.json(path, source)),
+    }
+}
+
+fn answer(
+    query: &Query,
+    path: Option<&str>,
+    source: &str,
+    prelude: &Option<String>,
+    session: &mut Session,
+) -> bool {
+    let prefix = path.map(|path| format!("{}: ", path)).unwrap_or_default();
+    // an outline or documentation only needs the source to parse
+    if let Query::Outline | Query::Doc(_) = query {
+        return match outline::outline(source) {
+            Ok(items) => {
+                let title = path.unwrap_or("example.txt");
+                match query {
+                    Query::Doc(DocFormat::Markdown) => print!("{}", doc::markdown(title, &items)),
+                    Query::Doc(DocFormat::Html) => print!("{}", doc::html(title, &items)),
+                    _ => print_outline(&items, &prefix, source, 0),
+                }
+                true
+            }
+            Err(diagnostics) => {
+                for diagnostic in &diagnostics {
+                    report(diagnostic, path, source, session.error_format);
+                }
+                false
+            }
+        };
+    }
+    let checked = match frontend::check_source(source, prelude.as_deref(), &mut session.profiler) {
+        Ok(checked) => checked,
+        Err(diagnostics) => {
+            for diagnostic in &diagnostics {
+                report(diagnostic, path, source, session.error_format);
+            }
+            return false;
+        }
+    };
+    match query {
+        Query::TypeAt(offset) => match checked.type_at(*offset) {
+            Some(ty) => println!("{}{}", prefix, ty.name(&checked.symbols)),
+            None => println!("{}no type known at offset {}", prefix, offset),
+        },
+        Query::References(offset) => match checked.target_at(*offset) {
+            Some(target) => {
+                for span in checked.references_of(target) {
+                    let pos = span.pos(source);
+                    println!("{}{}:{}", prefix, pos.line, pos.column);
+                }
+            }
+            None => println!("{}nothing declared at offset {}", prefix, offset),
+        },
+        // prints the renamed source
+        Query::Rename(offset, name) => {
+            let mut edits = checked
+                .target_at(*offset)
+                .map(|target| checked.rename(target, name))
+                .unwrap_or_default();
+            if edits.is_empty() {
+                println!("{}nothing to rename at offset {}", prefix, offset);
+                return false;
+            }
+            edits.sort_by_key(|edit| edit.span.start);
+            let mut renamed = source.to_string();
+            for edit in edits.iter().rev() {
+                renamed.replace_range(edit.span.start..edit.span.end, &edit.text);
+            }
+            print!("{}", renamed);
+        }
+        Query::SemanticTokens => {
+            for token in semantic::semantic_tokens(&checked, source) {
+                let pos = token.span.pos(source);
+                let mut modifiers = String::new();
+                if token.mutable {
+                    modifiers.push_str(" mutable");
+                }
+                if token.unused {
+                    modifiers.push_str(" unused");
+                }
+                println!(
+                    "{}{}:{} {} {}{}",
+                    prefix,
+                    pos.line,
+                    pos.column,
+                    token.span.end - token.span.start,
+                    token.class.name(),
+                    modifiers
+                );
+            }
+        }
+        Query::Outline | Query::Doc(_) => {}
+    }
+    true
+}
+
+fn print_outline(items: &[outline::Item], prefix: &str, source: &str, depth: usize) {
+    for item in items {
+        let pos = item.span.pos(source);
+        let keyword = item
+            .kind
+            .keyword()
+            .map(|keyword| format!("{} ", keyword))
+            .unwrap_or_default();
+        println!(
+            "{}{}:{}  {}{}{}{}",
+            prefix,
+            pos.line,
+            pos.column,
+            "    ".repeat(depth),
+            keyword,
+            item.name,
+            item.detail
+        );
+        print_outline(&item.children, prefix, source, depth + 1);
+    }
+}
+
+const CACHE_DIR: &str = ".pl-cache";
+
+// Where the object for a module is cached, keyed by everything that affects it,
+// including the compiler itself. Nothing is cached if the module would print more
+// than its diagnostics, as that output would be skipped.
+fn cache_path(source: &str, prelude: Option<&str>, session: &Session) -> Option<String> {
+    if !session.build_cache
+        || session.dump_cfg
+        || session.validate
+        || session.frame_limit.is_some()
+        || matches!(
+            session.emit,
+            Emit::FrameReport | Emit::UnsafeReport | Emit::Annotated
+        )
+    {
+        return None;
+    }
+    let compiler = fs::metadata(env::current_exe().ok()?)
+        .ok()?
+        .modified()
+        .ok()?;
+    let mut hasher = DefaultHasher::new();
+    (source, prelude, &session.backend).hash(&mut hasher);
+    (session.coalesce_slots, session.harness).hash(&mut hasher);
+    session.memory_checks.hash(&mut hasher);
+    session.target.hash(&mut hasher);
+    compiler.hash(&mut hasher);
+    Some(format!("{}/{:016x}.o", CACHE_DIR, hasher.finish()))
+}
+
+struct DefinedFunc {
+    name: String,
+    span: Span,
+    harness: Option<Harness>,
+}
+
+// Compiles a single source file to `{base}.o`, returning the functions it defines.
+// Modules are still checked when their object is cached, so warnings are reported.
+fn compile_module(
+    path: Option<&str>,
+    source: &str,
+    prelude: Option<&str>,
+    base: &str,
+    backend: &dyn Backend,
+    session: &mut Session,
+) -> Option<Vec<DefinedFunc>> {
+    let coalesce_slots = session.coalesce_slots;
+    let harness = session.harness;
+    let dump_cfg = session.dump_cfg;
+    let validate = session.validate;
+    let emit = session.emit;
+    let frame_limit = session.frame_limit;
+    let memory_checks = session.memory_checks;
+    let cc = session.cc();
+    let error_format = session.error_format;
+    let cached = cache_path(source, prelude, session);
+    let profiler = &mut session.profiler;
+    let prefix = path.map(|path| format!("{}: ", path)).unwrap_or_default();
+
+    let checked = match frontend::check_source(source, prelude, profiler) {
+        Ok(checked) => checked,
+        Err(diagnostics) => {
+            for diagnostic in diagnostics {
+                report(&diagnostic, path, source, error_format)
+            }
+            return None;
+        }
+    };
+    for warning in &checked.warnings {
+        report(warning, path, source, error_format)
+    }
+    let decls = checked.decls();
+    let symbols = &checked.symbols;
+    let funcs = checked
+        .program
+        .func_iter()
+        .filter(|func| func.body.is_some())
+        .map(|func| DefinedFunc {
+            name: symbols.get_str(func.name).to_string(),
+            span: func.span,
+            harness: func.harness,
+        })
+        .collect();
+    if emit == Emit::UnsafeReport {
+        println!("{}{:>8}  unsafe operation", prefix, "line");
+        for func in &checked.funcs {
+            for (span, op) in &func.unsafe_ops {
+                let line = span.pos(source).line;
+                let name = symbols.get_str(func.name);
+                println!("{}{:>8}  {} in `{}`", prefix, line, op, name);
+            }
+        }
+        return Some(funcs);
+    }
+    let object = format!("{}.o", base);
+    if let Some(cached) = &cached {
+        if fs::copy(cached, &object).is_ok() {
+            return Some(funcs);
+        }
+    }
+    let func_mirs = &checked.funcs;
+    let func_lirs: Vec<_> = profiler.time("lower", || {
+        func_mirs
+            .iter()
+            // the harness has its own `main`
+            .filter(|func| harness.is_none() || symbols.get_str(func.name) != "main")
+            .map(|func| compile_typed_ast::lower_func(func, coalesce_slots))
+            .collect()
+    });
+
+    if dump_cfg {
+        for func_lir in &func_lirs {
+            print!("{}", cfg::dump(func_lir, symbols, source));
+        }
+    }
+    let frame_sizes: Vec<_> = func_lirs
+        .iter()
+        .map(|func| (func.name, layout::frame_size(func)))
+        .collect();
+    if let Some(limit) = frame_limit {
+        for (name, size) in frame_sizes.iter().filter(|(_, size)| *size > limit) {
+            let message = format!(
+                "the stack frame of `{}` is {} bytes, above the limit of {}",
+                symbols.get_str(*name),
+                size,
+                limit
+            );
+            let span = decls.func(*name).unwrap().span;
+            let warning = Diagnostic::new(Code::LargeFrame, span, message);
+            report(&warning, path, source, error_format);
+        }
+    }
+    if emit == Emit::FrameReport {
+        println!("{}{:>8}  function", prefix, "bytes");
+        for (name, size) in &frame_sizes {
+            println!("{}{:>8}  {}", prefix, size, symbols.get_str(*name));
+        }
+        return Some(funcs);
+    }
+
+    let capabilities = backend.capabilities();
+    let module = format!("{}.{}", base, capabilities.extension);
+    let func_lines = profiler.time("emit", || {
+        let mut output = vec![];
+        backend.emit_module(&mut output, &decls, symbols).unwrap();
+        // the line each function starts on, so errors in the output can be traced back
+        let mut func_lines = vec![];
+        let mut constants = Constants::default();
+        for func_lir in &func_lirs {
+            let line = output.iter().filter(|ch| **ch == b'\n').count() + 1;
+            func_lines.push((line, func_lir.name));
+            backend
+                .emit_func(
+                    &mut output,
+                    func_lir,
+                    &decls,
+                    symbols,
+                    source,
+                    memory_checks,
+                    &mut constants,
+                )
+                .unwrap();
+        }
+        backend.emit_constants(&mut output, &constants).unwrap();
+        fs::write(&module, output).unwrap();
+        func_lines
+    });
+    if emit == Emit::Annotated {
+        let output = fs::read_to_string(&module).unwrap();
+        print!("{}", backend.annotate(&output, source));
+        return Some(funcs);
+    }
+    if validate {
+        let errors = profiler.time("validate", || backend.validate(&module));
+        for (line, message) in &errors {
+            match func_lines.iter().rev().find(|(start, _)| start <= line) {
+                Some((_, name)) => println!(
+                    "{}invalid output for function `{}` on line {}: {}",
+                    prefix,
+                    symbols.get_str(*name),
+                    line,
+                    message
+                ),
+                None => println!("{}invalid output on line {}: {}", prefix, line, message),
+            }
+        }
+        if !errors.is_empty() {
+            return None;
+        }
+    }
+    if !assemble(&module, base, backend, cc, profiler) {
+        return None;
+    }
+    if let Some(cached) = &cached {
+        // a failure to cache only makes the next build slower
+        _ = fs::create_dir_all(CACHE_DIR).and_then(|_| fs::copy(&object, cached));
+    }
+    Some(funcs)
+}
+
+// Turns an emitted module into `{base}.o`, returning whether it succeeded.
+fn assemble(
+    module: &str,
+    base: &str,
+    backend: &dyn Backend,
+    cc: &[&str],
+    profiler: &mut Profiler,
+) -> bool {
+    let asm = format!("{}.S", base);
+    let assembled = !backend.capabilities().needs_assembling
+        || profiler.time("assemble", || backend.assemble(module, &asm));
+    if !assembled {
+        return false;
+    }
+    let object = format!("{}.o", base);
+    let status = profiler.time("compile", || {
+        cc_command(cc)
+            .args(["-c", "-o", &object, &asm])
+            .status()
+            .unwrap()
+    });
+    status.success()
+}
+
+fn cc_command(cc: &[&str]) -> Command {
+    let mut command = Command::new(cc[0]);
+    command.args(&cc[1..]);
+    command
+}
+
+// Links the objects with a generated harness that runs the test or benchmark named
+// by its first argument, returning the path of the binary.
+fn link_harness(
+    session: &mut Session,
+    backend: &dyn Backend,
+    mut objects: Vec<String>,
+    harness: Harness,
+    names: &[String],
+) -> Option<String> {
+    let cc = session.cc();
+    let profiler = &mut session.profiler;
+    let module = format!("harness.{}", backend.capabilities().extension);
+    let mut output = vec![];
+    match harness {
+        Harness::Test => backend.emit_test_harness(&mut output, names, session.panic),
+        Harness::Bench => backend.emit_bench_harness(&mut output, names),
+    }
+    .unwrap();
+    fs::write(&module, output).unwrap();
+    if !assemble(&module, "harness", backend, cc, profiler) {
+        return None;
+    }
+    objects.push("harness.o".to_string());
+    let output = session.output.as_deref().unwrap_or("output");
+    let libatomic: &[&str] = match target::os(session.target) {
+        Os::Linux => &["-latomic"],
+        Os::MacOs => &[],
+    };
+    let status = profiler.time("link", || {
+        cc_command(cc)
+            .args(["-o", output, "main.c"])
+            .args(&objects)
+            .args(libatomic)
+            .args(session.libs.iter().map(|lib| format!("-l{}", lib)))
+            .status()
+            .unwrap()
+    });
+    profiler.report();
+    status
+        .success()
+        .then(|| Path::new(".").join(output).to_string_lossy().into_owned())
+}
+
+fn matches_filter(name: &str, session: &Session) -> bool {
+    session
+        .filter
+        .as_ref()
+        .is_none_or(|filter| name.contains(filter.as_str()))
+}
+
+// Returns the names that match the filter and how many were left out.
+fn filter<'a>(names: &'a [String], session: &Session) -> (Vec<&'a String>, usize) {
+    let selected: Vec<_> = names
+        .iter()
+        .filter(|name| matches_filter(name, session))
+        .collect();
+    let filtered = names.len() - selected.len();
+    (selected, filtered)
+}
+
+// Checks that the examples in each module's doc comments build, reporting the errors
+// in those that don't. Like tests, only examples whose `file:line` contains the
+// filter are checked.
+fn check_examples(
+    sources: &[(Option<String>, String)],
+    prelude: Option<&str>,
+    session: &mut Session,
+) -> bool {
+    let mut failed = 0;
+    for (path, source) in sources {
+        for example in doctest::examples(source) {
+            let line = example.span.pos(source).line;
+            let name = format!("{}:{}", path.as_deref().unwrap_or("example.txt"), line);
+            if !matches_filter(&name, session) {
+                continue;
+            }
+            let errors = example.check(source, prelude, &mut session.profiler);
+            if errors.is_empty() {
+                println!("doc example {} ... ok", name);
+                continue;
+            }
+            println!("doc example {} ... FAILED", name);
+            for error in &errors {
+                report(error, path.as_deref(), source, session.error_format);
+            }
+            failed += 1;
+        }
+    }
+    failed == 0
+}
+
+// Runs each test in a process of its own, so a failed assert doesn't stop the rest,
+// or all of them in one process when panics unwind back to the harness.
+fn run_tests(binary: &str, tests: &[String], session: &Session) -> i32 {
+    let (selected, filtered) = filter(tests, session);
+    let plural = if selected.len() == 1 { "" } else { "s" };
+    println!("running {} test{}", selected.len(), plural);
+    let results: Box<dyn Iterator<Item = _>> = match session.panic {
+        Panic::Abort => Box::new(selected.iter().map(|test| run_test(binary, test))),
+        Panic::UnwindLite => Box::new(run_tests_together(binary, &selected).into_iter()),
+    };
+    let mut failures = vec![];
+    for (test, passed, output) in results {
+        if passed {
+            println!("test {} ... ok", test);
+        } else {
+            println!("test {} ... FAILED", test);
+            failures.push((test, output));
+        }
+    }
+    for (test, output) in &failures {
+        println!("\n---- {} ----", test);
+        print!("{}", output);
+    }
+    println!(
+        "\ntest result: {}. {} passed; {} failed; {} filtered out",
+        if failures.is_empty() { "ok" } else { "FAILED" },
+        selected.len() - failures.len(),
+        failures.len(),
+        filtered
+    );
+    if failures.is_empty() {
+        0
+    } else {
+        1
+    }
+}
+
+fn run_test<'a>(binary: &str, test: &'a String) -> (&'a String, bool, String) {
+    let result = Command::new(binary).arg(test).output().unwrap();
+    let output = String::from_utf8_lossy(&result.stdout) + String::from_utf8_lossy(&result.stderr);
+    (test, result.status.success(), output.into_owned())
+}
+
+// The runtime prints a line after each test's output saying whether it passed, so
+// stderr goes to the same pipe to keep panics with their test. A test with no line
+// crashed the process.
+fn run_tests_together<'a>(binary: &str, tests: &[&'a String]) -> Vec<(&'a String, bool, String)> {
+    if tests.is_empty() {
+        return vec![];
+    }
+    let (mut reader, writer) = io::pipe().unwrap();
+    let mut child = Command::new(binary)
+        .args(tests)
+        .stdout(writer.try_clone().unwrap())
+        .stderr(writer)
+        .spawn()
         .unwrap();
-    Command::new("./output").status().unwrap();
+    let mut log = vec![];
+    reader.read_to_end(&mut log).unwrap();
+    child.wait().unwrap();
+    let log = String::from_utf8_lossy(&log);
+    let mut lines = log.lines();
+    let mut results = vec![];
+    for test in tests {
+        let mut output = String::new();
+        let passed = loop {
+            match lines.next() {
+                Some(line) if line == format!("test {} ... ok", test) => break true,
+                Some(line) if line == format!("test {} ... FAILED", test) => break false,
+                Some(line) => output.push_str(&format!("{}\n", line)),
+                None => break false,
+            }
+        };
+        results.push((*test, passed, output));
+    }
+    results
+}
+
+// Each benchmark is run for ten times as many iterations until it takes long enough
+// to time reliably. The harness prints how many nanoseconds the iterations took.
+fn run_benches(binary: &str, benches: &[String], session: &Session) -> i32 {
+    const MIN_TIME: u64 = 100_000_000;
+    const MAX_ITERS: u64 = 1_000_000_000;
+    let (selected, filtered) = filter(benches, session);
+    let plural = if selected.len() == 1 { "" } else { "es" };
+    println!("running {} bench{}", selected.len(), plural);
+    let mut failed = false;
+    for bench in &selected {
+        let mut iters = 1;
+        let time = loop {
+            let result = Command::new(binary)
+                .args([bench.as_str(), &iters.to_string()])
+                .output()
+                .unwrap();
+            // the benchmark may print things itself
+            let stdout = String::from_utf8_lossy(&result.stdout);
+            let time = stdout.lines().last().unwrap_or("").parse::<u64>();
+            match time {
+                Ok(time) if result.status.success() => {
+                    if time >= MIN_TIME || iters >= MAX_ITERS {
+                        break Some(time);
+                    }
+                    iters *= 10;
+                }
+                _ => {
+                    print!("{}", String::from_utf8_lossy(&result.stderr));
+                    break None;
+                }
+            }
+        };
+        match time {
+            Some(time) => println!(
+                "bench {} ... {} ns/iter ({} iterations)",
+                bench,
+                time / iters,
+                iters
+            ),
+            None => {
+                println!("bench {} ... FAILED", bench);
+                failed = true;
+            }
+        }
+    }
+    println!("\n{} filtered out", filtered);
+    if failed {
+        1
+    } else {
+        0
+    }
 }
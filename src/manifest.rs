@@ -0,0 +1,116 @@
+#![deny(clippy::unwrap_used, clippy::expect_used, clippy::panic)]
+
+use std::fs;
+
+pub const MANIFEST: &str = "pl.toml";
+
+// The build settings for a project, read from `pl.toml` in the directory the
+// compiler is run in. Anything given on the command line takes precedence.
+pub struct Manifest {
+    // every `.pl` file in the source roots, which default to `src`
+    pub sources: Vec<String>,
+    pub output: Option<String>,
+    pub backend: Option<String>,
+    pub opt_level: Option<u32>,
+    // libraries to link with, as for `-l`
+    pub libs: Vec<String>,
+}
+
+enum Value {
+    String(String),
+    Int(u32),
+    Array(Vec<String>),
+}
+
+pub fn load(path: &str) -> Result<Manifest, String> {
+    let source =
+        fs::read_to_string(path).map_err(|err| format!("failed to read '{}': {}", path, err))?;
+    let mut roots = vec!["src".to_string()];
+    let mut manifest = Manifest {
+        sources: vec![],
+        output: None,
+        backend: None,
+        opt_level: None,
+        libs: vec![],
+    };
+    for (index, line) in source.lines().enumerate() {
+        let error = |message: &str| format!("{}:{}: {}", path, index + 1, message);
+        let line = strip_comment(line).trim();
+        if line.is_empty() {
+            continue;
+        }
+        let (key, value) = line
+            .split_once('=')
+            .ok_or_else(|| error("expected `key = value`"))?;
+        let value = parse_value(value.trim()).ok_or_else(|| error("invalid value"))?;
+        match (key.trim(), value) {
+            ("roots", Value::Array(value)) => roots = value,
+            ("output", Value::String(value)) => manifest.output = Some(value),
+            ("backend", Value::String(value)) => manifest.backend = Some(value),
+            ("opt-level", Value::Int(value @ (0 | 1))) => manifest.opt_level = Some(value),
+            ("opt-level", _) => return Err(error("`opt-level` must be 0 or 1")),
+            ("libs", Value::Array(value)) => manifest.libs = value,
+            (key @ ("roots" | "libs"), _) => {
+                return Err(error(&format!("`{}` must be a list of strings", key)))
+            }
+            (key @ ("output" | "backend"), _) => {
+                return Err(error(&format!("`{}` must be a string", key)))
+            }
+            (key, _) => return Err(error(&format!("unknown key `{}`", key))),
+        }
+    }
+    for root in &roots {
+        let entries =
+            fs::read_dir(root).map_err(|err| format!("failed to read '{}': {}", root, err))?;
+        let mut sources: Vec<_> = entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "pl"))
+            .map(|path| path.to_string_lossy().into_owned())
+            .collect();
+        // sorted so the output doesn't depend on the order of the directory
+        sources.sort();
+        manifest.sources.extend(sources);
+    }
+    if manifest.sources.is_empty() {
+        return Err(format!("no `.pl` files in {}", roots.join(", ")));
+    }
+    Ok(manifest)
+}
+
+fn strip_comment(line: &str) -> &str {
+    let mut in_string = false;
+    for (index, ch) in line.char_indices() {
+        match ch {
+            '"' => in_string = !in_string,
+            '#' if !in_string => return &line[..index],
+            _ => {}
+        }
+    }
+    line
+}
+
+fn parse_value(value: &str) -> Option<Value> {
+    if let Some(items) = value.strip_prefix('[') {
+        let items = items.strip_suffix(']')?.trim();
+        let items = items.strip_suffix(',').unwrap_or(items);
+        if items.trim().is_empty() {
+            return Some(Value::Array(vec![]));
+        }
+        return items
+            .split(',')
+            .map(|item| parse_string(item.trim()))
+            .collect::<Option<_>>()
+            .map(Value::Array);
+    }
+    if value.starts_with('"') {
+        return parse_string(value).map(Value::String);
+    }
+    value.parse().ok().map(Value::Int)
+}
+
+// Escapes aren't needed for paths and names, so they aren't supported.
+fn parse_string(value: &str) -> Option<String> {
+    let value = value.strip_prefix('"')?.strip_suffix('"')?;
+    (!value.contains(['"', '\\'])).then(|| value.to_string())
+}
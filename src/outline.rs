@@ -0,0 +1,140 @@
+#![deny(clippy::unwrap_used, clippy::expect_used, clippy::panic)]
+
+use crate::{
+    ast::Decl,
+    diagnostic::Diagnostic,
+    lexer::{self, Span},
+    parser,
+    symbols::Symbols,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ItemKind {
+    Func,
+    Struct,
+    Field,
+    Const,
+    Mod,
+}
+
+impl ItemKind {
+    pub fn keyword(&self) -> Option<&str> {
+        match self {
+            ItemKind::Func => Some("func"),
+            ItemKind::Struct => Some("struct"),
+            ItemKind::Field => None,
+            ItemKind::Const => Some("const"),
+            ItemKind::Mod => Some("mod"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Item {
+    pub kind: ItemKind,
+    pub name: String,
+    // what follows the name, e.g. `(w: i32, h: i32) i32` for a function
+    pub detail: String,
+    pub span: Span,
+    pub doc: Option<String>,
+    pub children: Vec<Item>,
+}
+
+// Lists the items declared in a source file. Only parsing has to succeed, so an
+// editor can still show an outline of a file with type errors.
+pub fn outline(source: &str) -> Result<Vec<Item>, Vec<Diagnostic>> {
+    let (tokens, lex_errors) = lexer::lex(source);
+    if !lex_errors.is_empty() {
+        return Err(lex_errors);
+    }
+    let (program, symbols, parse_errors) = parser::parse(&tokens, Symbols::new());
+    if !parse_errors.is_empty() {
+        return Err(parse_errors);
+    }
+    Ok(decl_items(&program.decls, &symbols))
+}
+
+fn decl_items(decls: &[Decl], symbols: &Symbols) -> Vec<Item> {
+    let mut items = vec![];
+    for decl in decls {
+        let item = match decl {
+            Decl::Func(func) => {
+                let name = symbols.get_str(func.name).to_string();
+                let params: Vec<_> = func
+                    .params
+                    .iter()
+                    .map(|param| {
+                        format!(
+                            "{}: {}",
+                            symbols.get_str(param.name),
+                            param.ty.name(symbols)
+                        )
+                    })
+                    .collect();
+                let mut detail = format!("({})", params.join(", "));
+                if let Some(ty) = &func.returns {
+                    detail = format!("{} {}", detail, ty.name(symbols));
+                }
+                Item {
+                    kind: ItemKind::Func,
+                    name,
+                    detail,
+                    span: func.span,
+                    doc: func.doc.clone(),
+                    children: vec![],
+                }
+            }
+            Decl::Struct(struct_decl) => {
+                let name = symbols.get_str(struct_decl.name).to_string();
+                let children = struct_decl
+                    .fields
+                    .iter()
+                    .map(|field| {
+                        let name = symbols.get_str(field.name).to_string();
+                        Item {
+                            kind: ItemKind::Field,
+                            detail: format!(": {}", field.ty.name(symbols)),
+                            name,
+                            span: field.span,
+                            doc: None,
+                            children: vec![],
+                        }
+                    })
+                    .collect();
+                Item {
+                    kind: ItemKind::Struct,
+                    detail: String::new(),
+                    name,
+                    span: struct_decl.span,
+                    doc: struct_decl.doc.clone(),
+                    children,
+                }
+            }
+            Decl::Const(const_decl) => {
+                let name = symbols.get_str(const_decl.name).to_string();
+                Item {
+                    kind: ItemKind::Const,
+                    detail: format!(": {}", const_decl.ty.name(symbols)),
+                    name,
+                    span: const_decl.span,
+                    doc: None,
+                    children: vec![],
+                }
+            }
+            Decl::Mod(mod_decl) => {
+                let name = symbols.get_str(mod_decl.name).to_string();
+                Item {
+                    kind: ItemKind::Mod,
+                    detail: String::new(),
+                    name,
+                    span: mod_decl.span,
+                    doc: None,
+                    children: decl_items(&mod_decl.decls, symbols),
+                }
+            }
+            Decl::StaticAssert(_) | Decl::Use(_) => continue,
+        };
+        items.push(item);
+    }
+    items
+}
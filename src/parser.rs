@@ -1,22 +1,28 @@
+#![deny(clippy::unwrap_used, clippy::expect_used, clippy::panic)]
+
 use std::fmt;
 
 use crate::{
     ast::{
-        Block, Decl, Else, Expr, Func, FuncCall, If, InfixOp, Int, Param, PrefixOp, Program,
-        RefExpr, Stmt, Struct, StructField, StructValue, Ty,
+        Block, Const, Decl, Else, Expr, Func, FuncCall, Harness, If, InfixOp, Int, Mod, Param,
+        PrefixOp, Program, RefExpr, StaticAssert, Stmt, Struct, StructField, StructValue, Ty, Use,
     },
-    symbols::Symbols,
+    codes::Code,
+    diagnostic::Diagnostic,
+    lexer::{self, Span},
+    symbols::{self, Symbols},
     token::{Keyword, Symbol, Token, TokenKind},
-    tokens::{TokenIter, Tokens},
+    tokens::{TokenCursor, Tokens},
 };
 
-pub fn parse<'s, 't>(tokens: &Tokens<'s>) -> (Program, Symbols<'s>, Vec<ParseError<'s>>) {
-    let mut token_iter = tokens.iter();
-    let token = token_iter.next();
+pub fn parse<'s>(
+    tokens: &Tokens<'s>,
+    symbols: Symbols<'s>,
+) -> (Program, Symbols<'s>, Vec<Diagnostic>) {
     let mut parser = Parser {
-        token,
-        token_iter,
-        symbols: Symbols::new(),
+        cursor: tokens.cursor(),
+        source: tokens.source,
+        symbols,
         handled_errors: vec![],
     };
     (
@@ -27,16 +33,17 @@ pub fn parse<'s, 't>(tokens: &Tokens<'s>) -> (Program, Symbols<'s>, Vec<ParseErr
 }
 
 struct Parser<'s, 't> {
-    token_iter: TokenIter<'t, 's>,
-    token: Option<Token<'s>>,
+    cursor: TokenCursor<'t, 's>,
+    source: &'s str,
     symbols: Symbols<'s>,
-    handled_errors: Vec<ParseError<'s>>,
+    handled_errors: Vec<Diagnostic>,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
 enum Prec {
     Dot,
     Ref,
+    Cast,
     Product,
     Sum,
     Compare,
@@ -47,6 +54,15 @@ enum Prec {
 pub struct ParseError<'s> {
     pub token: Option<Token<'s>>,
     pub expected: Expected,
+    // the end of the token before, where a missing token would go
+    pub prev_end: usize,
+}
+
+enum Attribute {
+    ReprC,
+    Harness(Harness),
+    Unsafe,
+    Hidden,
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -57,33 +73,84 @@ pub enum Expected {
     Type,
     RefExpr,
     Decl,
+    Attribute,
 }
 
-impl<'s> fmt::Display for ParseError<'s> {
+impl fmt::Display for Expected {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        if let Some(token) = self.token {
-            write!(
-                f,
-                "syntax error on line {} at token '{}'",
-                token.pos().line,
-                token.str()
-            )?;
-        } else {
-            write!(f, "syntax error, unexpected end of file")?;
-        }
-        write!(f, " expected ")?;
-        match self.expected {
+        match self {
             Expected::Token(token) => match token {
                 TokenKind::Ident => write!(f, "an identifier"),
                 TokenKind::Integer => write!(f, "an integer"),
+                TokenKind::String => write!(f, "a string"),
+                TokenKind::Char => write!(f, "a character"),
                 TokenKind::Keyword(keyword) => write!(f, "the keyword '{}'", keyword.str()),
                 TokenKind::Symbol(symbol) => write!(f, "the symbol '{}'", symbol.str()),
+                TokenKind::Comment => write!(f, "a comment"),
+                TokenKind::Unknown => write!(f, "a valid character"),
             },
             Expected::Expr => write!(f, "an expression"),
             Expected::Stmt => write!(f, "a statement"),
             Expected::Type => write!(f, "a type"),
             Expected::RefExpr => write!(f, "a reference expression"),
             Expected::Decl => write!(f, "a top level declaration"),
+            Expected::Attribute => write!(f, "`repr(c)`, `test`, `bench`, `unsafe` or `hidden`"),
+        }
+    }
+}
+
+impl<'s> ParseError<'s> {
+    pub fn diagnostic(&self, source: &str) -> Diagnostic {
+        let (span, found) = match self.token {
+            Some(token) => (token.span(), format!("unexpected token '{}'", token.str())),
+            None => (
+                Span {
+                    start: source.len(),
+                    end: source.len(),
+                },
+                "unexpected end of file".to_string(),
+            ),
+        };
+        if let Some(TokenKind::Keyword(keyword)) = self.token.map(|token| token.kind) {
+            if keyword.is_reserved() {
+                return Diagnostic::new(
+                    Code::ReservedKeyword,
+                    span,
+                    format!("`{}` is a reserved keyword", keyword.str()),
+                )
+                .with_note(
+                    "it can't be used as a name, as it's kept for future syntax".to_string(),
+                );
+            }
+            // code from before a keyword was added may still use it as a name
+            let expects_name = matches!(
+                self.expected,
+                Expected::Token(TokenKind::Ident) | Expected::Expr | Expected::RefExpr
+            );
+            if expects_name {
+                return Diagnostic::new(
+                    Code::ReservedKeyword,
+                    span,
+                    format!("`{}` is a keyword", keyword.str()),
+                )
+                .with_note("it can't be used as a name".to_string());
+            }
+        }
+        let diagnostic = Diagnostic::new(
+            Code::SyntaxError,
+            span,
+            format!("syntax error, {} expected {}", found, self.expected),
+        );
+        match self.expected {
+            Expected::Token(TokenKind::Symbol(Symbol::Semicolon)) => diagnostic.with_suggestion(
+                Span {
+                    start: self.prev_end,
+                    end: self.prev_end,
+                },
+                ";",
+                "add a `;` at the end of the statement".to_string(),
+            ),
+            _ => diagnostic,
         }
     }
 }
@@ -91,13 +158,49 @@ impl<'s> fmt::Display for ParseError<'s> {
 type ParseResult<'a, T> = Result<T, ParseError<'a>>;
 
 impl<'s, 't> Parser<'s, 't> {
+    fn token(&self) -> Option<Token<'s>> {
+        self.cursor.lookahead(0)
+    }
     fn peek(&self) -> Option<TokenKind> {
-        self.token.map(|token| token.kind)
+        self.peek_nth(0)
+    }
+    fn peek_nth(&self, n: usize) -> Option<TokenKind> {
+        self.cursor.lookahead(n).map(|token| token.kind)
+    }
+    fn next(&mut self) {
+        self.cursor.advance();
     }
-    fn next(&mut self) -> Token<'s> {
-        let last_token = self.token;
-        self.token = self.token_iter.next();
-        last_token.unwrap()
+    // The span of the current token, or an empty span at the end of the input.
+    fn span(&self) -> Span {
+        let prev_end = self.cursor.prev_end();
+        self.token().map_or(
+            Span {
+                start: prev_end,
+                end: prev_end,
+            },
+            |token| token.span(),
+        )
+    }
+    fn at_decl(&self) -> bool {
+        matches!(
+            self.peek(),
+            Some(
+                TokenKind::Keyword(
+                    Keyword::Func
+                        | Keyword::Struct
+                        | Keyword::Const
+                        | Keyword::StaticAssert
+                        | Keyword::Mod
+                        | Keyword::Use
+                ) | TokenKind::Symbol(Symbol::At)
+            )
+        )
+    }
+    // Words like `repr` are only keywords where they're expected, so they can still be
+    // used as names everywhere else.
+    fn at_contextual(&self, word: &str) -> bool {
+        self.token()
+            .is_some_and(|token| token.kind == TokenKind::Ident && token.str() == word)
     }
     fn eat(&mut self, kind: TokenKind) -> bool {
         if self.peek() == Some(kind) {
@@ -108,30 +211,88 @@ impl<'s, 't> Parser<'s, 't> {
         }
     }
     fn expect(&mut self, kind: TokenKind) -> ParseResult<'s, Token<'s>> {
-        if self.peek() == Some(kind) {
-            Ok(self.next())
-        } else {
-            Err(self.unexpected_token(Expected::Token(kind)))
+        match self.token() {
+            Some(token) if token.kind == kind => {
+                self.next();
+                Ok(token)
+            }
+            _ => Err(self.unexpected_token(Expected::Token(kind))),
         }
     }
     fn unexpected_token(&self, expected: Expected) -> ParseError<'s> {
         ParseError {
-            token: self.token,
+            token: self.token(),
             expected,
+            prev_end: self.cursor.prev_end(),
+        }
+    }
+    // A name, optionally qualified by the modules it's in, e.g. `shapes::area`.
+    fn parse_path(&mut self) -> ParseResult<'s, (symbols::Symbol, Span)> {
+        let first = self.expect(TokenKind::Ident)?;
+        let mut span = first.span();
+        if self.peek() != Some(TokenKind::Symbol(Symbol::DoubleColon)) {
+            return Ok((self.symbols.get_symbol(first.str()), span));
         }
+        let mut path = first.str().to_string();
+        while self.eat(TokenKind::Symbol(Symbol::DoubleColon)) {
+            let token = self.expect(TokenKind::Ident)?;
+            path.push_str("::");
+            path.push_str(token.str());
+            span.end = token.span().end;
+        }
+        Ok((self.symbols.get_symbol(path), span))
+    }
+    // Reports a slip that's common when coming from another language, which the parser
+    // then carries on past as if it had been written correctly.
+    fn mistake(&mut self, span: Span, message: &str, replacement: &str, help: &str) {
+        let diagnostic = Diagnostic::new(Code::SyntaxError, span, message.to_string())
+            .with_suggestion(span, replacement, help.to_string());
+        self.handled_errors.push(diagnostic);
     }
+    fn expect_assign(&mut self) -> ParseResult<'s, ()> {
+        self.expect(TokenKind::Symbol(Symbol::Equals))?;
+        if self.peek() == Some(TokenKind::Symbol(Symbol::Equals)) {
+            let span = self.span();
+            self.mistake(
+                span,
+                "`==` isn't an assignment",
+                "",
+                "use `=` to assign a value",
+            );
+            self.next();
+        }
+        Ok(())
+    }
+    // A trailing separator is allowed before the terminator. If a separator is missing
+    // but another item follows, it's reported and the list carries on.
     fn parse_list<T>(
         &mut self,
-        sep: TokenKind,
+        sep: Symbol,
         term: TokenKind,
         f: impl Fn(&mut Parser<'s, 't>) -> ParseResult<'s, T>,
     ) -> ParseResult<'s, Vec<T>> {
         let mut items = vec![];
-        if self.peek() != Some(term) {
+        while self.peek() != Some(term) {
             items.push(f(self)?);
-            while self.eat(sep) {
-                items.push(f(self)?);
+            if self.eat(TokenKind::Symbol(sep)) || self.peek() == Some(term) {
+                continue;
             }
+            // only report a missing separator if another item follows
+            let checkpoint = self.cursor.checkpoint();
+            let handled = self.handled_errors.len();
+            let span = Span {
+                start: self.cursor.prev_end(),
+                end: self.cursor.prev_end(),
+            };
+            let at_item = f(self).is_ok();
+            self.cursor.rollback(checkpoint);
+            self.handled_errors.truncate(handled);
+            if !at_item {
+                break;
+            }
+            let message = format!("expected `{}` between items", sep.str());
+            let help = format!("add a `{}`", sep.str());
+            self.mistake(span, &message, sep.str(), &help);
         }
         self.expect(term)?;
         Ok(items)
@@ -150,8 +311,8 @@ impl<'s, 't> Parser<'s, 't> {
                 ref_expr
             }
             Some(TokenKind::Ident) => {
-                let ident = self.next().str();
-                RefExpr::Ident(self.symbols.get_symbol(ident))
+                let token = self.expect(TokenKind::Ident)?;
+                RefExpr::Ident(self.symbols.get_symbol(token.str()), token.span())
             }
             _ => Err(self.unexpected_token(Expected::RefExpr))?,
         };
@@ -162,11 +323,12 @@ impl<'s, 't> Parser<'s, 't> {
             match self.peek() {
                 Some(TokenKind::Symbol(Symbol::Dot)) => {
                     self.next();
-                    let ident = self.expect(TokenKind::Ident)?.str();
-                    let name = self.symbols.get_symbol(ident);
+                    let token = self.expect(TokenKind::Ident)?;
+                    let name = self.symbols.get_symbol(token.str());
                     left = RefExpr::Field {
                         ref_expr: Box::new(left),
                         name,
+                        span: token.span(),
                     };
                 }
                 _ => break Ok(left),
@@ -178,27 +340,29 @@ impl<'s, 't> Parser<'s, 't> {
             Some(TokenKind::Symbol(Symbol::Asterisk)) => {
                 self.parse_prefix(PrefixOp::Deref, Prec::Ref)?
             }
+            Some(TokenKind::Symbol(Symbol::Tilde)) => {
+                self.parse_prefix(PrefixOp::BitNot, Prec::Ref)?
+            }
             Some(TokenKind::Symbol(Symbol::Ampersand)) => {
                 self.next();
                 Expr::Ref(Box::new(self.parse_ref_expr()?))
             }
             Some(TokenKind::Ident) => {
-                let name = self.next().str();
-                let symbol = self.symbols.get_symbol(name);
+                let (symbol, span) = self.parse_path()?;
                 match self.peek() {
                     Some(TokenKind::Symbol(Symbol::OpenBrace)) => {
-                        self.next();
-                        let args = self.parse_list(
-                            TokenKind::Symbol(Symbol::Comma),
-                            TokenKind::Symbol(Symbol::CloseBrace),
-                            |parser| parser.parse_expr(Prec::Bracket),
-                        )?;
-                        Expr::FuncCall(FuncCall { name: symbol, args })
+                        let (args, args_span) = self.parse_args()?;
+                        Expr::FuncCall(FuncCall {
+                            name: symbol,
+                            args,
+                            span,
+                            args_span,
+                        })
                     }
-                    Some(TokenKind::Symbol(Symbol::OpenCurlyBrace)) => {
+                    Some(TokenKind::Symbol(Symbol::OpenCurlyBrace)) if self.at_struct_literal() => {
                         self.next();
                         let values = self.parse_list(
-                            TokenKind::Symbol(Symbol::Comma),
+                            Symbol::Comma,
                             TokenKind::Symbol(Symbol::CloseCurlyBrace),
                             |parser| {
                                 let name = parser.expect(TokenKind::Ident)?.str();
@@ -211,12 +375,20 @@ impl<'s, 't> Parser<'s, 't> {
                         Expr::InitStruct {
                             name: symbol,
                             values,
+                            span,
                         }
                     }
-                    _ => Expr::Ident(symbol),
+                    _ => Expr::Ident { name: symbol, span },
                 }
             }
-            Some(TokenKind::Integer) => Expr::Integer(self.next().str().parse().unwrap()),
+            // the lexer has already checked the literal fits, and that a char is valid
+            Some(TokenKind::Integer) => {
+                let token = self.expect(TokenKind::Integer)?;
+                Expr::Integer(token.str().parse().unwrap_or(0), token.span())
+            }
+            Some(TokenKind::Char) => {
+                Expr::Char(lexer::char_value(self.expect(TokenKind::Char)?.str()).unwrap_or(0))
+            }
             Some(TokenKind::Keyword(Keyword::True)) => {
                 self.next();
                 Expr::Bool(true)
@@ -225,11 +397,14 @@ impl<'s, 't> Parser<'s, 't> {
                 self.next();
                 Expr::Bool(false)
             }
+            Some(TokenKind::Keyword(Keyword::Null)) => {
+                self.next();
+                Expr::Null
+            }
             Some(TokenKind::Symbol(Symbol::OpenBrace)) => {
                 self.next();
                 let expr = self.parse_expr(Prec::Bracket)?;
                 self.expect(TokenKind::Symbol(Symbol::CloseBrace))?;
-                self.next();
                 expr
             }
             _ => Err(self.unexpected_token(Expected::Expr))?,
@@ -254,13 +429,23 @@ impl<'s, 't> Parser<'s, 't> {
                 Some(TokenKind::Symbol(Symbol::CloseAngleBrace)) if prec >= Prec::Compare => {
                     self.parse_infix(left, InfixOp::GreaterThan, Prec::Compare)?
                 }
+                Some(TokenKind::Keyword(Keyword::As)) if prec >= Prec::Cast => {
+                    let span = self.span();
+                    self.next();
+                    Expr::Cast {
+                        expr: Box::new(left),
+                        ty: self.parse_ty()?,
+                        span,
+                    }
+                }
                 Some(TokenKind::Symbol(Symbol::Dot)) if prec >= Prec::Dot => {
                     self.next();
-                    let name = self.expect(TokenKind::Ident)?.str();
-                    let symbol = self.symbols.get_symbol(name);
+                    let token = self.expect(TokenKind::Ident)?;
+                    let symbol = self.symbols.get_symbol(token.str());
                     Expr::Field {
                         expr: Box::new(left),
                         name: symbol,
+                        span: token.span(),
                     }
                 }
                 _ => break,
@@ -268,22 +453,50 @@ impl<'s, 't> Parser<'s, 't> {
         }
         Ok(left)
     }
+    // After `Name`, a `{` could start a struct literal or the block of an `if` or `while`.
+    // It's a struct literal if it has a field, or is empty and used as a value.
+    fn at_struct_literal(&mut self) -> bool {
+        match (self.peek_nth(1), self.peek_nth(2)) {
+            (Some(TokenKind::Ident), Some(TokenKind::Symbol(Symbol::Colon))) => true,
+            (Some(TokenKind::Symbol(Symbol::CloseCurlyBrace)), _) => {
+                let checkpoint = self.cursor.checkpoint();
+                self.next();
+                self.next();
+                let is_value = matches!(
+                    self.peek(),
+                    Some(TokenKind::Symbol(
+                        Symbol::Semicolon
+                            | Symbol::Comma
+                            | Symbol::CloseBrace
+                            | Symbol::Dot
+                            | Symbol::OpenCurlyBrace
+                    ))
+                );
+                self.cursor.rollback(checkpoint);
+                is_value
+            }
+            _ => false,
+        }
+    }
     fn parse_prefix(&mut self, op: PrefixOp, prec: Prec) -> ParseResult<'s, Expr> {
         self.next();
         let expr = Box::new(self.parse_expr(prec)?);
         Ok(Expr::Prefix { op, expr })
     }
     fn parse_infix(&mut self, left: Expr, op: InfixOp, prec: Prec) -> ParseResult<'s, Expr> {
+        let span = self.span();
         self.next();
         let right = self.parse_expr(prec)?;
         Ok(Expr::Infix {
             left: Box::new(left),
             right: Box::new(right),
             op,
+            span,
         })
     }
     fn parse_if(&mut self) -> ParseResult<'s, If> {
-        let cond = Box::new(self.parse_expr(Prec::Bracket)?);
+        let span = self.expect(TokenKind::Keyword(Keyword::If))?.span();
+        let (cond, cond_span) = self.parse_cond()?;
         let if_block = self.parse_block()?;
         let else_block = if self.eat(TokenKind::Keyword(Keyword::Else)) {
             if self.peek() == Some(TokenKind::Keyword(Keyword::If)) {
@@ -295,87 +508,134 @@ impl<'s, 't> Parser<'s, 't> {
             Else::None
         };
         Ok(If {
-            cond,
+            cond: Box::new(cond),
+            cond_span,
             if_block,
             else_block,
+            span,
         })
     }
+    // Literals have no span of their own, so the condition's is kept for diagnostics.
+    fn parse_cond(&mut self) -> ParseResult<'s, (Expr, Span)> {
+        let start = self.span().start;
+        let cond = self.parse_expr(Prec::Bracket)?;
+        let end = self.cursor.prev_end();
+        Ok((cond, Span { start, end }))
+    }
     fn parse_stmt(&mut self) -> ParseResult<'s, Stmt> {
         Ok(match self.peek() {
-            Some(TokenKind::Keyword(Keyword::If)) => {
-                self.next();
-                Stmt::If(self.parse_if()?)
-            }
+            Some(TokenKind::Keyword(Keyword::If)) => Stmt::If(self.parse_if()?),
             Some(TokenKind::Keyword(Keyword::While)) => {
-                self.next();
-                let cond = self.parse_expr(Prec::Bracket)?;
+                let span = self.expect(TokenKind::Keyword(Keyword::While))?.span();
+                let (cond, cond_span) = self.parse_cond()?;
                 let body = self.parse_block()?;
-                Stmt::While { cond, body }
+                Stmt::While {
+                    cond,
+                    cond_span,
+                    body,
+                    span,
+                }
+            }
+            Some(TokenKind::Keyword(Keyword::Unsafe)) => {
+                let span = self.expect(TokenKind::Keyword(Keyword::Unsafe))?.span();
+                let body = self.parse_block()?;
+                Stmt::Unsafe { body, span }
+            }
+            Some(TokenKind::Ident)
+                if self.at_contextual("let") && self.peek_nth(1) == Some(TokenKind::Ident) =>
+            {
+                let span = self.span();
+                self.mistake(
+                    span,
+                    "variables are declared with `var`, not `let`",
+                    "var",
+                    "use `var`",
+                );
+                self.next();
+                self.parse_var()?
             }
             Some(TokenKind::Keyword(Keyword::Var)) => {
                 self.next();
-                let name = self.expect(TokenKind::Ident)?.str();
-                let symbol = self.symbols.get_symbol(name);
-
-                let ty = if self.peek() == Some(TokenKind::Symbol(Symbol::Colon)) {
-                    self.next();
-                    Some(self.parse_ty()?)
-                } else {
-                    None
-                };
-                let expr = if self.peek() == Some(TokenKind::Symbol(Symbol::Equals)) {
-                    self.next();
-                    Some(self.parse_expr(Prec::Bracket)?)
-                } else {
-                    None
-                };
-                self.expect(TokenKind::Symbol(Symbol::Semicolon))?;
-                Stmt::Let {
-                    ident: symbol,
-                    expr,
-                    ty,
-                }
+                self.parse_var()?
             }
             Some(TokenKind::Keyword(Keyword::Return)) => {
-                self.next();
+                let span = self.expect(TokenKind::Keyword(Keyword::Return))?.span();
                 let expr = if self.peek() == Some(TokenKind::Symbol(Symbol::Semicolon)) {
                     None
                 } else {
                     Some(self.parse_expr(Prec::Bracket)?)
                 };
                 self.expect(TokenKind::Symbol(Symbol::Semicolon))?;
-                Stmt::Return(expr)
+                Stmt::Return { expr, span }
             }
             Some(TokenKind::Ident) => {
-                let name = self.next().str();
-                let symbol = self.symbols.get_symbol(name);
-                let stmt = if self.peek() == Some(TokenKind::Symbol(Symbol::OpenBrace)) {
-                    self.next();
-                    let args = self.parse_list(
-                        TokenKind::Symbol(Symbol::Comma),
-                        TokenKind::Symbol(Symbol::CloseBrace),
-                        |parser| parser.parse_expr(Prec::Bracket),
-                    )?;
-                    Stmt::FuncCall(FuncCall { name: symbol, args })
-                } else {
-                    let ref_expr = self.parse_ref_expr_fields(RefExpr::Ident(symbol))?;
+                let (symbol, span) = self.parse_path()?;
+                let stmt = if self.symbols.get_str(symbol) == "_" {
                     self.expect(TokenKind::Symbol(Symbol::Equals))?;
+                    Stmt::Discard {
+                        expr: self.parse_expr(Prec::Bracket)?,
+                        span,
+                    }
+                } else if self.peek() == Some(TokenKind::Symbol(Symbol::OpenBrace)) {
+                    let (args, args_span) = self.parse_args()?;
+                    Stmt::FuncCall(FuncCall {
+                        name: symbol,
+                        args,
+                        span,
+                        args_span,
+                    })
+                } else {
+                    let ref_expr = self.parse_ref_expr_fields(RefExpr::Ident(symbol, span))?;
+                    self.expect_assign()?;
                     let expr = self.parse_expr(Prec::Bracket)?;
-                    Stmt::Assign { ref_expr, expr }
+                    Stmt::Assign {
+                        ref_expr,
+                        expr,
+                        span,
+                    }
                 };
                 self.expect(TokenKind::Symbol(Symbol::Semicolon))?;
                 stmt
             }
             Some(TokenKind::Symbol(Symbol::Asterisk)) => {
+                let span = self.span();
                 let ref_expr = self.parse_ref_expr()?;
-                self.expect(TokenKind::Symbol(Symbol::Equals))?;
+                self.expect_assign()?;
                 let expr = self.parse_expr(Prec::Bracket)?;
                 self.expect(TokenKind::Symbol(Symbol::Semicolon))?;
-                Stmt::Assign { ref_expr, expr }
+                Stmt::Assign {
+                    ref_expr,
+                    expr,
+                    span,
+                }
             }
             _ => Err(self.unexpected_token(Expected::Stmt))?,
         })
     }
+    fn parse_var(&mut self) -> ParseResult<'s, Stmt> {
+        let token = self.expect(TokenKind::Ident)?;
+        let symbol = self.symbols.get_symbol(token.str());
+
+        let ty = if self.peek() == Some(TokenKind::Symbol(Symbol::Colon)) {
+            self.next();
+            Some(self.parse_ty()?)
+        } else {
+            None
+        };
+        let expr = if self.peek() == Some(TokenKind::Symbol(Symbol::Equals)) {
+            self.next();
+            Some(self.parse_expr(Prec::Bracket)?)
+        } else {
+            None
+        };
+        self.expect(TokenKind::Symbol(Symbol::Semicolon))?;
+        Ok(Stmt::Let {
+            ident: symbol,
+            expr,
+            ty,
+            span: token.span(),
+        })
+    }
     fn parse_basic_ty(&mut self, ty: Ty) -> Ty {
         self.next();
         ty
@@ -389,6 +649,9 @@ impl<'s, 't> Parser<'s, 't> {
             Some(TokenKind::Keyword(Keyword::U8)) => self.parse_basic_ty(Ty::Int(Int::U8)),
             Some(TokenKind::Keyword(Keyword::U16)) => self.parse_basic_ty(Ty::Int(Int::U16)),
             Some(TokenKind::Keyword(Keyword::U32)) => self.parse_basic_ty(Ty::Int(Int::U32)),
+            Some(TokenKind::Keyword(Keyword::Usize)) => self.parse_basic_ty(Ty::Int(Int::Usize)),
+            // a char is a byte, so `char` is another name for `u8`
+            Some(TokenKind::Keyword(Keyword::Char)) => self.parse_basic_ty(Ty::Int(Int::U8)),
 
             Some(TokenKind::Keyword(Keyword::Bool)) => self.parse_basic_ty(Ty::Bool),
 
@@ -396,9 +659,14 @@ impl<'s, 't> Parser<'s, 't> {
                 self.next();
                 Ty::Ref(Box::new(self.parse_ty()?))
             }
+            Some(TokenKind::Symbol(Symbol::Question)) => {
+                self.next();
+                self.expect(TokenKind::Symbol(Symbol::Asterisk))?;
+                Ty::NullableRef(Box::new(self.parse_ty()?))
+            }
             Some(TokenKind::Ident) => {
-                let name = self.next().str();
-                Ty::Struct(self.symbols.get_symbol(name))
+                let (name, span) = self.parse_path()?;
+                Ty::Struct(name, span)
             }
             _ => Err(self.unexpected_token(Expected::Type))?,
         })
@@ -410,7 +678,7 @@ impl<'s, 't> Parser<'s, 't> {
             match self.parse_stmt() {
                 Ok(stmt) => stmts.push(stmt),
                 Err(err) => {
-                    self.handled_errors.push(err);
+                    self.handled_errors.push(err.diagnostic(self.source));
                     loop {
                         match self.peek() {
                             Some(TokenKind::Symbol(Symbol::Semicolon)) => {
@@ -418,10 +686,9 @@ impl<'s, 't> Parser<'s, 't> {
                                 break;
                             }
                             Some(TokenKind::Symbol(Symbol::CloseCurlyBrace)) => break,
-                            Some(TokenKind::Keyword(Keyword::Func | Keyword::Struct)) => {
-                                return Ok(Block { stmts })
-                            }
-                            _ => _ = self.next(),
+                            None => return Ok(Block { stmts }),
+                            _ if self.at_decl() => return Ok(Block { stmts }),
+                            _ => self.next(),
                         }
                     }
                 }
@@ -431,20 +698,41 @@ impl<'s, 't> Parser<'s, 't> {
         Ok(Block { stmts })
     }
     fn parse_func(&mut self) -> ParseResult<'s, Func> {
-        let name = self.expect(TokenKind::Ident)?.str();
-        let symbol = self.symbols.get_symbol(name);
+        let token = self.expect(TokenKind::Ident)?;
+        let symbol = self.symbols.get_symbol(token.str());
         self.expect(TokenKind::Symbol(Symbol::OpenBrace))?;
         let params = self.parse_list(
-            TokenKind::Symbol(Symbol::Comma),
+            Symbol::Comma,
             TokenKind::Symbol(Symbol::CloseBrace),
             |parser| {
-                let name = parser.expect(TokenKind::Ident)?.str();
-                let symbol = parser.symbols.get_symbol(name);
+                let token = parser.expect(TokenKind::Ident)?;
+                let symbol = parser.symbols.get_symbol(token.str());
                 parser.expect(TokenKind::Symbol(Symbol::Colon))?;
                 let ty = parser.parse_ty()?;
-                Ok(Param { name: symbol, ty })
+                Ok(Param {
+                    name: symbol,
+                    ty,
+                    span: token.span(),
+                })
             },
         )?;
+        if let Some(TokenKind::Symbol(symbol @ (Symbol::Arrow | Symbol::Colon))) = self.peek() {
+            let span = self.span();
+            let message = format!("a return type isn't preceded by `{}`", symbol.str());
+            // remove the space before it too
+            let fix = Span {
+                start: self.cursor.prev_end(),
+                end: span.end,
+            };
+            self.handled_errors.push(
+                Diagnostic::new(Code::SyntaxError, span, message).with_suggestion(
+                    fix,
+                    "",
+                    "write the type straight after the parameters".to_string(),
+                ),
+            );
+            self.next();
+        }
         let returns = match self.peek() {
             Some(TokenKind::Symbol(Symbol::OpenCurlyBrace | Symbol::Semicolon)) => None,
             _ => Some(self.parse_ty()?),
@@ -465,55 +753,313 @@ impl<'s, 't> Parser<'s, 't> {
             body,
             params,
             returns,
+            harness: None,
+            is_unsafe: false,
+            is_hidden: false,
+            doc: None,
+            span: token.span(),
         })
     }
     fn parse_struct(&mut self) -> ParseResult<'s, Struct> {
-        let name = self.expect(TokenKind::Ident)?.str();
-        let symbol = self.symbols.get_symbol(name);
+        let token = self.expect(TokenKind::Ident)?;
+        let symbol = self.symbols.get_symbol(token.str());
         self.expect(TokenKind::Symbol(Symbol::OpenCurlyBrace))?;
         let fields = self.parse_list(
-            TokenKind::Symbol(Symbol::Comma),
+            Symbol::Comma,
             TokenKind::Symbol(Symbol::CloseCurlyBrace),
             |parser| {
-                let name = parser.expect(TokenKind::Ident)?.str();
-                let symbol = parser.symbols.get_symbol(name);
+                let token = parser.expect(TokenKind::Ident)?;
+                let symbol = parser.symbols.get_symbol(token.str());
                 parser.expect(TokenKind::Symbol(Symbol::Colon))?;
                 let ty = parser.parse_ty()?;
-                Ok(StructField { name: symbol, ty })
+                Ok(StructField {
+                    name: symbol,
+                    ty,
+                    span: token.span(),
+                })
             },
         )?;
         Ok(Struct {
             fields,
             name: symbol,
+            span: token.span(),
+            repr_c: false,
+            doc: None,
+        })
+    }
+    // Parses the bracketed arguments of a call, returning the span inside the brackets.
+    fn parse_args(&mut self) -> ParseResult<'s, (Vec<Expr>, Span)> {
+        self.expect(TokenKind::Symbol(Symbol::OpenBrace))?;
+        let start = self.span().start;
+        let args = self.parse_list(
+            Symbol::Comma,
+            TokenKind::Symbol(Symbol::CloseBrace),
+            |parser| parser.parse_expr(Prec::Bracket),
+        )?;
+        // less the closing bracket
+        let end = self.cursor.prev_end() - 1;
+        Ok((args, Span { start, end }))
+    }
+    fn parse_attribute(&mut self) -> ParseResult<'s, Attribute> {
+        for (name, harness) in [("test", Harness::Test), ("bench", Harness::Bench)] {
+            if self.at_contextual(name) {
+                self.next();
+                return Ok(Attribute::Harness(harness));
+            }
+        }
+        if self.peek() == Some(TokenKind::Keyword(Keyword::Unsafe)) {
+            self.next();
+            return Ok(Attribute::Unsafe);
+        }
+        if self.at_contextual("hidden") {
+            self.next();
+            return Ok(Attribute::Hidden);
+        }
+        for part in ["repr", "(", "c", ")"] {
+            if self.token().map(|token| token.str()) != Some(part) {
+                return Err(self.unexpected_token(Expected::Attribute));
+            }
+            self.next();
+        }
+        Ok(Attribute::ReprC)
+    }
+    fn parse_const(&mut self) -> ParseResult<'s, Const> {
+        let token = self.expect(TokenKind::Ident)?;
+        let symbol = self.symbols.get_symbol(token.str());
+        self.expect(TokenKind::Symbol(Symbol::Colon))?;
+        let ty = self.parse_ty()?;
+        self.expect(TokenKind::Symbol(Symbol::Equals))?;
+        let expr = self.parse_expr(Prec::Bracket)?;
+        self.expect(TokenKind::Symbol(Symbol::Semicolon))?;
+        Ok(Const {
+            name: symbol,
+            ty,
+            expr,
+            span: token.span(),
+        })
+    }
+    fn parse_static_assert(&mut self) -> ParseResult<'s, StaticAssert> {
+        self.expect(TokenKind::Symbol(Symbol::OpenBrace))?;
+        let start = self.token().map_or(0, |token| token.offset);
+        let cond = self.parse_expr(Prec::Bracket)?;
+        let comma = self.expect(TokenKind::Symbol(Symbol::Comma))?;
+        let message = self.expect(TokenKind::String)?.str();
+        self.expect(TokenKind::Symbol(Symbol::CloseBrace))?;
+        self.expect(TokenKind::Symbol(Symbol::Semicolon))?;
+        Ok(StaticAssert {
+            cond,
+            message: message[1..message.len() - 1].to_string(),
+            span: Span {
+                start,
+                end: comma.offset,
+            },
         })
     }
     fn parse_decl(&mut self) -> ParseResult<'s, Decl> {
+        let doc = self.doc_comment();
+        let mut decl = self.parse_undocumented_decl()?;
+        match &mut decl {
+            Decl::Func(func) => func.doc = doc,
+            Decl::Struct(struct_decl) => struct_decl.doc = doc,
+            _ => {}
+        }
+        Ok(decl)
+    }
+    // Comments are skipped by the lexer, so the lines above the current token are
+    // searched for a `///` comment instead.
+    fn doc_comment(&self) -> Option<String> {
+        let mut lines = self.source[..self.span().start].rsplit('\n');
+        // the comment has to end on the line before
+        if !lines.next().is_some_and(|line| line.trim().is_empty()) {
+            return None;
+        }
+        let mut doc: Vec<_> = lines
+            .map(str::trim)
+            .map_while(|line| line.strip_prefix("///"))
+            .map(|line| line.strip_prefix(' ').unwrap_or(line))
+            .collect();
+        doc.reverse();
+        (!doc.is_empty()).then(|| doc.join("\n"))
+    }
+    fn parse_undocumented_decl(&mut self) -> ParseResult<'s, Decl> {
         match self.peek() {
             Some(TokenKind::Keyword(Keyword::Func)) => {
                 self.next();
                 Ok(Decl::Func(self.parse_func()?))
             }
+            Some(TokenKind::Ident)
+                if self.at_contextual("fn") && self.peek_nth(1) == Some(TokenKind::Ident) =>
+            {
+                let span = self.span();
+                self.mistake(
+                    span,
+                    "functions are declared with `func`, not `fn`",
+                    "func",
+                    "use `func`",
+                );
+                self.next();
+                Ok(Decl::Func(self.parse_func()?))
+            }
             Some(TokenKind::Keyword(Keyword::Struct)) => {
                 self.next();
                 Ok(Decl::Struct(self.parse_struct()?))
             }
+            Some(TokenKind::Symbol(Symbol::At)) => {
+                self.next();
+                match self.parse_attribute()? {
+                    Attribute::ReprC => {
+                        self.expect(TokenKind::Keyword(Keyword::Struct))?;
+                        let struct_decl = self.parse_struct()?;
+                        Ok(Decl::Struct(Struct {
+                            repr_c: true,
+                            ..struct_decl
+                        }))
+                    }
+                    Attribute::Harness(harness) => {
+                        self.expect(TokenKind::Keyword(Keyword::Func))?;
+                        let func = self.parse_func()?;
+                        Ok(Decl::Func(Func {
+                            harness: Some(harness),
+                            ..func
+                        }))
+                    }
+                    Attribute::Unsafe => {
+                        self.expect(TokenKind::Keyword(Keyword::Func))?;
+                        let func = self.parse_func()?;
+                        Ok(Decl::Func(Func {
+                            is_unsafe: true,
+                            ..func
+                        }))
+                    }
+                    Attribute::Hidden => {
+                        self.expect(TokenKind::Keyword(Keyword::Func))?;
+                        let func = self.parse_func()?;
+                        Ok(Decl::Func(Func {
+                            is_hidden: true,
+                            ..func
+                        }))
+                    }
+                }
+            }
+            Some(TokenKind::Keyword(Keyword::Const)) => {
+                self.next();
+                Ok(Decl::Const(self.parse_const()?))
+            }
+            Some(TokenKind::Keyword(Keyword::StaticAssert)) => {
+                self.next();
+                Ok(Decl::StaticAssert(self.parse_static_assert()?))
+            }
+            Some(TokenKind::Keyword(Keyword::Mod)) => {
+                self.next();
+                let token = self.expect(TokenKind::Ident)?;
+                let name = self.symbols.get_symbol(token.str());
+                self.expect(TokenKind::Symbol(Symbol::OpenCurlyBrace))?;
+                let errors = self.handled_errors.len();
+                let decls = self.parse_decls(Some(TokenKind::Symbol(Symbol::CloseCurlyBrace)));
+                // the closing brace may have been skipped while recovering from an error
+                if self.peek().is_some() || self.handled_errors.len() == errors {
+                    self.expect(TokenKind::Symbol(Symbol::CloseCurlyBrace))?;
+                }
+                Ok(Decl::Mod(Mod {
+                    name,
+                    decls,
+                    span: token.span(),
+                }))
+            }
+            Some(TokenKind::Keyword(Keyword::Use)) => {
+                let start = self.span().start;
+                self.next();
+                let (path, span) = self.parse_path()?;
+                let alias = if self.eat(TokenKind::Keyword(Keyword::As)) {
+                    let alias = self.expect(TokenKind::Ident)?.str();
+                    self.symbols.get_symbol(alias)
+                } else {
+                    let path = self.symbols.get_str(path);
+                    let name = path.rsplit("::").next().unwrap_or(path).to_string();
+                    self.symbols.get_symbol(name)
+                };
+                let end = self
+                    .expect(TokenKind::Symbol(Symbol::Semicolon))?
+                    .span()
+                    .end;
+                Ok(Decl::Use(Use {
+                    path,
+                    alias,
+                    span,
+                    stmt_span: Span { start, end },
+                }))
+            }
             _ => Err(self.unexpected_token(Expected::Decl))?,
         }
     }
     fn parse_program(&mut self) -> Program {
+        Program {
+            decls: self.parse_decls(None),
+        }
+    }
+    fn parse_decls(&mut self, term: Option<TokenKind>) -> Vec<Decl> {
         let mut decls = vec![];
-        while self.peek().is_some() {
+        while self.peek().is_some() && self.peek() != term {
+            let start = self.cursor.checkpoint();
             match self.parse_decl() {
                 Ok(decl) => decls.push(decl),
-                Err(err) => loop {
-                    self.handled_errors.push(err);
-                    match self.peek() {
-                        Some(TokenKind::Keyword(Keyword::Func | Keyword::Struct)) => break,
-                        _ => _ = self.next(),
+                Err(err) => {
+                    self.handled_errors.push(err.diagnostic(self.source));
+                    // skip from the start of the declaration, so that its braces are
+                    // matched and recovery stops at the `}` that closes the module
+                    self.cursor.rollback(start);
+                    self.next();
+                    let mut depth = 0usize;
+                    while let Some(kind) = self.peek() {
+                        match kind {
+                            TokenKind::Symbol(Symbol::OpenCurlyBrace) => depth += 1,
+                            TokenKind::Symbol(Symbol::CloseCurlyBrace) => {
+                                if depth == 0 && term == Some(kind) {
+                                    break;
+                                }
+                                depth = depth.saturating_sub(1);
+                            }
+                            _ if self.at_decl() => break,
+                            _ => {}
+                        }
+                        self.next();
                     }
-                },
+                }
             }
         }
-        Program { decls }
+        decls
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer;
+
+    // The names of the top level declarations, after recovering from any errors.
+    fn top_level(source: &str) -> Vec<&'static str> {
+        let (tokens, _) = lexer::lex(source);
+        let (program, _, _) = parse(&tokens, Symbols::new());
+        program
+            .decls
+            .iter()
+            .map(|decl| match decl {
+                Decl::Mod(_) => "mod",
+                Decl::Func(_) => "func",
+                _ => "other",
+            })
+            .collect()
+    }
+
+    #[test]
+    fn recovery_stops_at_the_end_of_a_module() {
+        let sources = [
+            "mod a { func f(x i32) {} } func main() {}",
+            "mod a { struct S { x i32 } } func main() {}",
+            "mod a { const N = 1 } func main() {}",
+        ];
+        for source in sources {
+            assert_eq!(top_level(source), ["mod", "func"], "{}", source);
+        }
     }
 }
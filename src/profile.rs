@@ -0,0 +1,79 @@
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    sync::atomic::{AtomicUsize, Ordering},
+    time::{Duration, Instant},
+};
+
+pub struct CountingAlloc;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+static ALLOCATED_BYTES: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        ALLOCATED_BYTES.fetch_add(layout.size(), Ordering::Relaxed);
+        System.alloc(layout)
+    }
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+pub struct Profiler {
+    enabled: bool,
+    phases: Vec<Phase>,
+}
+
+struct Phase {
+    name: &'static str,
+    duration: Duration,
+    allocations: usize,
+    allocated_bytes: usize,
+}
+
+impl Profiler {
+    pub fn new(enabled: bool) -> Profiler {
+        Profiler {
+            enabled,
+            phases: vec![],
+        }
+    }
+    pub fn time<T>(&mut self, name: &'static str, f: impl FnOnce() -> T) -> T {
+        if !self.enabled {
+            return f();
+        }
+        let allocations = ALLOCATIONS.load(Ordering::Relaxed);
+        let allocated_bytes = ALLOCATED_BYTES.load(Ordering::Relaxed);
+        let start = Instant::now();
+        let result = f();
+        self.phases.push(Phase {
+            name,
+            duration: start.elapsed(),
+            allocations: ALLOCATIONS.load(Ordering::Relaxed) - allocations,
+            allocated_bytes: ALLOCATED_BYTES.load(Ordering::Relaxed) - allocated_bytes,
+        });
+        result
+    }
+    // Reports the phases timed since the last report, so each build is reported
+    // separately in watch mode.
+    pub fn report(&mut self) {
+        if !self.enabled {
+            return;
+        }
+        eprintln!(
+            "{:<10} {:>12} {:>10} {:>12}",
+            "phase", "time", "allocs", "bytes"
+        );
+        for phase in &self.phases {
+            eprintln!(
+                "{:<10} {:>12} {:>10} {:>12}",
+                phase.name,
+                format!("{:.3}ms", phase.duration.as_secs_f64() * 1000.0),
+                phase.allocations,
+                phase.allocated_bytes
+            );
+        }
+        self.phases.clear();
+    }
+}
@@ -1,79 +1,52 @@
 use std::{
-    fmt,
+    fmt, fs,
     io::{self, Write},
     iter::repeat_with,
+    process::Command,
 };
 
 use crate::{
-    ast, ir,
+    ast,
+    backend::{Backend, Capabilities, Constants},
+    decls::Decls,
+    ir,
+    layout::{align_bytes, align_to, field_offsets, size_bytes},
+    lexer::Span,
+    session::Panic,
     symbols::{Symbol, Symbols},
-    ty::{Signedness, Size},
+    target::{self, Os, Target},
+    ty::{self, Signedness, Size},
     typed_ast,
 };
 
 struct Compiler<'a, W: Write> {
+    slots: &'a [u32],
     stack_slots: Vec<Temp>,
+    temp_slots: Vec<Temp>,
     temp_count: u32,
+    allocs: Vec<u8>,
     output: W,
     symbols: &'a Symbols<'a>,
-    program: &'a ast::Program,
+    decls: &'a Decls<'a>,
+    source: &'a str,
+    is_main: bool,
+    // the message for each assert and memory check, shared by the whole module
+    constants: &'a mut Constants,
+    checks: u32,
+    memory_checks: bool,
 }
 
 #[derive(Debug, Clone, Copy)]
 struct Temp(u32);
 
+#[derive(Clone, Copy)]
 enum Value {
     Temp(Temp),
     Const(i64),
 }
 
 #[derive(Debug, Clone, Copy)]
-struct Label(u32);
-
-fn size_bytes(ty: &ir::Ty) -> u32 {
-    match ty {
-        ir::Ty::Bool => 1,
-        ir::Ty::Int(int_ty) => match int_ty.size {
-            Size::B8 => 1,
-            Size::B16 => 2,
-            Size::B32 => 4,
-        },
-        ir::Ty::Ptr => 8,
-        ir::Ty::Struct(fields) => {
-            let mut size = 0;
-            for field in fields {
-                size = align_to(size, align_bytes(&field.ty)) + size_bytes(&field.ty);
-            }
-            size
-        }
-    }
-}
-
-fn align_to(offset: u32, align: u32) -> u32 {
-    (offset + align - 1) & !(align - 1)
-}
-
-fn align_bytes(ty: &ir::Ty) -> u32 {
-    match ty {
-        ir::Ty::Bool => 1,
-        ir::Ty::Int(int_ty) => match int_ty.size {
-            Size::B8 => 1,
-            Size::B16 => 2,
-            Size::B32 => 4,
-        },
-        ir::Ty::Ptr => 8,
-        ir::Ty::Struct(fields) => {
-            let mut max = 0;
-            for field in fields {
-                let align = align_bytes(&field.ty);
-                if align > max {
-                    max = align
-                }
-            }
-            max
-        }
-    }
-}
+pub struct Label(pub u32);
 
 struct TyName<'a> {
     ty: &'a ast::Ty,
@@ -90,9 +63,10 @@ impl<'a> fmt::Display for TyName<'a> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self.ty {
             ast::Ty::Bool => write!(f, "w"),
-            ast::Ty::Ref(_) => write!(f, "l"),
+            ast::Ty::Ref(_) | ast::Ty::NullableRef(_) => write!(f, "l"),
+            ast::Ty::Int(ast::Int::Usize) => write!(f, "l"),
             ast::Ty::Int(_) => write!(f, "w"),
-            ast::Ty::Struct(name) => write!(f, ":{}", self.symbols.get_str(*name)),
+            ast::Ty::Struct(name, _) => write!(f, ":{}", mangle(self.symbols.get_str(*name))),
         }
     }
 }
@@ -118,78 +92,495 @@ impl fmt::Display for Label {
     }
 }
 
+pub struct Qbe {
+    // qbe's name for the target, if it isn't the host
+    target: Option<&'static str>,
+    os: Os,
+}
+
+impl Qbe {
+    pub fn new(target: Option<Target>) -> Result<Qbe, String> {
+        let os = target::os(target);
+        let target = match target.map(|target| target.name) {
+            None => None,
+            Some("x86_64-linux") => Some("amd64_sysv"),
+            Some("aarch64-linux") => Some("arm64"),
+            Some("riscv64-linux") => Some("rv64"),
+            Some("x86_64-macos") => Some("amd64_apple"),
+            Some("aarch64-macos") => Some("arm64_apple"),
+            Some(name) => return Err(format!("the qbe backend can't target '{}'", name)),
+        };
+        Ok(Qbe { target, os })
+    }
+    // Mach-O section names are qualified by their segment.
+    fn frame_section(&self) -> &'static str {
+        match self.os {
+            Os::Linux => "section \"pl_funcs\" \"aw\"",
+            Os::MacOs => "section \"__DATA,pl_funcs\"",
+        }
+    }
+    fn command(&self) -> Command {
+        let mut command = Command::new("qbe/obj/qbe");
+        if let Some(target) = self.target {
+            command.args(["-t", target]);
+        }
+        command
+    }
+}
+
+impl Backend for Qbe {
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            extension: "ssa",
+            needs_assembling: true,
+        }
+    }
+    fn emit_module(
+        &self,
+        output: &mut dyn Write,
+        decls: &Decls,
+        symbols: &Symbols,
+    ) -> io::Result<()> {
+        compile_structs(decls, output, symbols)
+    }
+    fn emit_func<'a>(
+        &self,
+        output: &mut dyn Write,
+        func: &'a ir::Func,
+        decls: &'a Decls<'a>,
+        symbols: &'a Symbols<'a>,
+        source: &str,
+        memory_checks: bool,
+        constants: &mut Constants,
+    ) -> io::Result<()> {
+        compile_func(
+            func,
+            output,
+            symbols,
+            decls,
+            source,
+            memory_checks,
+            constants,
+            self.frame_section(),
+        )
+    }
+    fn emit_constants(&self, output: &mut dyn Write, constants: &Constants) -> io::Result<()> {
+        compile_constants(constants, output)
+    }
+    fn emit_test_harness(
+        &self,
+        output: &mut dyn Write,
+        tests: &[String],
+        panic: Panic,
+    ) -> io::Result<()> {
+        match panic {
+            Panic::Abort => compile_test_harness(tests, self.frame_section(), output),
+            Panic::UnwindLite => {
+                compile_unwinding_test_harness(tests, self.frame_section(), output)
+            }
+        }
+    }
+    fn emit_bench_harness(&self, output: &mut dyn Write, benches: &[String]) -> io::Result<()> {
+        compile_bench_harness(benches, self.frame_section(), output)
+    }
+    fn validate(&self, module: &str) -> Vec<(usize, String)> {
+        // there is nothing to check against if qbe hasn't been built
+        let output = match self.command().args(["-o", "/dev/null", module]).output() {
+            Ok(output) => output,
+            Err(_) => return vec![],
+        };
+        let prefix = format!("qbe:{}:", module);
+        String::from_utf8_lossy(&output.stderr)
+            .lines()
+            .filter_map(|line| {
+                let (line, message) = line.strip_prefix(&prefix)?.split_once(": ")?;
+                Some((line.parse().ok()?, message.to_string()))
+            })
+            .collect()
+    }
+    // Each block is labelled with the line it starts on, and the first block starting on
+    // a line is preceded by the lines up to the next block of its function.
+    fn annotate(&self, module: &str, source: &str) -> String {
+        let source: Vec<_> = source.lines().collect();
+        let lines: Vec<_> = module.lines().collect();
+        let mut annotated = String::new();
+        let mut starts = vec![];
+        let mut shown = vec![];
+        for (index, line) in lines.iter().enumerate() {
+            if line.contains("function ") && line.ends_with('{') {
+                starts = lines[index..]
+                    .iter()
+                    .take_while(|line| **line != "}")
+                    .filter_map(|line| block_line(line))
+                    .collect();
+                starts.sort();
+                starts.dedup();
+                shown.clear();
+            }
+            if let Some(start) = block_line(line) {
+                let end = match shown.contains(&start) {
+                    true => None,
+                    false => starts.iter().find(|end| **end > start).copied(),
+                };
+                shown.push(start);
+                for line in start..end.unwrap_or(start + 1) {
+                    let text = source.get(line - 1).unwrap_or(&"");
+                    annotated.push_str(&format!("# {:>4} | {}\n", line, text));
+                }
+            }
+            annotated.push_str(line);
+            annotated.push('\n');
+        }
+        annotated
+    }
+    // qbe has no way to hide a symbol, so hidden functions are marked with a comment and
+    // hidden in the assembly afterwards. Apple platforms prefix symbols with `_`.
+    fn assemble(&self, module: &str, output: &str) -> bool {
+        let assembled = self
+            .command()
+            .args([module, "-o", output])
+            .status()
+            .unwrap()
+            .success();
+        if !assembled {
+            return false;
+        }
+        let module = fs::read_to_string(module).unwrap();
+        let hidden: String = module
+            .lines()
+            .filter_map(|line| line.strip_prefix("# hidden "))
+            .map(|name| match self.os {
+                Os::Linux => format!(".hidden {}\n", name),
+                Os::MacOs => format!(".private_extern _{}\n", name),
+            })
+            .collect();
+        let mut asm = fs::OpenOptions::new().append(true).open(output).unwrap();
+        asm.write_all(hidden.as_bytes()).unwrap();
+        true
+    }
+}
+
+// The source line of a block's label, which is commented with `Block::describe`.
+fn block_line(line: &str) -> Option<usize> {
+    let (label, comment) = line.split_once("  # ")?;
+    if !label.starts_with('@') {
+        return None;
+    }
+    comment.rsplit_once(" at line ")?.1.parse().ok()
+}
+
+// Names inside modules are qualified with `::`, which qbe doesn't allow, so it's replaced
+// with `.`, which can't appear in an identifier.
+fn mangle(name: &str) -> String {
+    name.replace("::", ".")
+}
+
+// Integers narrower than a word are computed as a word.
+fn int_class(int: ty::Int) -> &'static str {
+    match int.size {
+        Size::Ptr => "l",
+        _ => "w",
+    }
+}
+
+// External functions keep their own name wherever they're declared so they link
+// against C.
+fn link_name(func: &ast::Func, symbols: &Symbols) -> String {
+    let name = symbols.get_str(func.name);
+    match func.body {
+        Some(_) => mangle(name),
+        None => name.rsplit("::").next().unwrap_or(name).to_string(),
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
 pub fn compile_func<'a, W: Write>(
-    func: &ir::Func,
-    output: W,
+    func: &'a ir::Func,
+    mut output: W,
     symbols: &'a Symbols<'a>,
-    program: &ast::Program,
+    decls: &'a Decls<'a>,
+    source: &str,
+    memory_checks: bool,
+    constants: &'a mut Constants,
+    frame_section: &str,
 ) -> io::Result<()> {
+    // the body is buffered so that every alloc can be hoisted into @start, as an
+    // alloc inside a loop grows the stack each iteration
     let mut compiler = Compiler {
+        slots: &func.slots,
         stack_slots: vec![],
+        temp_slots: vec![],
         temp_count: 0,
-        output,
+        allocs: vec![],
+        output: vec![],
         symbols,
-        program,
+        decls,
+        source,
+        is_main: symbols.get_str(func.name) == "main",
+        constants,
+        checks: 0,
+        memory_checks,
     };
     // TODO WHY YOU NEED AST?!?!?
-    let func_ast = program
-        .func_iter()
-        .find(|func_ast| func_ast.name == func.name)
-        .unwrap();
-    write!(compiler.output, "export function ")?;
+    let func_ast = decls.func(func.name).unwrap();
+    write!(output, "export function ")?;
     if let Some(ty) = &func_ast.returns {
-        write!(compiler.output, "{} ", TyName::new(ty, symbols))?;
+        write!(output, "{} ", TyName::new(ty, symbols))?;
+    } else if compiler.is_main {
+        // main without a return type exits successfully
+        write!(output, "w ")?;
     }
-    write!(compiler.output, "${}(", symbols.get_str(func.name))?;
+    write!(output, "${}(", link_name(func_ast, symbols))?;
     let param_temps: Vec<_> = repeat_with(|| compiler.new_temp())
         .take(func_ast.params.len())
         .collect();
     let mut param_iter = param_temps.iter().zip(&func_ast.params);
     if let Some((temp, param)) = param_iter.next() {
-        write!(
-            compiler.output,
-            "{} {}",
-            TyName::new(&param.ty, symbols),
-            temp
-        )?;
+        write!(output, "{} {}", TyName::new(&param.ty, symbols), temp)?;
         for (temp, param) in param_iter {
-            write!(
-                compiler.output,
-                ", {} {}",
-                TyName::new(&param.ty, symbols),
-                temp
-            )?;
+            write!(output, ", {} {}", TyName::new(&param.ty, symbols), temp)?;
         }
     }
-    writeln!(compiler.output, ") {{")?;
-    writeln!(compiler.output, "@start")?;
-    for (temp, ty) in param_temps.iter().zip(&func.params) {
-        let addr = compiler.alloc_ty(&ty)?;
+    writeln!(output, ") {{")?;
+    let mut slot_tys = vec![vec![]; func.vars.len()];
+    for (ty, slot) in func.vars.iter().zip(&func.slots) {
+        slot_tys[*slot as usize].push(ty);
+    }
+    for tys in slot_tys.iter().filter(|tys| !tys.is_empty()) {
+        let addr = compiler.alloc_shared(tys.iter().copied())?;
         compiler.stack_slots.push(addr);
-        compiler.store(Value::Temp(*temp), &ty, Value::Temp(addr))?;
+    }
+    for tys in &func.temps {
+        let addr = compiler.alloc_shared(tys.iter())?;
+        compiler.temp_slots.push(addr);
+    }
+    for (index, (temp, ty)) in param_temps.iter().zip(&func.params).enumerate() {
+        let addr = compiler.stack_slot(typed_ast::Variable(index as u32));
+        compiler.store(Value::Temp(*temp), ty, Value::Temp(addr))?;
     }
     for (id, block) in func.blocks.iter().enumerate() {
-        writeln!(compiler.output, "{}", Label(id as u32))?;
+        let label = Label(id as u32);
+        writeln!(compiler.output, "{}  # {}", label, block.describe(source))?;
         compiler.compile_block(block)?;
     }
-    writeln!(compiler.output, "}}\n")?;
+    writeln!(output, "@start")?;
+    output.write_all(&compiler.allocs)?;
+    output.write_all(&compiler.output)?;
+    writeln!(output, "}}\n")?;
+    if func_ast.is_hidden {
+        writeln!(output, "# hidden {}\n", link_name(func_ast, symbols))?;
+    }
+    let line = func_ast.span.pos(source).line;
+    let name = symbols.get_str(func.name);
+    let link_name = link_name(func_ast, symbols);
+    compile_frame_entry(&mut output, frame_section, &link_name, name, line)
+}
+
+fn compile_constants<W: Write>(constants: &Constants, mut output: W) -> io::Result<()> {
+    for (label, payload) in constants.payloads.iter().enumerate() {
+        writeln!(
+            output,
+            "data $const.{} = {{ b \"{}\", b 0 }}",
+            label, payload
+        )?;
+    }
     Ok(())
 }
 
-pub fn compile_struct<W: Write>(
-    struct_decl: &ast::Struct,
+// Every function has an entry in the `pl_funcs` section, which the runtime uses to
+// name the functions in a stack trace. The linker defines symbols around the section.
+// The labels use `..`, which `mangle` never produces, so they can't clash with a
+// function in a module.
+fn compile_frame_entry<W: Write>(
+    mut output: W,
+    section: &str,
+    link_name: &str,
+    name: &str,
+    line: u32,
+) -> io::Result<()> {
+    writeln!(
+        output,
+        "data ${}..name = {{ b \"{}\", b 0 }}",
+        link_name, name
+    )?;
+    writeln!(
+        output,
+        "{} data ${}..frame = align 8 {{ l ${}, l ${}..name, l {} }}\n",
+        section, link_name, link_name, link_name, line
+    )
+}
+
+// The harness's `main` runs the function named by its first argument, exiting with 2
+// if there is no such function. `run` is given the function's index and name.
+fn compile_harness<W: Write>(
+    names: &[String],
+    args: u32,
+    frame_section: &str,
+    mut output: W,
+    run: impl Fn(&mut W, usize, &str) -> io::Result<()>,
+) -> io::Result<()> {
+    for (index, name) in names.iter().enumerate() {
+        writeln!(output, "data $name.{} = {{ b \"{}\", b 0 }}", index, name)?;
+    }
+    writeln!(output, "\nexport function w $main(w %argc, l %argv) {{")?;
+    writeln!(output, "@start")?;
+    writeln!(output, "  %has_args =w ceqw %argc, {}", args + 1)?;
+    writeln!(output, "  jnz %has_args, @args, @find{}", names.len())?;
+    writeln!(output, "@args")?;
+    writeln!(output, "  %arg =l add %argv, 8")?;
+    writeln!(output, "  %name =l loadl %arg")?;
+    for (index, name) in names.iter().enumerate() {
+        writeln!(output, "@find{}", index)?;
+        writeln!(
+            output,
+            "  %cmp{} =w call $strcmp(l %name, l $name.{})",
+            index, index
+        )?;
+        writeln!(
+            output,
+            "  jnz %cmp{}, @find{}, @run{}",
+            index,
+            index + 1,
+            index
+        )?;
+        writeln!(output, "@run{}", index)?;
+        run(&mut output, index, &mangle(name))?;
+    }
+    writeln!(output, "@find{}", names.len())?;
+    writeln!(output, "  ret 2")?;
+    writeln!(output, "}}\n")?;
+    // the trace stops at `main`, which isn't in the source
+    compile_frame_entry(output, frame_section, "main", "main", 0)
+}
+
+fn compile_test_harness<W: Write>(
+    tests: &[String],
+    frame_section: &str,
+    output: W,
+) -> io::Result<()> {
+    compile_harness(tests, 1, frame_section, output, |output, _, test| {
+        writeln!(output, "  call ${}()", test)?;
+        writeln!(output, "  ret 0")
+    })
+}
+
+// The runtime runs the tests named by the arguments from a table of each test's name
+// and function, recovering from panics with longjmp.
+fn compile_unwinding_test_harness<W: Write>(
+    tests: &[String],
+    frame_section: &str,
+    mut output: W,
+) -> io::Result<()> {
+    for (index, test) in tests.iter().enumerate() {
+        writeln!(output, "data $name.{} = {{ b \"{}\", b 0 }}", index, test)?;
+    }
+    // `..` keeps the table from clashing with a test called `tests`
+    write!(output, "data $tests..table = {{")?;
+    for (index, test) in tests.iter().enumerate() {
+        write!(output, " l $name.{}, l ${},", index, mangle(test))?;
+    }
+    writeln!(output, " l 0 }}")?;
+    writeln!(output, "\nexport function w $main(w %argc, l %argv) {{")?;
+    writeln!(output, "@start")?;
+    writeln!(
+        output,
+        "  %failed =w call $pl_run_tests(l $tests..table, w %argc, l %argv)"
+    )?;
+    writeln!(output, "  ret %failed")?;
+    writeln!(output, "}}\n")?;
+    compile_frame_entry(output, frame_section, "main", "main", 0)
+}
+
+// The second argument is the number of iterations, and the time they took in
+// nanoseconds is printed by the runtime.
+fn compile_bench_harness<W: Write>(
+    benches: &[String],
+    frame_section: &str,
+    output: W,
+) -> io::Result<()> {
+    compile_harness(benches, 2, frame_section, output, |output, index, bench| {
+        writeln!(output, "  %iters_arg{} =l add %argv, 16", index)?;
+        writeln!(output, "  %iters_str{} =l loadl %iters_arg{}", index, index)?;
+        writeln!(
+            output,
+            "  %iters{} =l call $atol(l %iters_str{})",
+            index, index
+        )?;
+        writeln!(output, "  %start{} =l call $pl_now()", index)?;
+        writeln!(output, "@loop{}", index)?;
+        writeln!(
+            output,
+            "  %left{} =l phi @run{} %iters{}, @body{} %next{}",
+            index, index, index, index, index
+        )?;
+        writeln!(
+            output,
+            "  jnz %left{}, @body{}, @done{}",
+            index, index, index
+        )?;
+        writeln!(output, "@body{}", index)?;
+        writeln!(output, "  call ${}()", bench)?;
+        writeln!(output, "  %next{} =l sub %left{}, 1", index, index)?;
+        writeln!(output, "  jmp @loop{}", index)?;
+        writeln!(output, "@done{}", index)?;
+        writeln!(output, "  %end{} =l call $pl_now()", index)?;
+        writeln!(
+            output,
+            "  %time{} =l sub %end{}, %start{}",
+            index, index, index
+        )?;
+        writeln!(output, "  call $pl_print_time(l %time{})", index)?;
+        writeln!(output, "  ret 0")
+    })
+}
+
+pub fn compile_structs<W: Write>(
+    decls: &Decls,
     mut output: W,
     symbols: &Symbols,
 ) -> io::Result<()> {
-    write!(output, "type :{} = {{ ", symbols.get_str(struct_decl.name))?;
+    // qbe requires a type to be defined before it is used in another type
+    let mut done = vec![];
+    for struct_decl in decls.program.struct_iter() {
+        compile_struct(struct_decl, decls, &mut output, symbols, &mut done)?;
+    }
+    Ok(())
+}
+
+fn compile_struct<W: Write>(
+    struct_decl: &ast::Struct,
+    decls: &Decls,
+    output: &mut W,
+    symbols: &Symbols,
+    done: &mut Vec<Symbol>,
+) -> io::Result<()> {
+    if done.contains(&struct_decl.name) {
+        return Ok(());
+    }
+    done.push(struct_decl.name);
     for field in &struct_decl.fields {
-        write!(output, "{}, ", TyName::new(&field.ty, symbols))?;
+        if let ast::Ty::Struct(name, _) = &field.ty {
+            let field_struct = decls.struct_decl(*name).unwrap();
+            compile_struct(field_struct, decls, output, symbols, done)?;
+        }
+    }
+    write!(
+        output,
+        "type :{} = {{ ",
+        mangle(symbols.get_str(struct_decl.name))
+    )?;
+    for field in &struct_decl.fields {
+        match &field.ty {
+            ast::Ty::Bool | ast::Ty::Int(ast::Int::I8 | ast::Int::U8) => write!(output, "b, ")?,
+            ast::Ty::Int(ast::Int::I16 | ast::Int::U16) => write!(output, "h, ")?,
+            ty => write!(output, "{}, ", TyName::new(ty, symbols))?,
+        }
     }
     writeln!(output, "}}\n")
 }
 
 impl<'a, W: Write> Compiler<'a, W> {
-    fn compile_block<'s>(&mut self, block: &ir::Block) -> io::Result<()> {
+    fn compile_block(&mut self, block: &ir::Block) -> io::Result<()> {
         for stmt in &block.stmts {
             self.compile_stmt(stmt)?;
         }
@@ -199,6 +590,7 @@ impl<'a, W: Write> Compiler<'a, W> {
                     let temp = self.compile_expr(expr)?;
                     writeln!(self.output, "  ret {}", temp)?;
                 }
+                None if self.is_main => writeln!(self.output, "  ret 0")?,
                 None => writeln!(self.output, "  ret")?,
             },
             ir::Branch::Static(target) => writeln!(self.output, "  jmp {}", Label(target.0))?,
@@ -207,7 +599,7 @@ impl<'a, W: Write> Compiler<'a, W> {
                 if_true,
                 if_false,
             } => {
-                let temp = self.compile_expr(&expr)?;
+                let temp = self.compile_expr(expr)?;
                 writeln!(
                     self.output,
                     "  jnz {}, {}, {}",
@@ -227,32 +619,76 @@ impl<'a, W: Write> Compiler<'a, W> {
             _ => panic!(),
         };
         let temp = self.new_temp();
-        writeln!(self.output, "  {} =l alloc{} {}", temp, align, size)?;
+        writeln!(self.allocs, "  {} =l alloc{} {}", temp, align, size)?;
         Ok(temp)
     }
-    fn alloc_ty(&mut self, ty: &ir::Ty) -> io::Result<Temp> {
-        self.alloc_size(size_bytes(ty), align_bytes(ty))
+    // allocates a slot large enough for any of the given types
+    fn alloc_shared<'b>(
+        &mut self,
+        tys: impl Iterator<Item = &'b ir::Ty> + Clone,
+    ) -> io::Result<Temp> {
+        let size = tys.clone().map(size_bytes).max().unwrap();
+        let align = tys.map(align_bytes).max().unwrap();
+        self.alloc_size(size, align)
+    }
+    fn stack_slot(&self, var: typed_ast::Variable) -> Temp {
+        self.stack_slots[self.slots[var.0 as usize] as usize]
     }
     fn compile_stmt(&mut self, stmt: &ir::Stmt) -> io::Result<()> {
         match stmt {
-            ir::Stmt::Alloc(ty) => {
-                let temp = self.alloc_ty(&ty)?;
-                self.stack_slots.push(temp);
-            }
             ir::Stmt::Assign { ref_expr, ty, expr } => {
                 let addr = self.compile_ref_expr(ref_expr)?;
                 let temp = self.compile_expr(expr)?;
-                self.store(temp, &ty, addr)?;
+                self.store(temp, ty, addr)?;
             }
             ir::Stmt::FuncCall(func_call) => {
-                let returns = self.compile_func_call(func_call)?;
-                if returns.is_some() {
-                    panic!()
-                }
+                self.compile_func_call(func_call)?;
+            }
+            ir::Stmt::Discard(expr) => {
+                self.compile_expr(expr)?;
+            }
+            ir::Stmt::Assert { expr, span } => {
+                let temp = self.compile_expr(expr)?;
+                let line = span.pos(self.source).line;
+                let cond = &self.source[span.start..span.end];
+                let message = format!("assertion failed on line {}: `{}`", line, cond);
+                self.compile_check(&temp, message)?;
             }
         };
         Ok(())
     }
+    // Panics with the message unless the value is non-zero.
+    fn compile_check(&mut self, value: &Value, message: String) -> io::Result<()> {
+        let index = self.checks;
+        self.checks += 1;
+        writeln!(
+            self.output,
+            "  jnz {}, @check{}.ok, @check{}.failed",
+            value, index, index
+        )?;
+        writeln!(self.output, "@check{}.failed", index)?;
+        // `pl_panic` is in the runtime and doesn't return
+        let label = self.constants.intern(message);
+        writeln!(self.output, "  call $pl_panic(l $const.{})", label)?;
+        writeln!(self.output, "@check{}.ok", index)?;
+        Ok(())
+    }
+    // Under `--checks=memory`, the address of every load or store through a reference
+    // is checked before it's used.
+    fn check_addr(&mut self, addr: &Value, span: Span) -> io::Result<()> {
+        if self.memory_checks {
+            let line = span.pos(self.source).line;
+            let message = format!("null reference used on line {}", line);
+            self.check_not_null(addr, message)?;
+        }
+        Ok(())
+    }
+    fn check_not_null(&mut self, addr: &Value, message: String) -> io::Result<()> {
+        // jnz only looks at the low word of a long
+        let temp = self.new_temp();
+        writeln!(self.output, "  {} =w cnel {}, 0", temp, addr)?;
+        self.compile_check(&Value::Temp(temp), message)
+    }
     fn compile_expr(&mut self, expr: &ir::Expr) -> io::Result<Value> {
         Ok(match expr {
             ir::Expr::Int(value) => Value::Const(*value),
@@ -266,54 +702,119 @@ impl<'a, W: Write> Compiler<'a, W> {
             } => {
                 let left_temp = self.compile_expr(left)?;
                 let right_temp = self.compile_expr(right)?;
-                let op = match bin_op {
-                    typed_ast::BinaryOp::Add => "add",
-                    typed_ast::BinaryOp::Subtract => "sub",
-                    typed_ast::BinaryOp::Multiply => "mul",
+                let class = int_class(*ty);
+                let (op, result) = match bin_op {
+                    typed_ast::BinaryOp::Add => ("add".to_string(), class),
+                    typed_ast::BinaryOp::Subtract => ("sub".to_string(), class),
+                    typed_ast::BinaryOp::Multiply => ("mul".to_string(), class),
                     typed_ast::BinaryOp::Divide => match ty.signedness {
-                        Signedness::Signed => "div",
-                        Signedness::Unsigned => "udiv",
+                        Signedness::Signed => ("div".to_string(), class),
+                        Signedness::Unsigned => ("udiv".to_string(), class),
                     },
+                    // comparisons give a word whatever they compare
                     typed_ast::BinaryOp::LessThan => match ty.signedness {
-                        Signedness::Signed => "csltw",
-                        Signedness::Unsigned => "cultw",
+                        Signedness::Signed => (format!("cslt{}", class), "w"),
+                        Signedness::Unsigned => (format!("cult{}", class), "w"),
                     },
                     typed_ast::BinaryOp::GreaterThan => match ty.signedness {
-                        Signedness::Signed => "csgtw",
-                        Signedness::Unsigned => "cugtw",
+                        Signedness::Signed => (format!("csgt{}", class), "w"),
+                        Signedness::Unsigned => (format!("cugt{}", class), "w"),
                     },
                 };
                 let temp = self.new_temp();
                 writeln!(
                     self.output,
-                    "  {} =w {} {}, {}",
-                    temp, op, left_temp, right_temp
+                    "  {} ={} {} {}, {}",
+                    temp, result, op, left_temp, right_temp
+                )?;
+                Value::Temp(temp)
+            }
+            ir::Expr::BitNot { expr, ty } => {
+                let value = self.compile_expr(expr)?;
+                let mask = match (ty.signedness, ty.size) {
+                    (Signedness::Unsigned, Size::B8) => 0xff,
+                    (Signedness::Unsigned, Size::B16) => 0xffff,
+                    _ => -1,
+                };
+                let temp = self.new_temp();
+                let class = int_class(*ty);
+                writeln!(self.output, "  {} ={} xor {}, {}", temp, class, value, mask)?;
+                Value::Temp(temp)
+            }
+            ir::Expr::Intrinsic { intrinsic, arg, ty } => {
+                let value = self.compile_expr(arg)?;
+                self.compile_intrinsic(*intrinsic, value, *ty)?
+            }
+            ir::Expr::Atomic { op, args, ty, span } => {
+                let mut values = vec![];
+                for arg in args {
+                    values.push(self.compile_expr(arg)?);
+                }
+                self.check_addr(&values[0], *span)?;
+                self.compile_atomic(*op, &values, *ty)?
+            }
+            ir::Expr::PtrOffset {
+                ptr,
+                offset,
+                ty,
+                offset_ty,
+                op,
+            } => {
+                let ptr = self.compile_expr(ptr)?;
+                let offset = self.compile_expr(offset)?;
+                let ext = match (offset_ty.signedness, offset_ty.size) {
+                    (_, Size::Ptr) => "copy",
+                    (Signedness::Signed, _) => "extsw",
+                    (Signedness::Unsigned, _) => "extuw",
+                };
+                let op = match op {
+                    typed_ast::BinaryOp::Add => "add",
+                    typed_ast::BinaryOp::Subtract => "sub",
+                    _ => panic!(),
+                };
+                let offset_temp = self.new_temp();
+                writeln!(self.output, "  {} =l {} {}", offset_temp, ext, offset)?;
+                let bytes_temp = self.new_temp();
+                writeln!(
+                    self.output,
+                    "  {} =l mul {}, {}",
+                    bytes_temp,
+                    offset_temp,
+                    size_bytes(ty)
                 )?;
+                let temp = self.new_temp();
+                writeln!(self.output, "  {} =l {} {}, {}", temp, op, ptr, bytes_temp)?;
                 Value::Temp(temp)
             }
             ir::Expr::Load { var, ty } => {
-                let temp = self.stack_slots[var.0 as usize];
-                self.load(&ty, Value::Temp(temp))?
+                let temp = self.stack_slot(*var);
+                self.load(ty, Value::Temp(temp))?
             }
             ir::Expr::Ref(ref_expr) => self.compile_ref_expr(ref_expr)?,
-            ir::Expr::Deref { expr, ty } => {
+            ir::Expr::Unwrap { expr, span } => {
+                let addr = self.compile_expr(expr)?;
+                let line = span.pos(self.source).line;
+                let message = format!("unwrapped a null reference on line {}", line);
+                self.check_not_null(&addr, message)?;
+                addr
+            }
+            ir::Expr::IsNull(expr) => {
+                let addr = self.compile_expr(expr)?;
+                let temp = self.new_temp();
+                writeln!(self.output, "  {} =w ceql {}, 0", temp, addr)?;
+                Value::Temp(temp)
+            }
+            ir::Expr::Deref { expr, ty, span } => {
                 let temp = self.compile_expr(expr)?;
-                self.load(&ty, temp)?
+                self.check_addr(&temp, *span)?;
+                self.load(ty, temp)?
             }
             ir::Expr::FuncCall(func_call) => {
                 let temp = self.compile_func_call(func_call)?.unwrap();
                 Value::Temp(temp)
             }
-            ir::Expr::InitStruct(values) => {
-                let size = values.iter().fold(0, |size, value| {
-                    align_to(size, align_bytes(&value.ty)) + size_bytes(&value.ty)
-                });
-                let align = values
-                    .iter()
-                    .map(|value| align_bytes(&value.ty))
-                    .max()
-                    .unwrap_or(0);
-                let temp = self.alloc_size(size, align)?;
+            ir::Expr::InitStruct { values, temp } => {
+                let temp = self.temp_slots[*temp as usize];
                 let mut offset = 0;
                 for value in values {
                     offset = align_to(offset, align_bytes(&value.ty));
@@ -328,20 +829,177 @@ impl<'a, W: Write> Compiler<'a, W> {
             ir::Expr::Field { expr, fields, name } => {
                 let struct_addr = self.compile_expr(expr)?;
                 let (field_addr, field_ty) = self.field_addr(struct_addr, fields, *name)?;
-                let field = self.load(field_ty, Value::Temp(field_addr))?;
-                field
+                self.load(field_ty, Value::Temp(field_addr))?
             }
         })
     }
+    // libgcc only has 32 bit versions of these, so smaller values are zero extended
+    fn compile_intrinsic(
+        &mut self,
+        intrinsic: typed_ast::Intrinsic,
+        value: Value,
+        ty: ty::Int,
+    ) -> io::Result<Value> {
+        let bits = match ty.size {
+            Size::B8 => 8,
+            Size::B16 => 16,
+            Size::B32 => return self.compile_intrinsic32(intrinsic, value, 32),
+            Size::Ptr => return self.compile_intrinsic64(intrinsic, value),
+        };
+        let temp = self.new_temp();
+        let mask = (1 << bits) - 1;
+        writeln!(self.output, "  {} =w and {}, {}", temp, value, mask)?;
+        self.compile_intrinsic32(intrinsic, Value::Temp(temp), bits)
+    }
+    fn compile_intrinsic32(
+        &mut self,
+        intrinsic: typed_ast::Intrinsic,
+        value: Value,
+        bits: i64,
+    ) -> io::Result<Value> {
+        let func = match intrinsic {
+            typed_ast::Intrinsic::Popcount => return self.call_libgcc("__popcountsi2", value),
+            typed_ast::Intrinsic::Bswap if bits == 8 => return Ok(value),
+            typed_ast::Intrinsic::Bswap => {
+                let swapped = self.call_libgcc("__bswapsi2", value)?;
+                let temp = self.new_temp();
+                writeln!(self.output, "  {} =w shr {}, {}", temp, swapped, 32 - bits)?;
+                return Ok(Value::Temp(temp));
+            }
+            typed_ast::Intrinsic::Ctz => "__ctzsi2",
+            typed_ast::Intrinsic::Clz => "__clzsi2",
+        };
+        // libgcc leaves zero undefined, so it's replaced with one and the count is corrected
+        let is_zero = self.new_temp();
+        writeln!(self.output, "  {} =w ceqw {}, 0", is_zero, value)?;
+        let non_zero = self.new_temp();
+        writeln!(self.output, "  {} =w or {}, {}", non_zero, value, is_zero)?;
+        let count = self.call_libgcc(func, Value::Temp(non_zero))?;
+        let adjust = self.new_temp();
+        let result = self.new_temp();
+        if let typed_ast::Intrinsic::Ctz = intrinsic {
+            // ctz(1) is 0 but ctz(0) should be the width
+            writeln!(self.output, "  {} =w mul {}, {}", adjust, is_zero, bits)?;
+            writeln!(self.output, "  {} =w add {}, {}", result, count, adjust)?;
+        } else {
+            // clz(1) is one less than clz(0), and both count the unused upper bits
+            writeln!(self.output, "  {} =w sub {}, {}", adjust, count, 32 - bits)?;
+            writeln!(self.output, "  {} =w add {}, {}", result, adjust, is_zero)?;
+        }
+        Ok(Value::Temp(result))
+    }
+    // The `di` versions in libgcc take a long, but other than `__bswapdi2` still return
+    // an int, which is extended back to a long.
+    fn compile_intrinsic64(
+        &mut self,
+        intrinsic: typed_ast::Intrinsic,
+        value: Value,
+    ) -> io::Result<Value> {
+        let func = match intrinsic {
+            typed_ast::Intrinsic::Bswap => {
+                let temp = self.new_temp();
+                writeln!(self.output, "  {} =l call $__bswapdi2(l {})", temp, value)?;
+                return Ok(Value::Temp(temp));
+            }
+            typed_ast::Intrinsic::Popcount => "__popcountdi2",
+            typed_ast::Intrinsic::Ctz => "__ctzdi2",
+            typed_ast::Intrinsic::Clz => "__clzdi2",
+        };
+        let count = self.new_temp();
+        let result = self.new_temp();
+        if let typed_ast::Intrinsic::Popcount = intrinsic {
+            writeln!(self.output, "  {} =w call ${}(l {})", count, func, value)?;
+            writeln!(self.output, "  {} =l extuw {}", result, count)?;
+            return Ok(Value::Temp(result));
+        }
+        // zero is replaced with one as for 32 bits, but there are no unused upper bits
+        let is_zero = self.new_temp();
+        writeln!(self.output, "  {} =l ceql {}, 0", is_zero, value)?;
+        let non_zero = self.new_temp();
+        writeln!(self.output, "  {} =l or {}, {}", non_zero, value, is_zero)?;
+        writeln!(self.output, "  {} =w call ${}(l {})", count, func, non_zero)?;
+        let wide = self.new_temp();
+        writeln!(self.output, "  {} =l extuw {}", wide, count)?;
+        let adjust = match intrinsic {
+            typed_ast::Intrinsic::Ctz => {
+                let adjust = self.new_temp();
+                writeln!(self.output, "  {} =l mul {}, 64", adjust, is_zero)?;
+                adjust
+            }
+            _ => is_zero,
+        };
+        writeln!(self.output, "  {} =l add {}, {}", result, wide, adjust)?;
+        Ok(Value::Temp(result))
+    }
+    // libatomic has a version of each function for every size, which take the memory
+    // order last, or the orders for success and failure for a compare exchange. 5 is
+    // sequentially consistent. Like C, values narrower than a word come
+    // back with their upper bits undefined.
+    fn compile_atomic(
+        &mut self,
+        op: typed_ast::AtomicOp,
+        args: &[Value],
+        ty: ty::Int,
+    ) -> io::Result<Value> {
+        let bytes = size_bytes(&ir::Ty::Int(ty));
+        let class = int_class(ty);
+        let temp = self.new_temp();
+        match op {
+            typed_ast::AtomicOp::Load => writeln!(
+                self.output,
+                "  {} ={} call $__atomic_load_{}(l {}, w 5)",
+                temp, class, bytes, args[0]
+            )?,
+            typed_ast::AtomicOp::Store => {
+                writeln!(
+                    self.output,
+                    "  call $__atomic_store_{}(l {}, {} {}, w 5)",
+                    bytes, args[0], class, args[1]
+                )?;
+                return Ok(Value::Const(0));
+            }
+            typed_ast::AtomicOp::Add => writeln!(
+                self.output,
+                "  {} ={} call $__atomic_fetch_add_{}(l {}, {} {}, w 5)",
+                temp, class, bytes, args[0], class, args[1]
+            )?,
+            typed_ast::AtomicOp::CompareExchange => {
+                // the expected value is passed by reference, and overwritten on failure
+                let expected = self.alloc_size(bytes, bytes)?;
+                self.store(args[1], &ir::Ty::Int(ty), Value::Temp(expected))?;
+                writeln!(
+                    self.output,
+                    "  {} =w call $__atomic_compare_exchange_{}(l {}, l {}, {} {}, w 5, w 5)",
+                    temp, bytes, args[0], expected, class, args[2]
+                )?;
+                let result = self.new_temp();
+                writeln!(self.output, "  {} =w extub {}", result, temp)?;
+                return Ok(Value::Temp(result));
+            }
+        }
+        let ext = match (ty.signedness, ty.size) {
+            (Signedness::Signed, Size::B8) => "extsb",
+            (Signedness::Signed, Size::B16) => "extsh",
+            (Signedness::Unsigned, Size::B8) => "extub",
+            (Signedness::Unsigned, Size::B16) => "extuh",
+            _ => return Ok(Value::Temp(temp)),
+        };
+        let result = self.new_temp();
+        writeln!(self.output, "  {} =w {} {}", result, ext, temp)?;
+        Ok(Value::Temp(result))
+    }
+    fn call_libgcc(&mut self, func: &str, value: Value) -> io::Result<Value> {
+        let temp = self.new_temp();
+        writeln!(self.output, "  {} =w call ${}(w {})", temp, func, value)?;
+        Ok(Value::Temp(temp))
+    }
     fn field_addr<'b>(
         &mut self,
         struct_addr: Value,
         fields: &'b [ir::StructField],
         name: Symbol,
     ) -> io::Result<(Temp, &'b ir::Ty)> {
-        let mut offset = 0;
-        for field in fields {
-            offset = align_to(offset, align_bytes(&field.ty));
+        for (field, offset) in fields.iter().zip(field_offsets(fields)) {
             if field.name == name {
                 let field_addr = self.new_temp();
                 writeln!(
@@ -351,14 +1009,17 @@ impl<'a, W: Write> Compiler<'a, W> {
                 )?;
                 return Ok((field_addr, &field.ty));
             }
-            offset += size_bytes(&field.ty);
         }
         panic!()
     }
     fn compile_ref_expr(&mut self, ref_expr: &ir::RefExpr) -> io::Result<Value> {
         match ref_expr {
-            ir::RefExpr::Variable(var) => Ok(Value::Temp(self.stack_slots[var.0 as usize])),
-            ir::RefExpr::Deref(expr) => self.compile_expr(expr),
+            ir::RefExpr::Variable(var) => Ok(Value::Temp(self.stack_slot(*var))),
+            ir::RefExpr::Deref(expr, span) => {
+                let addr = self.compile_expr(expr)?;
+                self.check_addr(&addr, *span)?;
+                Ok(addr)
+            }
             ir::RefExpr::Field {
                 ref_expr,
                 fields,
@@ -371,31 +1032,23 @@ impl<'a, W: Write> Compiler<'a, W> {
         }
     }
     fn compile_func_call(&mut self, func_call: &ir::FuncCall) -> io::Result<Option<Temp>> {
-        let func = self
-            .program
-            .func_iter()
-            .find(|func| func.name == func_call.name)
-            .unwrap();
+        let func = self.decls.func(func_call.name).unwrap();
 
         let values: Vec<_> = func_call
             .args
             .iter()
-            .map(|expr| self.compile_expr(&expr).unwrap())
+            .map(|expr| self.compile_expr(expr).unwrap())
             .collect();
         write!(self.output, "  ")?;
         let temp = if let Some(ty) = func.returns.as_ref() {
             let temp = self.new_temp();
-            write!(self.output, "{} ={} ", temp, TyName::new(&ty, self.symbols))?;
+            write!(self.output, "{} ={} ", temp, TyName::new(ty, self.symbols))?;
             Some(temp)
         } else {
             None
         };
 
-        write!(
-            self.output,
-            "call ${}(",
-            self.symbols.get_str(func_call.name)
-        )?;
+        write!(self.output, "call ${}(", link_name(func, self.symbols))?;
         let mut value_iter = values.iter().zip(&func.params);
         if let Some((temp, param)) = value_iter.next() {
             write!(
@@ -414,7 +1067,10 @@ impl<'a, W: Write> Compiler<'a, W> {
             }
         }
         writeln!(self.output, ")")?;
-        Ok(temp)
+        match (temp, &func.returns) {
+            (Some(temp), Some(ast::Ty::Bool)) => Ok(Some(self.normalize_bool(temp)?)),
+            _ => Ok(temp),
+        }
     }
     fn copy_struct(
         &mut self,
@@ -422,10 +1078,7 @@ impl<'a, W: Write> Compiler<'a, W> {
         dest: Value,
         fields: &[ir::StructField],
     ) -> io::Result<()> {
-        let mut offset = 0;
-        for field in fields {
-            offset = align_to(offset, align_bytes(&field.ty));
-
+        for (field, offset) in fields.iter().zip(field_offsets(fields)) {
             let src_off = self.new_temp();
             writeln!(self.output, "  {} =l add {}, {}", src_off, src, offset)?;
             let value = self.load(&field.ty, Value::Temp(src_off))?;
@@ -434,7 +1087,6 @@ impl<'a, W: Write> Compiler<'a, W> {
             writeln!(self.output, "  {} =l add {}, {}", dest_off, dest, offset)?;
 
             self.store(value, &field.ty, Value::Temp(dest_off))?;
-            offset += size_bytes(&field.ty);
         }
         Ok(())
     }
@@ -448,6 +1100,7 @@ impl<'a, W: Write> Compiler<'a, W> {
                     Size::B8 => "storeb",
                     Size::B16 => "storeh",
                     Size::B32 => "storew",
+                    Size::Ptr => "storel",
                 };
                 writeln!(self.output, "  {} {}, {}", op, value, addr)?;
             }
@@ -464,8 +1117,8 @@ impl<'a, W: Write> Compiler<'a, W> {
         Ok(match &ty {
             ir::Ty::Bool => {
                 let temp = self.new_temp();
-                writeln!(self.output, "  {} =w loadb {}", temp, addr)?;
-                Value::Temp(temp)
+                writeln!(self.output, "  {} =w loadub {}", temp, addr)?;
+                Value::Temp(self.normalize_bool(temp)?)
             }
             ir::Ty::Int(int) => {
                 let temp = self.new_temp();
@@ -476,8 +1129,10 @@ impl<'a, W: Write> Compiler<'a, W> {
                     (Signedness::Unsigned, Size::B8) => "loadub",
                     (Signedness::Unsigned, Size::B16) => "loaduh",
                     (Signedness::Unsigned, Size::B32) => "loaduw",
+                    (_, Size::Ptr) => "loadl",
                 };
-                writeln!(self.output, "  {} =w {} {}", temp, op, addr)?;
+                let class = int_class(*int);
+                writeln!(self.output, "  {} ={} {} {}", temp, class, op, addr)?;
                 Value::Temp(temp)
             }
             ir::Ty::Ptr => {
@@ -488,9 +1143,346 @@ impl<'a, W: Write> Compiler<'a, W> {
             ir::Ty::Struct(_) => addr,
         })
     }
+    // memory and foreign functions may hold any non-zero value for `true`, and a C
+    // `bool` returned from a call only defines the low byte
+    fn normalize_bool(&mut self, value: Temp) -> io::Result<Temp> {
+        let byte = self.new_temp();
+        writeln!(self.output, "  {} =w extub {}", byte, value)?;
+        let temp = self.new_temp();
+        writeln!(self.output, "  {} =w cnew {}, 0", temp, byte)?;
+        Ok(temp)
+    }
     fn new_temp(&mut self) -> Temp {
         let temp = Temp(self.temp_count);
         self.temp_count += 1;
         temp
     }
 }
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::{compile_typed_ast, frontend, profile::Profiler};
+    use std::{
+        env, fs,
+        path::Path,
+        process::{self, Output},
+    };
+
+    // Compiles a module to qbe's IL as `compile_module` does, without the prelude.
+    pub fn compile(source: &str) -> String {
+        let checked = match frontend::check_source(source, None, &mut Profiler::new(false)) {
+            Ok(checked) => checked,
+            Err(diagnostics) => {
+                let messages: Vec<_> = diagnostics.iter().map(|d| &d.message).collect();
+                panic!("{:?}", messages)
+            }
+        };
+        let decls = checked.decls();
+        let backend = Qbe::new(None).unwrap();
+        let mut output = vec![];
+        let mut constants = Constants::default();
+        backend
+            .emit_module(&mut output, &decls, &checked.symbols)
+            .unwrap();
+        for func in &checked.funcs {
+            let func = compile_typed_ast::lower_func(func, true);
+            backend
+                .emit_func(
+                    &mut output,
+                    &func,
+                    &decls,
+                    &checked.symbols,
+                    source,
+                    false,
+                    &mut constants,
+                )
+                .unwrap();
+        }
+        backend.emit_constants(&mut output, &constants).unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    fn labels(module: &str) -> Vec<&str> {
+        module
+            .split(|ch: char| ch.is_whitespace() || ch == '(')
+            .filter(|word| word.starts_with('$'))
+            .collect()
+    }
+
+    // Labels in the order they're defined.
+    fn defined_labels(module: &str) -> Vec<&str> {
+        module
+            .lines()
+            .filter(|line| line.contains("function ") || line.contains("data "))
+            .flat_map(|line| labels(line).into_iter().take(1))
+            .collect()
+    }
+
+    #[test]
+    fn frame_labels_dont_clash_with_modules() {
+        let module = compile("mod a { func frame() {} func name() {} } func a() {}");
+        let defined = defined_labels(&module);
+        for (index, label) in defined.iter().enumerate() {
+            assert!(
+                !defined[..index].contains(label),
+                "{} is defined twice",
+                label
+            );
+        }
+        assert!(defined.contains(&"$a.frame"));
+        assert!(defined.contains(&"$a..frame"));
+    }
+
+    // Inference and slot coalescing mustn't depend on hash map iteration order, which
+    // changes between maps.
+    #[test]
+    fn output_is_deterministic() {
+        let source = "
+            struct Point { x: i32, y: i32, z: i32 }
+            struct Line { start: Point, end: Point }
+            func print_int(n: i32);
+            func length(line: Line) i32 {
+                var start = line.start;
+                var end = line.end;
+                return end.x - start.x + end.y - start.y;
+            }
+            func main() {
+                var a = Point { z: 3, y: 2, x: 1 };
+                var b = Point { x: 4, z: 6, y: 5 };
+                var line = Line { end: b, start: a };
+                var total = length(line);
+                if total > 0 {
+                    var c = 1;
+                    total = total + c;
+                }
+                if total > 1 {
+                    var d = 2;
+                    total = total + d;
+                }
+                print_int(total);
+            }
+        ";
+        let first = compile(source);
+        for _ in 0..10 {
+            assert_eq!(compile(source), first);
+        }
+    }
+
+    // A struct is declared to qbe before the structs that contain it, whatever order
+    // the source declares them in.
+    #[test]
+    fn forward_references() {
+        let module = compile(
+            "
+            func main() {
+                var line = make();
+                _ = line;
+            }
+            func make() Line {
+                var start = Point { x: 1, y: 2 };
+                return Line { start: start, end: start };
+            }
+            struct Line { start: Point, end: Point }
+            struct Point { x: i32, y: i32 }
+        ",
+        );
+        let point = module.find("type :Point").unwrap();
+        let line = module.find("type :Line").unwrap();
+        assert!(point < line);
+        assert!(module.contains("call $make("));
+    }
+
+    #[test]
+    fn bools_from_calls_only_use_the_low_byte() {
+        let module = compile(
+            "
+            func is_ready() bool;
+            func main() {
+                if is_ready() {
+                    _ = 1;
+                }
+            }
+        ",
+        );
+        let call = module.find("call $is_ready()").unwrap();
+        let extend = module[call..].find("extub").unwrap();
+        let compare = module[call..].find("cnew").unwrap();
+        assert!(extend < compare);
+    }
+
+    // The body of the function named `name`.
+    fn body<'m>(module: &'m str, name: &str) -> &'m str {
+        let start = module.find(&format!("${}(", name)).unwrap();
+        let end = start + module[start..].find("\n}").unwrap();
+        &module[start..end]
+    }
+
+    // C would use whatever is in the return register as the exit status.
+    #[test]
+    fn main_without_a_return_type_exits_with_zero() {
+        let module = compile(
+            "
+            func main(argc: i32, argv: **u8) {
+                if argc > 1 {
+                    return;
+                }
+            }
+        ",
+        );
+        let main = body(&module, "main");
+        assert!(module.contains("export function w $main(w %"));
+        let returns: Vec<_> = main
+            .lines()
+            .filter(|line| line.trim_start().starts_with("ret"))
+            .collect();
+        assert_eq!(returns, ["  ret 0", "  ret 0"]);
+        let module = compile("func main() i32 { return 3; }");
+        assert!(body(&module, "main").contains("ret 3"));
+    }
+
+    // Every call matches libatomic's signature for the size, with sequentially
+    // consistent ordering.
+    #[test]
+    fn atomics_call_libatomic() {
+        let module = compile(
+            "
+            func main() {
+                var n: i32 = 0;
+                var small: u16 = 0;
+                atomic_store(&n, 1);
+                _ = atomic_load(&n);
+                _ = atomic_add(&small, 2);
+                _ = atomic_compare_exchange(&n, 1, 2);
+            }
+        ",
+        );
+        let calls: Vec<_> = module
+            .lines()
+            .filter_map(|line| line.split_once("call $__atomic_"))
+            .map(|(_, call)| call)
+            .collect();
+        assert_eq!(calls.len(), 4);
+        assert!(calls[0].starts_with("store_4(l ") && calls[0].ends_with(", w 1, w 5)"));
+        assert!(calls[1].starts_with("load_4(l ") && calls[1].ends_with(", w 5)"));
+        assert!(calls[2].starts_with("fetch_add_2(l ") && calls[2].ends_with(", w 2, w 5)"));
+        // the expected value is passed through memory, and there's no weak flag
+        let args: Vec<_> = calls[3]
+            .trim_start_matches("compare_exchange_4(")
+            .trim_end_matches(')')
+            .split(", ")
+            .collect();
+        assert_eq!(args.len(), 5);
+        assert!(args[0].starts_with("l ") && args[1].starts_with("l "));
+        assert!(module.contains(&format!("storew 1, {}", &args[1][2..])));
+        assert_eq!(args[2..], ["w 2", "w 5", "w 5"]);
+    }
+
+    #[test]
+    fn frame_entries_name_each_function() {
+        let module = compile("func main() {}\nmod shapes {\n    func area() {}\n}");
+        let backend = Qbe::new(None).unwrap();
+        assert!(module.contains("data $shapes.area..name = { b \"shapes::area\", b 0 }"));
+        let entry = format!(
+            "{} data $shapes.area..frame = align 8 {{ l $shapes.area, l $shapes.area..name, l 3 }}",
+            backend.frame_section()
+        );
+        assert!(module.contains(&entry), "{}", module);
+        assert!(module.contains("l $main, l $main..name, l 1 }"));
+    }
+
+    #[test]
+    fn unwinding_harness_lists_the_tests() {
+        let backend = Qbe::new(None).unwrap();
+        let tests = ["first".to_string(), "tests".to_string()];
+        let mut output = vec![];
+        backend
+            .emit_test_harness(&mut output, &tests, Panic::UnwindLite)
+            .unwrap();
+        let module = String::from_utf8(output).unwrap();
+        assert!(module.contains("{ l $name.0, l $first, l $name.1, l $tests, l 0 }"));
+        assert!(module.contains("call $pl_run_tests(l $tests..table, w %argc, l %argv)"));
+        let defined = defined_labels(&module);
+        assert!(!defined.contains(&"$tests"), "{}", module);
+    }
+
+    // Compiles a C program with the runtime and runs it with the arguments, in a
+    // directory named after the test. None if there's no C compiler.
+    fn run_with_runtime(test: &str, program: &str, args: &[&str]) -> Option<Output> {
+        let dir = env::temp_dir().join(format!("{}-{}", test, process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let source = dir.join("program.c");
+        let binary = dir.join("program");
+        fs::write(&source, program).unwrap();
+        let runtime = Path::new(env!("CARGO_MANIFEST_DIR")).join("main.c");
+        // the stack trace follows frame pointers
+        let compiled = Command::new("cc")
+            .args(["-O0", "-fno-omit-frame-pointer", "-o"])
+            .arg(&binary)
+            .arg(&source)
+            .arg(&runtime)
+            .output()
+            .ok()?;
+        assert!(
+            compiled.status.success(),
+            "{}",
+            String::from_utf8_lossy(&compiled.stderr)
+        );
+        let output = Command::new(&binary).args(args).output().unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        Some(output)
+    }
+
+    #[test]
+    fn panics_print_a_stack_trace() {
+        let program = r#"
+            struct pl_func { void *start; char *name; long line; };
+            void pl_panic(char *message);
+            void fail(void) { pl_panic("assertion failed"); }
+            int main(void) { fail(); return 0; }
+            #ifdef __APPLE__
+            __attribute__((section("__DATA,pl_funcs"), used))
+            #else
+            __attribute__((section("pl_funcs"), used))
+            #endif
+            struct pl_func entries[] = { { (void *)fail, "fail", 3 }, { (void *)main, "main", 0 } };
+        "#;
+        let Some(output) = run_with_runtime("stack-trace", program, &[]) else {
+            eprintln!("skipping: no C compiler found");
+            return;
+        };
+        assert_eq!(output.status.code(), Some(1));
+        assert_eq!(
+            String::from_utf8_lossy(&output.stderr),
+            "assertion failed\nstack trace:\n  at fail (line 3)\n  at main\n"
+        );
+    }
+
+    #[test]
+    fn unwind_lite_runs_the_remaining_tests() {
+        let program = r#"
+            #include <stddef.h>
+            struct pl_test { char *name; void (*run)(void); };
+            int pl_run_tests(struct pl_test *tests, int argc, char **argv);
+            void pl_panic(char *message);
+            void pass(void) {}
+            void fail(void) { pl_panic("assertion failed"); }
+            struct pl_test tests[] = { { "pass", pass }, { "fail", fail }, { NULL, NULL } };
+            int main(int argc, char **argv) { return pl_run_tests(tests, argc, argv); }
+        "#;
+        let Some(output) = run_with_runtime("unwind-lite", program, &["pass", "fail", "pass"])
+        else {
+            eprintln!("skipping: no C compiler found");
+            return;
+        };
+        assert_eq!(output.status.code(), Some(1));
+        assert_eq!(
+            String::from_utf8_lossy(&output.stdout),
+            "test pass ... ok\ntest fail ... FAILED\ntest pass ... ok\n"
+        );
+        let Some(output) = run_with_runtime("unknown-test", program, &["missing"]) else {
+            return;
+        };
+        assert_eq!(output.status.code(), Some(2));
+    }
+}
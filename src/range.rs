@@ -0,0 +1,208 @@
+#![deny(clippy::unwrap_used, clippy::expect_used, clippy::panic)]
+
+use crate::{
+    codes::Code,
+    compile_typed_ast::concrete_int,
+    diagnostic::Diagnostic,
+    ty::{Int, IntTy, Signedness, Size, Ty},
+    typed_ast::{BinaryOp, Branch, Expr, Func, RefExpr, Stmt},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Range {
+    min: i128,
+    max: i128,
+}
+
+struct Checker {
+    warnings: Vec<Diagnostic>,
+}
+
+pub fn check_func(func: &Func) -> Vec<Diagnostic> {
+    let mut checker = Checker { warnings: vec![] };
+    for block in &func.blocks {
+        for stmt in &block.stmts {
+            match stmt {
+                Stmt::Alloc(..) => {}
+                Stmt::Assign { ref_expr, expr, .. } => {
+                    checker.ref_expr(ref_expr);
+                    checker.expr(expr);
+                }
+                Stmt::FuncCall(func_call) => {
+                    func_call.args.iter().for_each(|arg| _ = checker.expr(arg))
+                }
+                Stmt::Discard(expr) | Stmt::Assert { expr, .. } => _ = checker.expr(expr),
+            }
+        }
+        match &block.branch {
+            Branch::Return(Some(expr)) | Branch::Condition { expr, .. } => _ = checker.expr(expr),
+            Branch::Return(None) | Branch::Static(_) => {}
+        }
+    }
+    checker.warnings
+}
+
+fn int_range(int: Int) -> Range {
+    let bits = match int.size {
+        Size::B8 => 8,
+        Size::B16 => 16,
+        Size::B32 => 32,
+        Size::Ptr => 64,
+    };
+    match int.signedness {
+        Signedness::Signed => Range {
+            min: -(1 << (bits - 1)),
+            max: (1 << (bits - 1)) - 1,
+        },
+        Signedness::Unsigned => Range {
+            min: 0,
+            max: (1 << bits) - 1,
+        },
+    }
+}
+
+// Whether a literal of an integer type can hold the value.
+pub fn fits(int: Int, value: i64) -> bool {
+    let range = int_range(int);
+    range.min <= value as i128 && value as i128 <= range.max
+}
+
+fn corners(left: Range, right: Range, f: impl Fn(i128, i128) -> i128) -> Range {
+    let values = [
+        f(left.min, right.min),
+        f(left.min, right.max),
+        f(left.max, right.min),
+        f(left.max, right.max),
+    ];
+    Range {
+        min: values.into_iter().fold(i128::MAX, i128::min),
+        max: values.into_iter().fold(i128::MIN, i128::max),
+    }
+}
+
+impl Checker {
+    // Returns the range of values an integer expression can take, if it is known.
+    fn expr(&mut self, expr: &Expr) -> Option<Range> {
+        match expr {
+            Expr::Int(value) => Some(Range {
+                min: *value as i128,
+                max: *value as i128,
+            }),
+            Expr::Binary {
+                left,
+                right,
+                ty,
+                op,
+                span,
+            } => {
+                let int = concrete_int(ty);
+                let ty_range = int_range(int);
+                let left = self.expr(left).unwrap_or(ty_range);
+                let right = self.expr(right).unwrap_or(ty_range);
+                let result = match op {
+                    BinaryOp::Add => Range {
+                        min: left.min + right.min,
+                        max: left.max + right.max,
+                    },
+                    BinaryOp::Subtract => Range {
+                        min: left.min - right.max,
+                        max: left.max - right.min,
+                    },
+                    BinaryOp::Multiply => corners(left, right, |a, b| a.saturating_mul(b)),
+                    BinaryOp::Divide if right.min == 0 && right.max == 0 => {
+                        self.warnings.push(Diagnostic::new(
+                            Code::DivisionByZero,
+                            *span,
+                            "this division always divides by zero".to_string(),
+                        ));
+                        return None;
+                    }
+                    BinaryOp::Divide if right.min > 0 || right.max < 0 => {
+                        corners(left, right, |a, b| a / b)
+                    }
+                    BinaryOp::Divide => return None,
+                    BinaryOp::LessThan | BinaryOp::GreaterThan => {
+                        let (left, right) = match op {
+                            BinaryOp::LessThan => (left, right),
+                            _ => (right, left),
+                        };
+                        let always = if left.max < right.min {
+                            Some(true)
+                        } else if left.min >= right.max {
+                            Some(false)
+                        } else {
+                            None
+                        };
+                        // comparisons between constants are reported as constant conditions
+                        let is_const = left.min == left.max && right.min == right.max;
+                        if let (Some(always), false) = (always, is_const) {
+                            self.warnings.push(Diagnostic::new(
+                                Code::ConstantComparison,
+                                *span,
+                                format!("this comparison is always `{}`", always),
+                            ));
+                        }
+                        return None;
+                    }
+                };
+                if result.min > ty_range.max || result.max < ty_range.min {
+                    self.warnings.push(Diagnostic::new(
+                        Code::Overflow,
+                        *span,
+                        format!("this operation always overflows `{}`", IntTy::Int(int)),
+                    ));
+                    None
+                } else if result.min >= ty_range.min && result.max <= ty_range.max {
+                    Some(result)
+                } else {
+                    None
+                }
+            }
+            Expr::BitNot { expr, .. }
+            | Expr::Intrinsic { arg: expr, .. }
+            | Expr::Deref { expr, .. }
+            | Expr::Unwrap { expr, .. }
+            | Expr::IsNull(expr)
+            | Expr::Field { expr, .. } => {
+                self.expr(expr);
+                None
+            }
+            Expr::PtrOffset { ptr, offset, .. } => {
+                self.expr(ptr);
+                self.expr(offset);
+                None
+            }
+            Expr::Ref(ref_expr) => {
+                self.ref_expr(ref_expr);
+                None
+            }
+            Expr::FuncCall(func_call) => {
+                func_call.args.iter().for_each(|arg| _ = self.expr(arg));
+                None
+            }
+            Expr::Atomic { args, .. } => {
+                args.iter().for_each(|arg| _ = self.expr(arg));
+                None
+            }
+            Expr::InitStruct(values) => {
+                values.iter().for_each(|value| _ = self.expr(&value.expr));
+                None
+            }
+            Expr::Load { ty, .. } => {
+                let int = ty.map(|ty| match ty {
+                    Ty::Int(int) => Some(concrete_int(int)),
+                    _ => None,
+                });
+                int.map(int_range)
+            }
+            Expr::Bool(_) | Expr::Null => None,
+        }
+    }
+    fn ref_expr(&mut self, ref_expr: &RefExpr) {
+        match ref_expr {
+            RefExpr::Variable(_) => {}
+            RefExpr::Deref(expr, _) => _ = self.expr(expr),
+            RefExpr::Field { ref_expr, .. } => self.ref_expr(ref_expr),
+        }
+    }
+}
@@ -0,0 +1,462 @@
+#![deny(clippy::unwrap_used, clippy::expect_used, clippy::panic)]
+
+use std::{
+    cell::Cell,
+    collections::{HashMap, HashSet},
+    iter::{once, successors},
+};
+
+use crate::{
+    ast::{Block, Decl, Else, Expr, FuncCall, If, Program, RefExpr, Stmt, Ty, Use},
+    codes::Code,
+    diagnostic::Diagnostic,
+    lexer::Span,
+    symbols::{Symbol, Symbols},
+};
+
+// Something that's referred to by name, for finding references and renaming.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Target {
+    Func(Symbol),
+    Struct(Symbol),
+    Const(Symbol),
+    // every variable in a function with the same name is the same variable
+    Var { func: Symbol, name: Symbol },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RefKind {
+    Def,
+    Read,
+    Write,
+    // the variable may be read or written through the pointer
+    AddressOf,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Reference {
+    pub target: Target,
+    // only the last segment of a path, which is the part that's renamed
+    pub span: Span,
+    pub kind: RefKind,
+    // through a `use` that gives it another name, so renaming doesn't change it
+    pub aliased: bool,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Namespace {
+    Func,
+    Struct,
+    Const,
+}
+
+struct Import {
+    use_decl: Use,
+    module: String,
+    used: Cell<bool>,
+}
+
+struct Resolver<'a, 's> {
+    symbols: &'a Symbols<'s>,
+    funcs: HashSet<Symbol>,
+    structs: HashSet<Symbol>,
+    consts: HashSet<Symbol>,
+    // keyed by the name they're imported as, qualified by the module the `use` is in
+    imports: HashMap<Symbol, Import>,
+    // the path of the module being resolved, empty at the top level
+    module: String,
+    func: Symbol,
+    locals: Vec<Symbol>,
+    references: Vec<Reference>,
+}
+
+// Declarations inside `mod` blocks are moved to the top level with names qualified by
+// their module, e.g. `shapes::area`. Every name that refers to a declaration is then
+// replaced by its qualified name, looking in the enclosing module first and then in
+// each module around it and finally in the prelude. At each level a name can also be
+// brought in by a `use`. Names that can't be found are left for the checker to report.
+// Every reference to a function or variable outside the prelude is returned too.
+pub fn resolve(
+    program: Program,
+    symbols: &mut Symbols,
+    warnings: &mut Vec<Diagnostic>,
+) -> (Program, Vec<Reference>, Vec<Diagnostic>) {
+    let mut decls = vec![];
+    flatten(program.decls, "", symbols, &mut decls);
+    let mut uses = vec![];
+    let func = symbols.get_symbol("");
+    for (module, decl) in &decls {
+        if let Decl::Use(use_decl) = decl {
+            let alias = qualify(module, symbols.get_str(use_decl.alias));
+            uses.push((symbols.get_symbol(alias), module.clone(), use_decl.clone()));
+        }
+    }
+    let mut resolver = Resolver {
+        symbols,
+        funcs: HashSet::new(),
+        structs: HashSet::new(),
+        consts: HashSet::new(),
+        imports: HashMap::new(),
+        module: String::new(),
+        func,
+        locals: vec![],
+        references: vec![],
+    };
+    for (_, decl) in &decls {
+        match decl {
+            Decl::Func(func) => _ = resolver.funcs.insert(func.name),
+            Decl::Struct(struct_decl) => _ = resolver.structs.insert(struct_decl.name),
+            Decl::Const(const_decl) => _ = resolver.consts.insert(const_decl.name),
+            Decl::StaticAssert(_) | Decl::Mod(_) | Decl::Use(_) => {}
+        }
+    }
+
+    let mut errors = vec![];
+    for (alias, module, use_decl) in uses {
+        let path = resolver.symbols.get_str(use_decl.path);
+        let target = [Namespace::Func, Namespace::Struct, Namespace::Const]
+            .into_iter()
+            .find_map(|namespace| {
+                let name = resolver.lookup_decl(namespace, &module, path)?;
+                Some(namespace.target(name))
+            });
+        let target = match target {
+            Some(target) => target,
+            None => {
+                errors.push(Diagnostic::new(
+                    Code::UnresolvedImport,
+                    use_decl.span,
+                    format!("unresolved import `{}`", path),
+                ));
+                continue;
+            }
+        };
+        if let Some(first) = resolver.imports.get(&alias) {
+            let name = resolver.symbols.get_str(use_decl.alias);
+            errors.push(
+                Diagnostic::new(
+                    Code::ImportConflict,
+                    use_decl.span,
+                    format!("`{}` is imported more than once", name),
+                )
+                .with_span_note(
+                    first.use_decl.span,
+                    format!("`{}` is first imported here", name),
+                ),
+            );
+        } else {
+            resolver.module = module.clone();
+            resolver.reference(target, path, use_decl.span, RefKind::Read);
+            let import = Import {
+                use_decl,
+                module,
+                used: Cell::new(false),
+            };
+            resolver.imports.insert(alias, import);
+        }
+    }
+
+    for (module, decl) in &mut decls {
+        resolver.module = std::mem::take(module);
+        resolver.locals.clear();
+        resolver.decl(decl);
+    }
+    for import in resolver
+        .imports
+        .values()
+        .filter(|import| !import.used.get())
+    {
+        let use_decl = &import.use_decl;
+        warnings.push(
+            Diagnostic::new(
+                Code::UnusedImport,
+                use_decl.span,
+                format!(
+                    "unused import `{}`",
+                    resolver.symbols.get_str(use_decl.path)
+                ),
+            )
+            .with_suggestion(use_decl.stmt_span, "", "remove the import".to_string()),
+        );
+    }
+    let program = Program {
+        decls: decls.into_iter().map(|(_, decl)| decl).collect(),
+    };
+    (program, resolver.references, errors)
+}
+
+fn flatten(decls: Vec<Decl>, module: &str, symbols: &mut Symbols, out: &mut Vec<(String, Decl)>) {
+    for decl in decls {
+        let decl = match decl {
+            Decl::Mod(mod_decl) => {
+                let path = qualify(module, symbols.get_str(mod_decl.name));
+                flatten(mod_decl.decls, &path, symbols, out);
+                continue;
+            }
+            _ if module.is_empty() => decl,
+            Decl::Func(mut func) => {
+                func.name = symbols.get_symbol(qualify(module, symbols.get_str(func.name)));
+                Decl::Func(func)
+            }
+            Decl::Struct(mut struct_decl) => {
+                struct_decl.name =
+                    symbols.get_symbol(qualify(module, symbols.get_str(struct_decl.name)));
+                Decl::Struct(struct_decl)
+            }
+            Decl::Const(mut const_decl) => {
+                const_decl.name =
+                    symbols.get_symbol(qualify(module, symbols.get_str(const_decl.name)));
+                Decl::Const(const_decl)
+            }
+            Decl::StaticAssert(_) | Decl::Use(_) => decl,
+        };
+        out.push((module.to_string(), decl));
+    }
+}
+
+fn last_segment(path: &str) -> &str {
+    path.rsplit("::").next().unwrap_or(path)
+}
+
+fn qualify(module: &str, name: &str) -> String {
+    if module.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}::{}", module, name)
+    }
+}
+
+// The module and each module around it, out to the top level and then the prelude.
+fn enclosing(module: &str) -> impl Iterator<Item = &str> {
+    successors(Some(module), |module| {
+        (!module.is_empty()).then(|| module.rsplit_once("::").map_or("", |(outer, _)| outer))
+    })
+    .chain(once("prelude"))
+}
+
+impl Namespace {
+    fn target(self, name: Symbol) -> Target {
+        match self {
+            Namespace::Func => Target::Func(name),
+            Namespace::Struct => Target::Struct(name),
+            Namespace::Const => Target::Const(name),
+        }
+    }
+}
+
+impl<'a, 's> Resolver<'a, 's> {
+    fn names(&self, namespace: Namespace) -> &HashSet<Symbol> {
+        match namespace {
+            Namespace::Func => &self.funcs,
+            Namespace::Struct => &self.structs,
+            Namespace::Const => &self.consts,
+        }
+    }
+    // Imports aren't followed here, so one import can't refer to another.
+    fn lookup_decl(&self, namespace: Namespace, module: &str, name: &str) -> Option<Symbol> {
+        enclosing(module).find_map(|module| {
+            let qualified = self.symbols.find(&qualify(module, name))?;
+            self.names(namespace)
+                .contains(&qualified)
+                .then_some(qualified)
+        })
+    }
+    fn lookup(&self, namespace: Namespace, name: Symbol) -> Symbol {
+        let name_str = self.symbols.get_str(name);
+        for module in enclosing(&self.module) {
+            let qualified = match self.symbols.find(&qualify(module, name_str)) {
+                Some(qualified) => qualified,
+                None => continue,
+            };
+            if self.names(namespace).contains(&qualified) {
+                return qualified;
+            }
+            if let Some(import) = self.imports.get(&qualified) {
+                let path = self.symbols.get_str(import.use_decl.path);
+                if let Some(symbol) = self.lookup_decl(namespace, &import.module, path) {
+                    import.used.set(true);
+                    return symbol;
+                }
+            }
+        }
+        name
+    }
+    // `written` is the name as it appears at `span`, which may be a path.
+    fn reference(&mut self, target: Target, written: &str, span: Span, kind: RefKind) {
+        // the prelude's spans are in another source
+        if self.module == "prelude" || self.module.starts_with("prelude::") {
+            return;
+        }
+        let name = match target {
+            Target::Func(name) | Target::Struct(name) | Target::Const(name) => name,
+            Target::Var { name, .. } => name,
+        };
+        let written = last_segment(written);
+        self.references.push(Reference {
+            target,
+            span: Span {
+                start: span.end - written.len(),
+                end: span.end,
+            },
+            kind,
+            aliased: written != last_segment(self.symbols.get_str(name)),
+        });
+    }
+    fn var(&mut self, name: Symbol, span: Span, kind: RefKind) {
+        let target = Target::Var {
+            func: self.func,
+            name,
+        };
+        self.reference(target, self.symbols.get_str(name), span, kind);
+    }
+    // Looks up a name that refers to a declaration and records the reference to it.
+    fn resolve_name(&mut self, namespace: Namespace, name: Symbol, span: Span) -> Symbol {
+        let resolved = self.lookup(namespace, name);
+        if self.names(namespace).contains(&resolved) {
+            let written = self.symbols.get_str(name);
+            self.reference(namespace.target(resolved), written, span, RefKind::Read);
+        }
+        resolved
+    }
+    fn decl(&mut self, decl: &mut Decl) {
+        match decl {
+            Decl::Func(func) => {
+                self.func = func.name;
+                let name = self.symbols.get_str(func.name);
+                self.reference(Target::Func(func.name), name, func.span, RefKind::Def);
+                for param in &mut func.params {
+                    self.ty(&mut param.ty);
+                    self.locals.push(param.name);
+                    self.var(param.name, param.span, RefKind::Def);
+                }
+                if let Some(ty) = &mut func.returns {
+                    self.ty(ty);
+                }
+                if let Some(body) = &mut func.body {
+                    self.block(body);
+                }
+            }
+            Decl::Struct(struct_decl) => {
+                let name = self.symbols.get_str(struct_decl.name);
+                let target = Target::Struct(struct_decl.name);
+                self.reference(target, name, struct_decl.span, RefKind::Def);
+                for field in &mut struct_decl.fields {
+                    self.ty(&mut field.ty);
+                }
+            }
+            Decl::Const(const_decl) => {
+                let name = self.symbols.get_str(const_decl.name);
+                let target = Target::Const(const_decl.name);
+                self.reference(target, name, const_decl.span, RefKind::Def);
+                self.ty(&mut const_decl.ty);
+                self.expr(&mut const_decl.expr);
+            }
+            Decl::StaticAssert(static_assert) => self.expr(&mut static_assert.cond),
+            Decl::Mod(_) | Decl::Use(_) => {}
+        }
+    }
+    fn ty(&mut self, ty: &mut Ty) {
+        match ty {
+            Ty::Struct(name, span) => *name = self.resolve_name(Namespace::Struct, *name, *span),
+            Ty::Ref(ty) | Ty::NullableRef(ty) => self.ty(ty),
+            Ty::Int(_) | Ty::Bool => {}
+        }
+    }
+    fn block(&mut self, block: &mut Block) {
+        for stmt in &mut block.stmts {
+            self.stmt(stmt);
+        }
+    }
+    fn stmt(&mut self, stmt: &mut Stmt) {
+        match stmt {
+            Stmt::Let {
+                ident,
+                expr,
+                ty,
+                span,
+            } => {
+                if let Some(ty) = ty {
+                    self.ty(ty);
+                }
+                if let Some(expr) = expr {
+                    self.expr(expr);
+                }
+                self.locals.push(*ident);
+                self.var(*ident, *span, RefKind::Def);
+            }
+            Stmt::Assign { ref_expr, expr, .. } => {
+                self.ref_expr(ref_expr, RefKind::Write);
+                self.expr(expr);
+            }
+            Stmt::Discard { expr, .. } => self.expr(expr),
+            Stmt::While { cond, body, .. } => {
+                self.expr(cond);
+                self.block(body);
+            }
+            Stmt::Unsafe { body, .. } => self.block(body),
+            Stmt::Return { expr, .. } => {
+                if let Some(expr) = expr {
+                    self.expr(expr);
+                }
+            }
+            Stmt::If(if_stmt) => self.if_stmt(if_stmt),
+            Stmt::FuncCall(func_call) => self.func_call(func_call),
+        }
+    }
+    fn if_stmt(&mut self, if_stmt: &mut If) {
+        self.expr(&mut if_stmt.cond);
+        self.block(&mut if_stmt.if_block);
+        match &mut if_stmt.else_block {
+            Else::Block(block) => self.block(block),
+            Else::If(if_stmt) => self.if_stmt(if_stmt),
+            Else::None => {}
+        }
+    }
+    fn func_call(&mut self, func_call: &mut FuncCall) {
+        func_call.name = self.resolve_name(Namespace::Func, func_call.name, func_call.span);
+        for arg in &mut func_call.args {
+            self.expr(arg);
+        }
+    }
+    fn expr(&mut self, expr: &mut Expr) {
+        match expr {
+            Expr::Integer(..) | Expr::Char(_) | Expr::Bool(_) | Expr::Null => {}
+            // variables shadow constants
+            Expr::Ident { name, span } => {
+                if self.locals.contains(name) {
+                    self.var(*name, *span, RefKind::Read);
+                } else {
+                    *name = self.resolve_name(Namespace::Const, *name, *span);
+                }
+            }
+            Expr::Field { expr, .. } | Expr::Prefix { expr, .. } => self.expr(expr),
+            Expr::Infix { left, right, .. } => {
+                self.expr(left);
+                self.expr(right);
+            }
+            Expr::Ref(ref_expr) => self.ref_expr(ref_expr, RefKind::AddressOf),
+            Expr::FuncCall(func_call) => self.func_call(func_call),
+            Expr::InitStruct { name, values, span } => {
+                *name = self.resolve_name(Namespace::Struct, *name, *span);
+                for value in values {
+                    self.expr(&mut value.expr);
+                }
+            }
+            Expr::Cast { expr, ty, .. } => {
+                self.expr(expr);
+                self.ty(ty);
+            }
+        }
+    }
+    fn ref_expr(&mut self, ref_expr: &mut RefExpr, kind: RefKind) {
+        match ref_expr {
+            RefExpr::Ident(name, span) => {
+                if self.locals.contains(name) {
+                    self.var(*name, *span, kind);
+                }
+            }
+            RefExpr::Deref(expr) => self.expr(expr),
+            RefExpr::Field { ref_expr, .. } => self.ref_expr(ref_expr, kind),
+        }
+    }
+}
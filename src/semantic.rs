@@ -0,0 +1,139 @@
+#![deny(clippy::unwrap_used, clippy::expect_used, clippy::panic)]
+
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+    frontend::CheckedModule,
+    lexer::{self, Span},
+    resolve::{RefKind, Target},
+    token::{Keyword, Symbol, Token, TokenKind},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TokenClass {
+    Keyword,
+    Number,
+    String,
+    Operator,
+    Function,
+    Struct,
+    Const,
+    Parameter,
+    Variable,
+    Field,
+    Module,
+    // a name that wasn't resolved to anything
+    Name,
+    Comment,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct SemanticToken {
+    pub span: Span,
+    pub class: TokenClass,
+    // assigned to after it's declared, or has its address taken
+    pub mutable: bool,
+    // never read, and its address is never taken
+    pub unused: bool,
+}
+
+impl TokenClass {
+    pub fn name(&self) -> &str {
+        match self {
+            TokenClass::Keyword => "keyword",
+            TokenClass::Number => "number",
+            TokenClass::String => "string",
+            TokenClass::Operator => "operator",
+            TokenClass::Function => "function",
+            TokenClass::Struct => "struct",
+            TokenClass::Const => "const",
+            TokenClass::Parameter => "parameter",
+            TokenClass::Variable => "variable",
+            TokenClass::Field => "field",
+            TokenClass::Module => "module",
+            TokenClass::Name => "name",
+            TokenClass::Comment => "comment",
+        }
+    }
+}
+
+// Classifies every token in a checked module. Names are classified by what they were
+// resolved to, and fields and modules by the tokens around them.
+pub fn semantic_tokens(checked: &CheckedModule, source: &str) -> Vec<SemanticToken> {
+    let references: HashMap<_, _> = checked
+        .references
+        .iter()
+        .map(|reference| (reference.span.start, reference))
+        .collect();
+    let mut params = HashSet::new();
+    for func in checked.program.func_iter() {
+        params.extend(func.params.iter().map(|param| (func.name, param.name)));
+    }
+    let (tokens, _) = lexer::lex(source);
+    let mut cursor = tokens.cursor();
+    let mut prev: Option<Token> = None;
+    let mut semantic_tokens = vec![];
+    while let Some(token) = cursor.lookahead(0) {
+        let next = cursor.lookahead(1).map(|token| token.kind);
+        let class = match token.kind {
+            TokenKind::Keyword(_) => TokenClass::Keyword,
+            TokenKind::Integer => TokenClass::Number,
+            TokenKind::String | TokenKind::Char => TokenClass::String,
+            TokenKind::Symbol(_) | TokenKind::Unknown => TokenClass::Operator,
+            TokenKind::Comment => TokenClass::Comment,
+            TokenKind::Ident => match references.get(&token.offset) {
+                Some(reference) => match reference.target {
+                    Target::Func(_) => TokenClass::Function,
+                    Target::Struct(_) => TokenClass::Struct,
+                    Target::Const(_) => TokenClass::Const,
+                    Target::Var { func, name } if params.contains(&(func, name)) => {
+                        TokenClass::Parameter
+                    }
+                    Target::Var { .. } => TokenClass::Variable,
+                },
+                None => match (prev.map(|token| token.kind), next) {
+                    (Some(TokenKind::Symbol(Symbol::Dot)), _)
+                    | (_, Some(TokenKind::Symbol(Symbol::Colon))) => TokenClass::Field,
+                    (Some(TokenKind::Keyword(Keyword::Mod)), _)
+                    | (_, Some(TokenKind::Symbol(Symbol::DoubleColon))) => TokenClass::Module,
+                    _ => TokenClass::Name,
+                },
+            },
+        };
+        let (mutable, unused) = match references.get(&token.offset) {
+            Some(reference) if matches!(reference.target, Target::Var { .. }) => {
+                let kinds = kinds(checked, reference.target);
+                (
+                    kinds.contains(&RefKind::Write) || kinds.contains(&RefKind::AddressOf),
+                    !kinds.contains(&RefKind::Read) && !kinds.contains(&RefKind::AddressOf),
+                )
+            }
+            _ => (false, false),
+        };
+        semantic_tokens.push(SemanticToken {
+            span: token.span(),
+            class,
+            mutable,
+            unused,
+        });
+        prev = Some(token);
+        cursor.advance();
+    }
+    semantic_tokens.extend(tokens.comments.iter().map(|span| SemanticToken {
+        span: *span,
+        class: TokenClass::Comment,
+        mutable: false,
+        unused: false,
+    }));
+    semantic_tokens.sort_by_key(|token| token.span.start);
+    semantic_tokens
+}
+
+fn kinds(checked: &CheckedModule, target: Target) -> Vec<RefKind> {
+    checked
+        .references
+        .iter()
+        .filter(|reference| reference.target == target)
+        .map(|reference| reference.kind)
+        .collect()
+}
@@ -0,0 +1,288 @@
+use std::{
+    io::{self, IsTerminal},
+    path::Path,
+};
+
+use crate::{
+    ast::Harness,
+    manifest::{self, MANIFEST},
+    profile::Profiler,
+    target::{self, Os, Target},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Emit {
+    Exe,
+    Obj,
+    Lib,
+    Shared,
+    FrameReport,
+    UnsafeReport,
+    Annotated,
+}
+
+// What a panic does. `UnwindLite` returns to the test harness with longjmp so the
+// remaining tests can run in the same process.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Panic {
+    Abort,
+    UnwindLite,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Prelude {
+    Builtin,
+    File(String),
+    None,
+}
+
+// Asks about the source instead of compiling it, for editors and documentation.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Query {
+    TypeAt(usize),
+    References(usize),
+    Rename(usize, String),
+    Outline,
+    SemanticTokens,
+    Doc(DocFormat),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DocFormat {
+    Markdown,
+    Html,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ErrorFormat {
+    Human { color: bool },
+    Json,
+}
+
+pub struct Session {
+    pub profiler: Profiler,
+    pub coalesce_slots: bool,
+    pub dump_cfg: bool,
+    pub backend: String,
+    // the host when it's `None`
+    pub target: Option<Target>,
+    pub validate: bool,
+    pub emit: Emit,
+    pub output: Option<String>,
+    pub error_format: ErrorFormat,
+    pub explain: Option<String>,
+    pub prelude: Prelude,
+    pub freestanding: bool,
+    pub frame_limit: Option<u32>,
+    // check references before loading or storing through them
+    pub memory_checks: bool,
+    pub panic: Panic,
+    // qbe's output is already position independent, so this is only for the runtime
+    pub pic: bool,
+    pub libs: Vec<String>,
+    pub build_cache: bool,
+    pub watch: bool,
+    // run the tests or benchmarks instead of the program
+    pub harness: Option<Harness>,
+    // only tests or benchmarks whose name contains this are run
+    pub filter: Option<String>,
+    pub query: Option<Query>,
+    pub sources: Vec<String>,
+}
+
+impl Session {
+    pub fn from_args(mut args: impl Iterator<Item = String>) -> Result<Session, String> {
+        let mut time_report = false;
+        let mut coalesce_slots = true;
+        let mut dump_cfg = false;
+        let mut validate = false;
+        let mut backend = None;
+        let mut target = None;
+        let mut emit = Emit::Exe;
+        let mut output = None;
+        let mut json = false;
+        let mut color = None;
+        let mut explain = None;
+        let mut prelude = Prelude::Builtin;
+        let mut freestanding = false;
+        let mut frame_limit = None;
+        let mut memory_checks = false;
+        let mut panic = Panic::Abort;
+        let mut pic = false;
+        let mut libs = vec![];
+        let mut build_cache = true;
+        let mut watch = false;
+        let mut harness = None;
+        let mut filter = None;
+        let mut query = None;
+        let mut sources = vec![];
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--time-report" => time_report = true,
+                "--no-slot-coalescing" => coalesce_slots = false,
+                "--dump-cfg" => dump_cfg = true,
+                "--validate" => validate = true,
+                "--emit=exe" => emit = Emit::Exe,
+                "--emit=obj" => emit = Emit::Obj,
+                "--emit=lib" => emit = Emit::Lib,
+                "--emit=shared" => emit = Emit::Shared,
+                "--emit=frame-report" => emit = Emit::FrameReport,
+                "--emit=unsafe-report" => emit = Emit::UnsafeReport,
+                "--emit=annotated" => emit = Emit::Annotated,
+                "--error-format=human" => json = false,
+                "--error-format=json" => json = true,
+                "--color=auto" => color = None,
+                "--color=always" => color = Some(true),
+                "--color=never" => color = Some(false),
+                "--no-prelude" => prelude = Prelude::None,
+                "--freestanding" => freestanding = true,
+                "--checks=memory" => memory_checks = true,
+                "--checks=none" => memory_checks = false,
+                "--panic=abort" => panic = Panic::Abort,
+                "--panic=unwind-lite" => panic = Panic::UnwindLite,
+                "--fpic" => pic = true,
+                "--no-build-cache" => build_cache = false,
+                "--watch" => watch = true,
+                "--test" => harness = Some(Harness::Test),
+                "--bench" => harness = Some(Harness::Bench),
+                "--outline" => query = Some(Query::Outline),
+                "--semantic-tokens" => query = Some(Query::SemanticTokens),
+                "--doc=markdown" => query = Some(Query::Doc(DocFormat::Markdown)),
+                "--doc=html" => query = Some(Query::Doc(DocFormat::Html)),
+                "-o" => output = Some(args.next().ok_or("expected a file after '-o'")?),
+                "-l" => libs.push(args.next().ok_or("expected a library after '-l'")?),
+                "--explain" => {
+                    explain = Some(args.next().ok_or("expected a code after '--explain'")?)
+                }
+                _ if arg.starts_with("--backend=") => {
+                    backend = Some(arg["--backend=".len()..].to_string())
+                }
+                _ if arg.starts_with("--target=") => {
+                    let name = &arg["--target=".len()..];
+                    let found = target::lookup(name).ok_or(format!("unknown target '{}'", name))?;
+                    target = Some(found);
+                }
+                _ if arg.starts_with("--prelude=") => {
+                    prelude = Prelude::File(arg["--prelude=".len()..].to_string())
+                }
+                _ if arg.starts_with("--filter=") => {
+                    filter = Some(arg["--filter=".len()..].to_string())
+                }
+                _ if arg.starts_with("--frame-limit=") => {
+                    let limit = &arg["--frame-limit=".len()..];
+                    let limit = limit
+                        .parse()
+                        .map_err(|_| format!("invalid frame limit '{}'", limit))?;
+                    frame_limit = Some(limit);
+                }
+                _ if arg.starts_with("--type-at=") => {
+                    query = Some(Query::TypeAt(parse_offset(&arg["--type-at=".len()..])?))
+                }
+                _ if arg.starts_with("--references=") => {
+                    let offset = parse_offset(&arg["--references=".len()..])?;
+                    query = Some(Query::References(offset))
+                }
+                _ if arg.starts_with("--rename=") => {
+                    let (offset, name) = arg["--rename=".len()..]
+                        .split_once(':')
+                        .ok_or("expected '--rename=OFFSET:NAME'")?;
+                    query = Some(Query::Rename(parse_offset(offset)?, name.to_string()))
+                }
+                _ if !arg.starts_with('-') => sources.push(arg),
+                _ => return Err(format!("unknown argument '{}'", arg)),
+            }
+        }
+        // a project is built from its manifest when no sources are given
+        if sources.is_empty() && Path::new(MANIFEST).exists() {
+            let manifest = manifest::load(MANIFEST)?;
+            sources = manifest.sources;
+            output = output.or(manifest.output);
+            backend = backend.or(manifest.backend);
+            // slot coalescing is the only optimization so far
+            if manifest.opt_level == Some(0) {
+                coalesce_slots = false;
+            }
+            libs.extend(manifest.libs);
+        }
+        if filter.is_some() && harness.is_none() {
+            return Err("'--filter' can only be used with '--test' or '--bench'".to_string());
+        }
+        // only the test harness has anywhere to unwind to
+        if panic == Panic::UnwindLite && harness != Some(Harness::Test) {
+            return Err("'--panic=unwind-lite' can only be used with '--test'".to_string());
+        }
+        // the harness runs the tests on this machine
+        if harness.is_some() && target.is_some() {
+            return Err("'--test' and '--bench' can't be used with '--target'".to_string());
+        }
+        // the harness is linked with the runtime
+        if harness.is_some() && freestanding {
+            return Err("'--test' and '--bench' can't be used with '--freestanding'".to_string());
+        }
+        // start.S is written for x86_64 Linux
+        let x86_64_linux = match target {
+            Some(target) => target.name == "x86_64-linux",
+            None => target::os(None) == Os::Linux,
+        };
+        if freestanding && !x86_64_linux {
+            return Err("'--freestanding' can only target x86_64-linux".to_string());
+        }
+        // a failed check panics through the runtime
+        if memory_checks && freestanding {
+            return Err("'--checks=memory' can't be used with '--freestanding'".to_string());
+        }
+        // the runtime is linked into the library with it
+        if emit == Emit::Shared && freestanding {
+            return Err("'--emit=shared' can't be used with '--freestanding'".to_string());
+        }
+        if emit == Emit::Shared {
+            pic = true;
+        }
+        // the builtin prelude declares the functions in main.c, which needs libc
+        if freestanding && prelude == Prelude::Builtin {
+            prelude = Prelude::None;
+        }
+        // diagnostics go to stdout, so only color them if it's a terminal
+        let color = color.unwrap_or_else(|| io::stdout().is_terminal());
+        let error_format = if json {
+            ErrorFormat::Json
+        } else {
+            ErrorFormat::Human { color }
+        };
+        Ok(Session {
+            profiler: Profiler::new(time_report),
+            coalesce_slots,
+            dump_cfg,
+            backend: backend.unwrap_or_else(|| "qbe".to_string()),
+            target,
+            validate,
+            emit,
+            output,
+            error_format,
+            explain,
+            prelude,
+            freestanding,
+            frame_limit,
+            memory_checks,
+            panic,
+            pic,
+            libs,
+            build_cache,
+            watch,
+            harness,
+            filter,
+            query,
+            sources,
+        })
+    }
+    // The C compiler used to assemble and link, followed by its arguments.
+    pub fn cc(&self) -> &'static [&'static str] {
+        self.target.map_or(&["gcc"], |target| target.cc)
+    }
+}
+
+fn parse_offset(offset: &str) -> Result<usize, String> {
+    offset
+        .parse()
+        .map_err(|_| format!("invalid offset '{}'", offset))
+}
@@ -1,29 +1,35 @@
-use std::collections::HashMap;
+#![deny(clippy::unwrap_used, clippy::expect_used, clippy::panic)]
+
+use std::{borrow::Cow, collections::HashMap};
 
 pub struct Symbols<'a> {
-    symbol_map: HashMap<&'a str, Symbol>,
-    symbols: Vec<&'a str>,
+    symbol_map: HashMap<Cow<'a, str>, Symbol>,
+    symbols: Vec<Cow<'a, str>>,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct Symbol(pub u32);
 
 impl<'a> Symbols<'a> {
     pub fn new() -> Symbols<'a> {
         Symbols { symbol_map: HashMap::new(), symbols: vec![] }
     }
-    pub fn get_symbol(&mut self, ident: &'a str) -> Symbol {
-        match self.symbol_map.get(ident) {
+    pub fn get_symbol(&mut self, ident: impl Into<Cow<'a, str>>) -> Symbol {
+        let ident = ident.into();
+        match self.symbol_map.get(&ident) {
             Some(symbol) => *symbol,
             None => {
                 let symbol = Symbol(self.symbols.len() as u32);
-                self.symbol_map.insert(ident, symbol);
+                self.symbol_map.insert(ident.clone(), symbol);
                 self.symbols.push(ident);
                 symbol
             }
         }
     }
-    pub fn get_str(&self, symbol: Symbol) -> &'a str {
-        self.symbols[symbol.0 as usize]
+    pub fn find(&self, ident: &str) -> Option<Symbol> {
+        self.symbol_map.get(ident).copied()
+    }
+    pub fn get_str(&self, symbol: Symbol) -> &str {
+        &self.symbols[symbol.0 as usize]
     }
-}
\ No newline at end of file
+}
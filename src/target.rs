@@ -0,0 +1,56 @@
+// A platform to compile for other than the one the compiler runs on. Every target is
+// 64 bit with the same C type sizes, so the layout of types doesn't depend on it.
+#[derive(Debug, Clone, Copy, PartialEq, Hash)]
+pub struct Target {
+    pub name: &'static str,
+    pub os: Os,
+    // the C compiler that assembles and links for it, and the arguments it needs
+    pub cc: &'static [&'static str],
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Hash)]
+pub enum Os {
+    Linux,
+    MacOs,
+}
+
+const TARGETS: &[Target] = &[
+    Target {
+        name: "x86_64-linux",
+        os: Os::Linux,
+        cc: &["x86_64-linux-gnu-gcc"],
+    },
+    Target {
+        name: "aarch64-linux",
+        os: Os::Linux,
+        cc: &["aarch64-linux-gnu-gcc"],
+    },
+    Target {
+        name: "riscv64-linux",
+        os: Os::Linux,
+        cc: &["riscv64-linux-gnu-gcc"],
+    },
+    Target {
+        name: "x86_64-macos",
+        os: Os::MacOs,
+        cc: &["clang", "--target=x86_64-apple-macos11"],
+    },
+    Target {
+        name: "aarch64-macos",
+        os: Os::MacOs,
+        cc: &["clang", "--target=arm64-apple-macos11"],
+    },
+];
+
+pub fn lookup(name: &str) -> Option<Target> {
+    TARGETS.iter().find(|target| target.name == name).copied()
+}
+
+// The OS of the target, or of the host if there isn't one.
+pub fn os(target: Option<Target>) -> Os {
+    match target {
+        Some(target) => target.os,
+        None if cfg!(target_os = "macos") => Os::MacOs,
+        None => Os::Linux,
+    }
+}
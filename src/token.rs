@@ -1,6 +1,8 @@
+#![deny(clippy::unwrap_used, clippy::expect_used, clippy::panic)]
+
 use strum::EnumIter;
 
-use crate::lexer::{Lexer, Position};
+use crate::lexer::{Lexer, Span};
 
 #[derive(Debug, Clone, Copy)]
 pub struct Token<'s> {
@@ -19,23 +21,35 @@ pub struct RawToken {
 pub enum TokenKind {
     Ident,
     Integer,
+    String,
+    Char,
     Keyword(Keyword),
     Symbol(Symbol),
+    // comments are kept out of the tokens the parser sees
+    Comment,
+    Unknown,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, EnumIter)]
 pub enum Keyword {
     Func,
     Struct,
+    Const,
+    StaticAssert,
+    Mod,
+    Use,
+    As,
 
     Var,
     If,
     Else,
     While,
     Return,
+    Unsafe,
 
     True,
     False,
+    Null,
 
     I32,
     I16,
@@ -43,7 +57,15 @@ pub enum Keyword {
     U32,
     U16,
     U8,
+    Usize,
     Bool,
+    Char,
+
+    // reserved for syntax that doesn't exist yet
+    Match,
+    Enum,
+    For,
+    Pub,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, EnumIter)]
@@ -60,11 +82,15 @@ pub enum Symbol {
     OpenAngleBrace,
     CloseAngleBrace,
     Semicolon,
+    DoubleColon,
     Colon,
     Dot,
     Ampersand,
     Comma,
     Equals,
+    Tilde,
+    At,
+    Question,
 }
 
 impl Keyword {
@@ -72,12 +98,19 @@ impl Keyword {
         match self {
             Keyword::Func => "func",
             Keyword::Var => "var",
+            Keyword::Const => "const",
+            Keyword::StaticAssert => "static_assert",
+            Keyword::Mod => "mod",
+            Keyword::Use => "use",
+            Keyword::As => "as",
             Keyword::If => "if",
             Keyword::Else => "else",
             Keyword::While => "while",
+            Keyword::Unsafe => "unsafe",
             Keyword::I32 => "i32",
             Keyword::True => "true",
             Keyword::False => "false",
+            Keyword::Null => "null",
             Keyword::Return => "return",
             Keyword::Struct => "struct",
             Keyword::I16 => "i16",
@@ -85,9 +118,21 @@ impl Keyword {
             Keyword::U32 => "u32",
             Keyword::U16 => "u16",
             Keyword::U8 => "u8",
+            Keyword::Usize => "usize",
             Keyword::Bool => "bool",
+            Keyword::Char => "char",
+            Keyword::Match => "match",
+            Keyword::Enum => "enum",
+            Keyword::For => "for",
+            Keyword::Pub => "pub",
         }
     }
+    pub fn is_reserved(&self) -> bool {
+        matches!(
+            self,
+            Keyword::Match | Keyword::Enum | Keyword::For | Keyword::Pub
+        )
+    }
 }
 
 impl Symbol {
@@ -102,6 +147,7 @@ impl Symbol {
             Symbol::OpenCurlyBrace => "{",
             Symbol::CloseCurlyBrace => "}",
             Symbol::Semicolon => ";",
+            Symbol::DoubleColon => "::",
             Symbol::Colon => ":",
             Symbol::Arrow => "->",
             Symbol::Dot => ".",
@@ -110,25 +156,19 @@ impl Symbol {
             Symbol::OpenAngleBrace => "<",
             Symbol::CloseAngleBrace => ">",
             Symbol::Equals => "=",
+            Symbol::Tilde => "~",
+            Symbol::At => "@",
+            Symbol::Question => "?",
         }
     }
 }
 
 impl<'s> Token<'s> {
-    pub fn pos(&self) -> Position {
-        let mut line = 1;
-        let mut column = 1;
-        for (offset, ch) in self.source.char_indices() {
-            if offset == self.offset {
-                return Position { line, column };
-            }
-            if ch == '\n' {
-                column = 0;
-                line += 1;
-            }
-            column += 1;
+    pub fn span(&self) -> Span {
+        Span {
+            start: self.offset,
+            end: self.offset + self.len(),
         }
-        unreachable!()
     }
     pub fn len(&self) -> usize {
         let mut lexer = Lexer {
@@ -1,13 +1,20 @@
-use crate::token::{RawToken, Token};
+#![deny(clippy::unwrap_used, clippy::expect_used, clippy::panic)]
+
+use crate::{
+    lexer::Span,
+    token::{RawToken, Token},
+};
 
 pub struct Tokens<'s> {
     pub raw_tokens: Vec<RawToken>,
+    // the span of every comment, in order
+    pub comments: Vec<Span>,
     pub source: &'s str,
 }
 
 impl<'s> Tokens<'s> {
-    pub fn iter(&self) -> TokenIter<'_, 's> {
-        TokenIter {
+    pub fn cursor(&self) -> TokenCursor<'_, 's> {
+        TokenCursor {
             raw_tokens: &self.raw_tokens,
             source: self.source,
             index: 0,
@@ -15,26 +22,43 @@ impl<'s> Tokens<'s> {
     }
 }
 
-pub struct TokenIter<'t, 's> {
-    raw_tokens: &'t Vec<RawToken>,
+#[derive(Debug, Clone, Copy)]
+pub struct Checkpoint(usize);
+
+pub struct TokenCursor<'t, 's> {
+    raw_tokens: &'t [RawToken],
     source: &'s str,
     index: usize,
 }
 
-impl<'t, 's> Iterator for TokenIter<'t, 's> {
-    type Item = Token<'s>;
-
-    fn next(&mut self) -> Option<Self::Item> {
+impl<'t, 's> TokenCursor<'t, 's> {
+    fn get(&self, index: usize) -> Option<Token<'s>> {
+        self.raw_tokens.get(index).map(|raw_token| Token {
+            kind: raw_token.kind,
+            offset: raw_token.offset,
+            source: self.source,
+        })
+    }
+    // The token `n` places after the current one, so `lookahead(0)` is the current token.
+    pub fn lookahead(&self, n: usize) -> Option<Token<'s>> {
+        self.get(self.index + n)
+    }
+    pub fn advance(&mut self) {
         if self.index < self.raw_tokens.len() {
-            let raw_token = self.raw_tokens[self.index];
             self.index += 1;
-            Some(Token {
-                kind: raw_token.kind,
-                offset: raw_token.offset,
-                source: self.source,
-            })
-        } else {
-            None
         }
     }
+    // The end of the token before the current one, where a missing token would go.
+    pub fn prev_end(&self) -> usize {
+        self.index
+            .checked_sub(1)
+            .and_then(|index| self.get(index))
+            .map_or(0, |token| token.span().end)
+    }
+    pub fn checkpoint(&self) -> Checkpoint {
+        Checkpoint(self.index)
+    }
+    pub fn rollback(&mut self, checkpoint: Checkpoint) {
+        self.index = checkpoint.0;
+    }
 }
@@ -1,6 +1,13 @@
-use std::{collections::HashMap, fmt};
+#![deny(clippy::unwrap_used, clippy::expect_used, clippy::panic)]
 
-use crate::{infer::{InferTyRef, Unify, unify}, symbols::Symbol};
+use std::{collections::BTreeMap, fmt};
+
+use crate::{
+    compile_typed_ast::concrete_int,
+    infer::{InferTyRef, Unify, unify},
+    lexer::Span,
+    symbols::{Symbol, Symbols},
+};
 
 pub type TyRef = InferTyRef<Ty>;
 pub type IntTyRef = InferTyRef<IntTy>;
@@ -10,9 +17,12 @@ pub type StructTyRef = InferTyRef<StructTy>;
 pub enum Ty {
     Bool,
     Ref(TyRef),
+    NullableRef(TyRef),
     Int(IntTyRef),
     Struct(StructTyRef),
     Any,
+    // the type of something that has already been reported as an error
+    Error,
 }
 
 #[derive(Debug, Clone)]
@@ -34,7 +44,9 @@ pub enum Signedness {
 
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Size {
-    B8, B16, B32
+    B8, B16, B32,
+    // the width of a reference on the target
+    Ptr,
 }
 
 #[derive(Debug, Clone)]
@@ -43,7 +55,17 @@ pub enum StructTy {
         name: Symbol,
         fields: Vec<Field>,
     },
-    WithFields(HashMap<Symbol, TyRef>),
+    WithFields(BTreeMap<Symbol, (TyRef, Span)>),
+}
+
+// A type after inference, with unknown integer types defaulted to `i32`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConcreteTy {
+    Bool,
+    Ref(Box<ConcreteTy>),
+    NullableRef(Box<ConcreteTy>),
+    Int(Int),
+    Struct(Symbol),
 }
 
 #[derive(Debug, Clone)]
@@ -52,58 +74,77 @@ pub struct Field {
     pub ty: TyRef,
 }
 
+#[derive(Debug, Clone)]
+pub enum TyError {
+    Mismatch,
+    MissingField {
+        struct_name: Symbol,
+        field: Symbol,
+        span: Span,
+        fields: Vec<Symbol>,
+    },
+}
+
 impl Unify for Ty {
-    fn unify(a: Ty, b: Ty) -> Result<Ty, ()> {
+    fn unify(a: Ty, b: Ty) -> Result<Ty, TyError> {
         match (a, b) {
+            // anything matches an error, so it's only reported once
+            (Ty::Error, _) | (_, Ty::Error) => Ok(Ty::Error),
             (Ty::Any, Ty::Any) => Ok(Ty::Any),
             (Ty::Any, ty) | (ty,  Ty::Any) => Ok(ty),
             (Ty::Bool, Ty::Bool) => Ok(Ty::Bool),
             (Ty::Ref(a), Ty::Ref(b)) => Ok(Ty::Ref(unify(&a, &b)?)),
+            (Ty::NullableRef(a), Ty::NullableRef(b)) => Ok(Ty::NullableRef(unify(&a, &b)?)),
             (Ty::Int(a), Ty::Int(b)) => Ok(Ty::Int(unify(&a, &b)?)),
             (Ty::Struct(a), Ty::Struct(b)) => Ok(Ty::Struct(unify(&a, &b)?)),
-            (a, b) => {
-                println!("{:?}\n{:?}", a, b);
-                Err(())?
-            }
+            _ => Err(TyError::Mismatch),
         }
     }
 }
 
 impl Unify for IntTy {
-    fn unify(a: Self, b: Self) -> Result<Self, ()> {
+    fn unify(a: Self, b: Self) -> Result<Self, TyError> {
         Ok(match (a, b) {
             (IntTy::Any, IntTy::Any) => IntTy::Any,
             (ty, IntTy::Any) | (IntTy::Any, ty) => ty,
             (IntTy::Int(a), IntTy::Int(b)) => if a == b {
                 IntTy::Int(a)
             } else {
-                Err(())?
+                Err(TyError::Mismatch)?
             } 
         })
     }
 }
 
 impl Unify for StructTy {
-    fn unify(a: Self, b: Self) -> Result<Self, ()> {
+    fn unify(a: Self, b: Self) -> Result<Self, TyError> {
         Ok(match (a, b) {
             (StructTy::Known { name: a, fields }, StructTy::Known { name: b, .. }) => if a == b {
                 StructTy::Known { name: a, fields }
             } else {
-                Err(())?
+                Err(TyError::Mismatch)?
             }
             (StructTy::Known { name, fields }, StructTy::WithFields(required_fields)) |
             (StructTy::WithFields(required_fields), StructTy::Known { name, fields }) => {
-                for (name, ty) in required_fields {
-                    let field = fields.iter().find(|field| field.name == name).unwrap();
+                for (field_name, (ty, span)) in required_fields {
+                    let field = fields
+                        .iter()
+                        .find(|field| field.name == field_name)
+                        .ok_or_else(|| TyError::MissingField {
+                            struct_name: name,
+                            field: field_name,
+                            span,
+                            fields: fields.iter().map(|field| field.name).collect(),
+                        })?;
                     unify(&field.ty, &ty)?;
                 }
                 StructTy::Known { name, fields }
             }
             (StructTy::WithFields(mut a), StructTy::WithFields(b)) => {
-                for (name, b_ty) in b {
+                for (name, (b_ty, span)) in b {
                     match a.get(&name) {
-                        Some(a_ty) => _ = unify(a_ty, &b_ty)?,
-                        None => _ = a.insert(name, b_ty),
+                        Some((a_ty, _)) => _ = unify(a_ty, &b_ty)?,
+                        None => _ = a.insert(name, (b_ty, span)),
                     }
                 }
                 StructTy::WithFields(a)
@@ -112,6 +153,32 @@ impl Unify for StructTy {
     }
 }
 
+pub fn concrete(ty: &TyRef) -> Option<ConcreteTy> {
+    ty.map(|ty| match ty {
+        Ty::Bool => Some(ConcreteTy::Bool),
+        Ty::Ref(ty) => Some(ConcreteTy::Ref(Box::new(concrete(ty)?))),
+        Ty::NullableRef(ty) => Some(ConcreteTy::NullableRef(Box::new(concrete(ty)?))),
+        Ty::Int(int) => Some(ConcreteTy::Int(concrete_int(int))),
+        Ty::Struct(s) => s.map(|s| match s {
+            StructTy::Known { name, .. } => Some(ConcreteTy::Struct(*name)),
+            StructTy::WithFields(_) => None,
+        }),
+        Ty::Any | Ty::Error => None,
+    })
+}
+
+impl ConcreteTy {
+    pub fn name(&self, symbols: &Symbols) -> String {
+        match self {
+            ConcreteTy::Bool => "bool".to_string(),
+            ConcreteTy::Ref(ty) => format!("*{}", ty.name(symbols)),
+            ConcreteTy::NullableRef(ty) => format!("?*{}", ty.name(symbols)),
+            ConcreteTy::Int(int) => IntTy::Int(*int).to_string(),
+            ConcreteTy::Struct(name) => symbols.get_str(*name).to_string(),
+        }
+    }
+}
+
 impl fmt::Display for IntTy {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
@@ -124,6 +191,7 @@ impl fmt::Display for IntTy {
                     Size::B8 => "8",
                     Size::B16 => "16",
                     Size::B32 => "32",
+                    Size::Ptr => "size",
                 };
                 write!(f, "{}{}", signedness, size)
             }
@@ -137,9 +205,11 @@ impl fmt::Display for Ty {
         match self {
             Ty::Bool => write!(f, "bool"),
             Ty::Ref(ty) => write!(f, "&{}", ty),
+            Ty::NullableRef(ty) => write!(f, "?&{}", ty),
             Ty::Int(int_ty) => write!(f, "{}", int_ty),
             Ty::Struct(s) => write!(f, "{}", s),
             Ty::Any => write!(f, "any?"),
+            Ty::Error => write!(f, "{{error}}"),
         }
     }
 }
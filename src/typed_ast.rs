@@ -1,10 +1,16 @@
-use crate::{ty::{TyRef, IntTyRef, StructTyRef}, symbols::Symbol};
+#![deny(clippy::unwrap_used, clippy::expect_used, clippy::panic)]
+
+use crate::{ty::{TyRef, IntTyRef, StructTyRef}, symbols::Symbol, lexer::Span};
 
 #[derive(Debug, Clone)]
 pub struct Func {
     pub name: Symbol,
     pub params: Vec<TyRef>,
     pub blocks: Vec<Block>,
+    // the type of each expression and variable declaration with a span
+    pub types: Vec<(Span, TyRef)>,
+    // what each operation in an `unsafe` block does, for `--emit=unsafe-report`
+    pub unsafe_ops: Vec<(Span, String)>,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -14,6 +20,8 @@ pub struct Variable(pub u32);
 pub struct Block {
     pub stmts: Vec<Stmt>,
     pub branch: Branch,
+    pub name: &'static str,
+    pub span: Span,
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -32,19 +40,27 @@ pub enum Branch {
 
 #[derive(Debug, Clone)]
 pub enum Stmt {
-    Alloc(TyRef),
+    Alloc(Variable, TyRef),
     Assign {
         ref_expr: RefExpr,
         expr: Expr,
         ty: TyRef,
+        span: Span,
     },
     FuncCall(FuncCall),
+    Discard(Expr),
+    // the span is of the condition, for the message if it fails
+    Assert {
+        expr: Expr,
+        span: Span,
+    },
 }
 
 #[derive(Debug, Clone)]
 pub enum RefExpr {
     Variable(Variable),
-    Deref(Box<Expr>),
+    // the span is of the statement, for the message if a memory check fails
+    Deref(Box<Expr>, Span),
     Field {
         ref_expr: Box<RefExpr>,
         name: Symbol,
@@ -60,16 +76,50 @@ pub enum Expr {
         right: Box<Expr>,
         ty: IntTyRef,
         op: BinaryOp,
+        span: Span,
+    },
+    BitNot {
+        expr: Box<Expr>,
+        ty: IntTyRef,
+    },
+    Intrinsic {
+        intrinsic: Intrinsic,
+        arg: Box<Expr>,
+        ty: IntTyRef,
+    },
+    // the first argument is the reference, and the span is of the statement for the
+    // message if a memory check fails
+    Atomic {
+        op: AtomicOp,
+        args: Vec<Expr>,
+        ty: IntTyRef,
+        span: Span,
+    },
+    PtrOffset {
+        ptr: Box<Expr>,
+        offset: Box<Expr>,
+        ty: TyRef,
+        offset_ty: IntTyRef,
+        op: BinaryOp,
     },
     Bool(bool),
+    Null,
+    // the span is of the statement, for the message if the reference is null
+    Unwrap {
+        expr: Box<Expr>,
+        span: Span,
+    },
+    IsNull(Box<Expr>),
     Load {
         var: Variable,
         ty: TyRef,
+        span: Span,
     },
     Ref(RefExpr),
     Deref {
         expr: Box<Expr>,
         ty: TyRef,
+        span: Span,
     },
     FuncCall(FuncCall),
     InitStruct(Vec<StructValue>),
@@ -82,6 +132,7 @@ pub enum Expr {
 
 #[derive(Debug, Clone)]
 pub struct StructValue {
+    pub name: Symbol,
     pub expr: Expr,
     pub ty: TyRef,
 }
@@ -92,6 +143,22 @@ pub struct FuncCall {
     pub args: Vec<Expr>,
 }
 
+#[derive(Debug, Clone, Copy)]
+pub enum Intrinsic {
+    Ctz,
+    Clz,
+    Popcount,
+    Bswap,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum AtomicOp {
+    Load,
+    Store,
+    Add,
+    CompareExchange,
+}
+
 #[derive(Debug, Clone, Copy)]
 pub enum BinaryOp {
     Add,
@@ -0,0 +1,225 @@
+#![deny(clippy::unwrap_used, clippy::expect_used, clippy::panic)]
+
+use crate::{
+    codes::Code,
+    diagnostic::Diagnostic,
+    lexer::Span,
+    symbols::{Symbol, Symbols},
+    ty::{StructTy, StructTyRef},
+    typed_ast::{Block, Branch, Expr, Func, RefExpr, Stmt, Variable},
+};
+
+// The parts of a variable that have been assigned, as paths of field names, where the
+// empty path is the whole variable.
+#[derive(Debug, Clone, Default, PartialEq)]
+struct Init(Vec<Vec<Symbol>>);
+
+impl Init {
+    fn whole() -> Init {
+        Init(vec![vec![]])
+    }
+    fn covers(&self, path: &[Symbol]) -> bool {
+        self.0.iter().any(|init| path.starts_with(init))
+    }
+    fn insert(&mut self, path: Vec<Symbol>) {
+        if !self.covers(&path) {
+            self.0.retain(|init| !init.starts_with(&path));
+            self.0.push(path);
+        }
+    }
+    // The parts assigned in both.
+    fn meet(&self, other: &Init) -> Init {
+        let mut init = Init::default();
+        let both = self.0.iter().filter(|path| other.covers(path));
+        for path in both.chain(other.0.iter().filter(|path| self.covers(path))) {
+            init.insert(path.clone());
+        }
+        init
+    }
+}
+
+struct Checker {
+    init: Vec<Init>,
+    uses: Vec<(Variable, Span)>,
+}
+
+// A variable is only initialized at the start of a block if it has been
+// assigned on every path leading to that block. Fields are tracked separately, so a
+// struct is initialized once each of its fields has been.
+pub fn check_func(func: &Func, vars: &[(Symbol, Span)], symbols: &Symbols) -> Vec<Diagnostic> {
+    let mut entry = vec![Init::default(); vars.len()];
+    entry[..func.params.len()].fill(Init::whole());
+    let mut states: Vec<Option<Vec<Init>>> = vec![None; func.blocks.len()];
+    states[0] = Some(entry);
+    let mut work = vec![0];
+    while let Some(id) = work.pop() {
+        // blocks are only queued once they have a state
+        let init = match states[id].clone() {
+            Some(init) => check_block(&func.blocks[id], init).init,
+            None => continue,
+        };
+        for succ in successors(&func.blocks[id].branch) {
+            let changed = match &mut states[succ] {
+                Some(succ_init) => {
+                    let mut changed = false;
+                    for (succ_init, init) in succ_init.iter_mut().zip(&init) {
+                        let meet = succ_init.meet(init);
+                        if meet != *succ_init {
+                            *succ_init = meet;
+                            changed = true;
+                        }
+                    }
+                    changed
+                }
+                None => {
+                    states[succ] = Some(init.clone());
+                    true
+                }
+            };
+            if changed {
+                work.push(succ);
+            }
+        }
+    }
+    let mut errors = vec![];
+    for (block, init) in func.blocks.iter().zip(states) {
+        let init = match init {
+            Some(init) => init,
+            None => continue,
+        };
+        for (var, span) in check_block(block, init).uses {
+            let (name, decl_span) = vars[var.0 as usize];
+            let name = symbols.get_str(name);
+            errors.push(
+                Diagnostic::new(
+                    Code::Uninitialized,
+                    span,
+                    format!("variable `{}` may be uninitialized here", name),
+                )
+                .with_span_note(decl_span, format!("`{}` is declared here", name)),
+            );
+        }
+    }
+    errors
+}
+
+fn check_block(block: &Block, init: Vec<Init>) -> Checker {
+    let mut checker = Checker { init, uses: vec![] };
+    for stmt in &block.stmts {
+        match stmt {
+            Stmt::Alloc(..) => {}
+            Stmt::Assign { ref_expr, expr, .. } => {
+                checker.expr(expr);
+                checker.assign(ref_expr);
+            }
+            Stmt::FuncCall(func_call) => func_call.args.iter().for_each(|arg| checker.expr(arg)),
+            Stmt::Discard(expr) | Stmt::Assert { expr, .. } => checker.expr(expr),
+        }
+    }
+    match &block.branch {
+        Branch::Return(expr) => expr.iter().for_each(|expr| checker.expr(expr)),
+        Branch::Static(_) => {}
+        Branch::Condition { expr, .. } => checker.expr(expr),
+    }
+    checker
+}
+
+fn successors(branch: &Branch) -> Vec<usize> {
+    match branch {
+        Branch::Return(_) => vec![],
+        Branch::Static(target) => vec![target.0 as usize],
+        Branch::Condition {
+            if_true, if_false, ..
+        } => vec![if_true.0 as usize, if_false.0 as usize],
+    }
+}
+
+impl Checker {
+    fn expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Int(_) | Expr::Bool(_) | Expr::Null => {}
+            Expr::Load { .. } | Expr::Field { .. } => self.load(expr, vec![]),
+            Expr::Binary { left, right, .. } => {
+                self.expr(left);
+                self.expr(right);
+            }
+            Expr::BitNot { expr, .. } | Expr::Intrinsic { arg: expr, .. } => self.expr(expr),
+            Expr::PtrOffset { ptr, offset, .. } => {
+                self.expr(ptr);
+                self.expr(offset);
+            }
+            // taking the address of a variable may be used to initialize it
+            Expr::Ref(ref_expr) => self.assign(ref_expr),
+            Expr::Deref { expr, .. } | Expr::Unwrap { expr, .. } | Expr::IsNull(expr) => {
+                self.expr(expr)
+            }
+            Expr::FuncCall(func_call) => func_call.args.iter().for_each(|arg| self.expr(arg)),
+            Expr::Atomic { args, .. } => args.iter().for_each(|arg| self.expr(arg)),
+            Expr::InitStruct(values) => values.iter().for_each(|value| self.expr(&value.expr)),
+        }
+    }
+    // Reads the fields along `path` from a value, which only needs that part of a
+    // variable to be initialized.
+    fn load(&mut self, expr: &Expr, mut path: Vec<Symbol>) {
+        match expr {
+            Expr::Load { var, span, .. } => {
+                path.reverse();
+                if !self.init[var.0 as usize].covers(&path) {
+                    self.uses.push((*var, *span));
+                }
+            }
+            Expr::Field { expr, name, .. } => {
+                path.push(*name);
+                self.load(expr, path);
+            }
+            _ => self.expr(expr),
+        }
+    }
+    // Assigning the last uninitialized field of a struct initializes the struct.
+    fn assign(&mut self, ref_expr: &RefExpr) {
+        let Some((var, fields)) = self.assign_path(ref_expr) else {
+            return;
+        };
+        let init = &mut self.init[var.0 as usize];
+        let mut path: Vec<_> = fields.iter().map(|(name, _)| *name).collect();
+        init.insert(path.clone());
+        for (_, ty) in fields.iter().rev() {
+            path.pop();
+            // a struct's fields are only unknown after a type error
+            let names = ty.map(|ty| match ty {
+                StructTy::Known { fields, .. } => {
+                    Some(fields.iter().map(|field| field.name).collect::<Vec<_>>())
+                }
+                StructTy::WithFields(_) => None,
+            });
+            let complete = names.is_some_and(|names| {
+                names
+                    .into_iter()
+                    .all(|name| init.covers(&[path.as_slice(), &[name]].concat()))
+            });
+            if !complete {
+                break;
+            }
+            init.insert(path.clone());
+        }
+    }
+    // The variable an assignment writes to, if any, with the fields along the way and
+    // the structs they're in.
+    fn assign_path(
+        &mut self,
+        ref_expr: &RefExpr,
+    ) -> Option<(Variable, Vec<(Symbol, StructTyRef)>)> {
+        match ref_expr {
+            RefExpr::Variable(var) => Some((*var, vec![])),
+            RefExpr::Deref(expr, _) => {
+                self.expr(expr);
+                None
+            }
+            RefExpr::Field { ref_expr, name, ty } => {
+                let (var, mut fields) = self.assign_path(ref_expr)?;
+                fields.push((*name, ty.clone()));
+                Some((var, fields))
+            }
+        }
+    }
+}